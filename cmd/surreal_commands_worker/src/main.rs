@@ -8,7 +8,7 @@ use tokio::process::Command;
 use tokio::time::sleep;
 use tracing::{error, info, warn};
 
-use surrealdb::engine::remote::ws::{Client, Ws};
+use surrealdb::engine::remote::ws::{Client, Ws, Wss};
 use surrealdb::opt::auth::Root;
 use surrealdb::value::{from_value, Value as SurValue};
 use surrealdb::{Action, RecordId, Surreal};
@@ -25,6 +25,27 @@ struct Opts {
     #[arg(long, env = "COMMANDS_TABLE", default_value = "fs_commands")] table: String,
     #[arg(long, env = "FS_CLI", default_value = "fs_cli")] fs_cli: String,
     #[arg(long, env = "POLL_MS", default_value_t = 500u64)] poll_ms: u64,
+    /// Recorded in `claimed_by`; defaults to the host name.
+    #[arg(long, env = "NODE_ID")] node_id: Option<String>,
+    /// How long a claim is held, written to `lease_until`.
+    #[arg(long, env = "CLAIM_LEASE_MS", default_value_t = 60_000u64)] lease_ms: u64,
+    /// How often expired claims are recovered.
+    #[arg(long, env = "REAP_MS", default_value_t = 10_000u64)] reap_ms: u64,
+    /// Claims allowed before an expired command is marked failed.
+    #[arg(long, env = "MAX_ATTEMPTS", default_value_t = 3u32)] max_attempts: u32,
+}
+
+impl Opts {
+    /// Resolved once at startup: the host name is read from the system.
+    fn node(&self) -> String {
+        self.node_id.clone().filter(|n| !n.trim().is_empty()).unwrap_or_else(|| {
+            std::fs::read_to_string("/proc/sys/kernel/hostname").ok()
+                .or_else(|| std::env::var("HOSTNAME").ok())
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| format!("pid-{}", std::process::id()))
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,21 +63,21 @@ struct CmdRow {
     status: Option<String>,
 }
 
-fn normalize_ws_hostport(url: &str) -> String {
+/// Returns (use TLS, host:port); wss:// and https:// select TLS.
+fn split_ws_hostport(url: &str) -> (bool, String) {
     let u = url.trim();
-    for p in ["ws://", "wss://", "http://", "https://"] {
+    for (p, tls) in [("wss://", true), ("https://", true), ("ws://", false), ("http://", false)] {
         if let Some(stripped) = u.strip_prefix(p) {
-            return stripped.to_string();
+            return (tls, stripped.to_string());
         }
     }
-    u.to_string()
+    (false, u.to_string())
 }
 
 async fn connect(opts: &Opts) -> Result<Surreal<Client>> {
-    let hostport = normalize_ws_hostport(&opts.url);
-    let db = Surreal::new::<Ws>(&hostport)
-        .await
-        .with_context(|| format!("ws connect failed (to {})", hostport))?;
+    let (tls, hostport) = split_ws_hostport(&opts.url);
+    let db = if tls { Surreal::new::<Wss>(&hostport).await } else { Surreal::new::<Ws>(&hostport).await }
+        .with_context(|| format!("ws connect failed (to {})", opts.url))?;
     if let Some(token) = &opts.token {
         db.authenticate(token).await.context("token auth failed")?;
     } else {
@@ -96,28 +117,71 @@ async fn fs_exec(fs_cli: &str, cmd: &str, args: Option<&str>) -> Result<String>
     }
 }
 
-async fn claim(db: &Surreal<Client>, table: &str, key: &str) -> Result<()> {
-    let sql = format!("UPDATE {}:{} SET status = 'processing', claimed_at = time::now()", table, key);
-    db.query(sql).await.context("claim update failed")?;
-    Ok(())
+#[derive(Debug, Deserialize)]
+struct Claimed {
+    status: Option<String>,
+    claimed_by: Option<String>,
 }
 
-async fn ack(db: &Surreal<Client>, table: &str, key: &str, ok: bool, result: &str) -> Result<()> {
+/// Compare-and-set claim: only succeeds if the row is still `new`. Returns false when
+/// another worker or switch got there first.
+async fn claim(db: &Surreal<Client>, opts: &Opts, node: &str, id: &RecordId) -> Result<bool> {
+    let rows: Vec<Claimed> = db
+        .query("UPDATE $rid SET status = 'processing', claimed_at = time::now(), claimed_by = $node, \
+                lease_until = time::now() + duration::from::millis($lease_ms) WHERE status = 'new' RETURN AFTER")
+        .bind(("rid", id.clone()))
+        .bind(("node", node.to_string()))
+        .bind(("lease_ms", opts.lease_ms as i64))
+        .await
+        .context("claim update failed")?
+        .take(0)
+        .context("claim result decode failed")?;
+    Ok(rows.iter().any(|r| r.status.as_deref() == Some("processing") && r.claimed_by.as_deref() == Some(node)))
+}
+
+/// Extends the lease every third of `lease_ms` while the command runs, so a slow command
+/// is not reaped and run again by another node. Aborted once the command finishes.
+async fn renew_lease(db: Surreal<Client>, id: RecordId, node: String, lease_ms: u64) {
+    let every = Duration::from_millis((lease_ms / 3).max(100));
+    loop {
+        sleep(every).await;
+        let res = db
+            .query("UPDATE $rid SET lease_until = time::now() + duration::from::millis($lease_ms) \
+                    WHERE status = 'processing' AND claimed_by = $node")
+            .bind(("rid", id.clone()))
+            .bind(("node", node.clone()))
+            .bind(("lease_ms", lease_ms as i64))
+            .await;
+        if let Err(e) = res { warn!(id = %id, "lease renewal failed: {}", e); }
+    }
+}
+
+/// Records the outcome, but only while this node still holds the claim.
+async fn ack(db: &Surreal<Client>, node: &str, id: &RecordId, ok: bool, result: &str) -> Result<()> {
     let safe = result.replace('\n', " ").replace('\r', " ");
     let patch = json!({
         "status": if ok { "done" } else { "failed" },
         "processed_at": chrono::Utc::now().timestamp(),
         "result": safe,
     });
-    let sql = format!("UPDATE {}:{} MERGE {}", table, key, patch);
-    db.query(sql).await.context("ack update failed")?;
+    db.query("UPDATE $rid MERGE $patch WHERE claimed_by = $node")
+        .bind(("rid", id.clone()))
+        .bind(("patch", patch))
+        .bind(("node", node.to_string()))
+        .await
+        .context("ack update failed")?;
     Ok(())
 }
 
-async fn handle_row(opts: &Opts, db: &Surreal<Client>, row: CmdRow) -> Result<()> {
-    let tb = row.id.table().to_string();
-    let key: String = row.id.key().clone().try_into().map_err(|_| anyhow!("id key not string-like"))?;
-    claim(db, &tb, &key).await.ok();
+async fn handle_row(opts: &Opts, node: &str, db: &Surreal<Client>, row: CmdRow) -> Result<()> {
+    let id = row.id.clone();
+    // Never execute without holding the claim
+    match claim(db, opts, node, &id).await {
+        Ok(true) => {}
+        Ok(false) => { info!(id = %id, "already claimed by another node; skipping"); return Ok(()); }
+        Err(e) => { warn!(id = %id, "claim failed: {}", e); return Err(e); }
+    }
+    let renewal = tokio::spawn(renew_lease(db.clone(), id.clone(), node.to_string(), opts.lease_ms));
 
     let action = row.action.unwrap_or_default().to_ascii_lowercase();
     let mut ok = false;
@@ -161,45 +225,108 @@ async fn handle_row(opts: &Opts, db: &Surreal<Client>, row: CmdRow) -> Result<()
         _ => res = format!("unknown action: {}", action),
     }
 
-    ack(db, &tb, &key, ok, &res).await.ok();
+    renewal.abort();
+    ack(db, node, &id, ok, &res).await.ok();
     if ok {
         info!(url = %opts.url, ns = %opts.ns, db = %opts.db, table = %opts.table, "done: {}", res);
     } else {
-        warn!(table = %tb, key = %key, "failed: {}", res);
+        warn!(id = %id, "failed: {}", res);
     }
     Ok(())
 }
 
-async fn live_loop(opts: &Opts, db: &Surreal<Client>) -> Result<()> {
+/// Returns commands whose lease expired (their claimant died or hung) to `new`, or marks
+/// them `failed` once `attempts` reaches the limit.
+async fn reap_loop(opts: Opts, db: Surreal<Client>) {
+    loop {
+        let res = db
+            .query("UPDATE type::table($tb) SET status = IF (attempts ?? 0) + 1 >= $max THEN 'failed' ELSE 'new' END, \
+                    attempts = (attempts ?? 0) + 1, claimed_by = NONE, lease_until = NONE \
+                    WHERE status = 'processing' AND lease_until != NONE AND lease_until < time::now() RETURN VALUE id")
+            .bind(("tb", opts.table.clone()))
+            .bind(("max", opts.max_attempts as i64))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|mut resp| resp.take::<Vec<RecordId>>(0).map_err(|e| e.to_string()));
+        match res {
+            Ok(rows) if !rows.is_empty() => warn!(count = rows.len(), table = %opts.table, "recovered expired claims"),
+            Ok(_) => {}
+            Err(e) => warn!("claim recovery failed: {}", e),
+        }
+        sleep(Duration::from_millis(opts.reap_ms)).await;
+    }
+}
+
+async fn live_loop(opts: &Opts, node: &str, db: &Surreal<Client>) -> Result<()> {
     info!(table = %opts.table, "starting LIVE feed");
     let mut stream = db
         .select::<Vec<CmdRow>>(&opts.table)
         .live()
         .await
         .context("live start failed")?;
+    // Rows created before the stream opened are never notified: claim them first, oldest
+    // first. Atomic claiming keeps rows that also arrive on the stream from running twice.
+    catch_up(opts, node, db).await;
     while let Some(item) = stream.next().await {
         let notif: surrealdb::Notification<CmdRow> = match item { Ok(n) => n, Err(e) => { warn!("live notif error: {}", e); continue; } };
         if notif.action != Action::Create && notif.action != Action::Update { continue; }
         let row: CmdRow = notif.data;
         if !matches!(row.status.as_deref(), Some(s) if s.eq_ignore_ascii_case("new")) { continue; }
-        handle_row(opts, db, row).await.ok();
+        handle_row(opts, node, db, row).await.ok();
     }
     Err(anyhow!("live stream ended"))
 }
 
-async fn poll_loop(opts: &Opts, db: &Surreal<Client>) -> Result<()> {
+/// Rows per catch-up page, as in the poll loop.
+const CATCH_UP_PAGE: usize = 50;
+/// Pages read per catch-up; anything left is picked up by the LIVE feed or the next restart.
+const CATCH_UP_MAX_PAGES: usize = 100;
+
+/// Claims the `new` backlog in pages of `CATCH_UP_PAGE`, ordered by `created_at` then id. Each
+/// page starts after the last row of the previous one, so rows whose claim failed are not
+/// fetched again.
+async fn catch_up(opts: &Opts, node: &str, db: &Surreal<Client>) {
+    let sql = "LET $ts = IF $after != NONE THEN (SELECT VALUE created_at FROM ONLY $after) END;
+        SELECT * FROM type::table($tb) WHERE status = 'new'
+            AND ($after = NONE OR created_at > $ts OR (created_at = $ts AND id > $after))
+            ORDER BY created_at, id LIMIT $n";
+    let mut after: Option<RecordId> = None;
+    let mut total = 0;
+    for _ in 0..CATCH_UP_MAX_PAGES {
+        let list = match db
+            .query(sql)
+            .bind(("tb", opts.table.clone()))
+            .bind(("n", CATCH_UP_PAGE as i64))
+            .bind(("after", after.clone()))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|mut resp| resp.take::<Vec<CmdRow>>(1).map_err(|e| e.to_string()))
+        {
+            Ok(list) => list,
+            Err(e) => { warn!("backlog query failed: {}", e); break; }
+        };
+        let n = list.len();
+        for row in list {
+            after = Some(row.id.clone());
+            handle_row(opts, node, db, row).await.ok();
+        }
+        total += n;
+        if n < CATCH_UP_PAGE { break; }
+    }
+    if total > 0 { info!(count = total, table = %opts.table, "caught up on backlog"); }
+}
+
+async fn poll_loop(opts: &Opts, node: &str, db: &Surreal<Client>) -> Result<()> {
     info!(table = %opts.table, every_ms = %opts.poll_ms, "starting POLL loop");
     loop {
-        let sql = format!(
-            "SELECT *, type::string(id) AS id FROM {} WHERE status = 'new' LIMIT 50",
-            opts.table
-        );
-        match db.query(sql).await {
+        // Keep ids typed so non-string keys deserialize into RecordId
+        let sql = "SELECT * FROM type::table($tb) WHERE status = 'new' LIMIT 50";
+        match db.query(sql).bind(("tb", opts.table.clone())).await {
             Ok(mut resp) => match resp.take::<Vec<SurValue>>(0) {
                 Ok(list) => {
                     if !list.is_empty() { info!(count=list.len(), table = %opts.table, "fetched new rows"); }
                     for v in list {
-                        if let Ok(row) = from_value::<CmdRow>(v) { handle_row(opts, db, row).await.ok(); }
+                        if let Ok(row) = from_value::<CmdRow>(v) { handle_row(opts, node, db, row).await.ok(); }
                     }
                 }
                 Err(e) => warn!("decode failed: {}", e),
@@ -217,8 +344,8 @@ async fn main() -> Result<()> {
     tracing_subscriber::fmt().with_env_filter(filter).init();
 
     let opts = Opts::parse();
-    println!("surreal_commands_worker starting: url={} ns={} db={} table={}", opts.url, opts.ns, opts.db, opts.table);
-    info!(url = %opts.url, ns = %opts.ns, db = %opts.db, table = %opts.table, "connecting");
+    let node = opts.node();
+    info!(url = %opts.url, ns = %opts.ns, db = %opts.db, table = %opts.table, node = %node, "connecting");
     let db = loop {
         match connect(&opts).await {
             Ok(db) => break db,
@@ -226,10 +353,12 @@ async fn main() -> Result<()> {
         }
     };
 
+    tokio::spawn(reap_loop(opts.clone(), db.clone()));
+
     // Try LIVE, fallback to POLL
-    match live_loop(&opts, &db).await {
+    match live_loop(&opts, &node, &db).await {
         Ok(()) => {}
-        Err(e) => { warn!("live failed: {} — falling back to poll", e); poll_loop(&opts, &db).await?; }
+        Err(e) => { warn!("live failed: {} — falling back to poll", e); poll_loop(&opts, &node, &db).await?; }
     }
 
     Ok(())
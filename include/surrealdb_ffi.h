@@ -4,14 +4,70 @@
 #include <stdint.h>
 
 // Forward-declared opaque handle to a SurrealDB connection context.
+// A handle is thread-safe: every function taking a SurHandle* may be called
+// concurrently from any thread (event threads, API commands, subscription
+// callbacks). Reconnects are serialized internally. The only exception is
+// surreal_close(), which must not race with other calls on the same handle.
 typedef struct SurHandle SurHandle;
 typedef void (*surreal_command_cb)(const char* topic, const char* json, void* user_data);
 typedef void (*surreal_log_cb)(const char* msg, void* user_data);
+// Raw subscription callback; action is one of SURREAL_ACTION_*, json the full record
+// (for a delete, the record as it was). Invoked from an FFI runtime thread.
+typedef void (*surreal_change_cb)(const char* topic, int32_t action, const char* json, void* user_data);
+// Leveled log record (see SURREAL_LOG_*). target names the emitting component
+// ("surrealdb_ffi" or an SDK module path); fields_json is a JSON object, "{}" if
+// none. Invoked from any thread; pointers are only valid for the duration of the call.
+typedef void (*surreal_log_handler_cb)(int32_t level, const char* target, const char* msg, const char* fields_json, void* user_data);
+// Completion callback for async publishes. rc is 0 on success, 1 if spooled, otherwise
+// a publish code (see "Publish return codes" below); error is NULL unless rc < 0.
+// Invoked from an FFI runtime thread; pointers are only valid for the duration of the call.
+typedef void (*surreal_publish_cb)(int32_t rc, const char* table, const char* json, const char* error, void* user_data);
+// Connection state callback; see SURREAL_STATE_*. Invoked from an FFI runtime thread,
+// once per transition; detail is a short human-readable reason.
+typedef void (*surreal_state_cb)(int32_t state, const char* detail, void* user_data);
+// Token provider: writes a fresh NUL-terminated token into out (at most out_len
+// bytes) and returns its length, or <= 0 if none is available. Invoked from an
+// FFI thread when the server rejects the current token.
+typedef int32_t (*surreal_token_provider_cb)(char* out, uint32_t out_len, void* user_data);
+
+#define SURREAL_STATE_CONNECTED    1
+#define SURREAL_STATE_DISCONNECTED 2
+#define SURREAL_STATE_RECONNECTING 3
+#define SURREAL_STATE_AUTH_FAILED  4
+
+#define SURREAL_ACTION_CREATE 1
+#define SURREAL_ACTION_UPDATE 2
+#define SURREAL_ACTION_DELETE 3
+
+// Subscription kinds; a topic can have one subscription of each kind at a time.
+// surreal_subscribe and surreal_subscribe_where share SURREAL_SUB_COMMAND.
+#define SURREAL_SUB_COMMAND    1
+#define SURREAL_SUB_RAW        2
+#define SURREAL_SUB_CHANGEFEED 3
 
 // Initializes async runtime (no-op in stub). Returns 0 on success.
 int32_t surreal_init_runtime(void);
 
+// Like surreal_init_runtime with tuning: worker_threads (0 = one per CPU) and a
+// thread name prefix (NULL = "surrealdb-ffi"; threads are named prefix-N).
+// Does nothing if a runtime is already running.
+int32_t surreal_init_runtime_ex(uint32_t worker_threads, const char* thread_name_prefix);
+
+// Stops all subscriptions, flushes pending async publishes and shuts the runtime
+// down, waiting at most timeout_ms (0 = 5000) overall. Returns 0, or 1 if calls
+// were still running at the deadline (the runtime ends when they return).
+// Handles are unusable afterwards but must still be released with surreal_close.
+// A later surreal_init_runtime starts a fresh runtime (e.g. on module reload).
+int32_t surreal_shutdown_runtime(uint32_t timeout_ms);
+
 // Connects to SurrealDB and returns a handle, or NULL on failure.
+// The URL scheme selects the transport: ws:// (default for a bare host:port) or
+// wss:// use WebSocket /rpc; http:// or https:// use plain HTTP requests for
+// proxies that do not pass WebSocket upgrades. Over HTTP, surreal_subscribe
+// polls the table for new rows once per second instead of using LIVE queries.
+// mem:// runs an in-memory datastore inside the library (builds with the
+// `embedded` feature only); mem:// handles with the same ns/db share one, and
+// credentials are ignored.
 SurHandle* surreal_connect(const char* url,
                            const char* ns,
                            const char* db,
@@ -24,12 +80,193 @@ SurHandle* surreal_connect_with_token(const char* url,
                                       const char* db,
                                       const char* token);
 
-// Publishes a JSON payload to a table/topic. Returns 0 on success.
+// TLS settings for surreal_connect_tls. Every field may be NULL or empty.
+//  ca_file     PEM bundle that replaces the built-in web PKI roots (private CA).
+//  cert_file   PEM client certificate chain for mutual TLS; requires key_file.
+//  key_file    PEM private key (PKCS#8, PKCS#1 or SEC1) matching cert_file.
+//  server_name Name the server certificate must match instead of the URL host
+//              (useful when connecting by IP or through a tunnel).
+typedef struct SurTlsOptions {
+    const char* ca_file;
+    const char* cert_file;
+    const char* key_file;
+    const char* server_name;
+} SurTlsOptions;
+
+// Connects with explicit TLS settings. The URL scheme selects the transport
+// (see surreal_connect); tls only applies to wss:// and https://. A non-empty token takes precedence over
+// user/pass. tls may be NULL for default verification. Returns NULL on failure;
+// surreal_last_error_global() is -106 if the TLS files could not be loaded.
+SurHandle* surreal_connect_tls(const char* url,
+                               const char* ns,
+                               const char* db,
+                               const char* user,
+                               const char* pass,
+                               const char* token,
+                               const SurTlsOptions* tls);
+
+// Generic connect from a JSON object, e.g.
+//   {"url":"wss://db:8000","ns":"voice","db":"pbx",
+//    "auth":{"level":"database","user":"fs","pass":"secret"},
+//    "tls":{"ca_file":"/etc/ssl/ca.pem"}}
+// auth.level picks the sign-in:
+//   "root"      user, pass
+//   "namespace" user, pass (a user defined ON NAMESPACE ns)
+//   "database"  user, pass (a user defined ON DATABASE db)
+//   "record"    access, params (object passed to the access method), signup
+//               (true = sign up when signin fails)
+//   "token"     token
+// Optional: "transport" (scheme for a host:port url: ws, wss, http, https, mem)
+// and "tls" with ca_file, cert_file, key_file, server_name (see SurTlsOptions).
+// Returns NULL on failure; surreal_last_error_global() is -107 if the options
+// do not parse, -103 if signin/signup is rejected.
+SurHandle* surreal_connect_opts(const char* json_options);
+
+// Publishes a JSON payload to a table/topic. Returns 0 on success, 1 if the
+// document was written to the spool (see surreal_spool_enable) for later replay,
+// or a publish code (see "Publish return codes" below).
 int32_t surreal_publish(SurHandle* handle,
                         const char* table_or_topic,
                         const char* json_payload);
 
-// Frees the handle and closes connections.
+// Publishes a JSON array of documents to a table in a single request.
+// Each row is inserted independently, so one bad document does not fail the batch.
+// Returns 0 if every row was inserted, 1 if the whole batch was spooled (same rules
+// as surreal_publish), -7 if the server rejected some rows, or another publish
+// code if the batch could not be sent at all. On -7 and -10,
+// surreal_last_error_text() holds a JSON array of {"index":n,"error":"..."}.
+int32_t surreal_publish_batch(SurHandle* handle,
+                              const char* table,
+                              const char* json_array);
+
+// Starts async publish mode: documents are queued in a bounded Rust-side queue
+// (queue_capacity, 0 = 10000) and inserted in batches of up to max_batch (0 = 256)
+// by a background task with its own connection, in queue order (a batch is split
+// into runs of consecutive documents for the same table). cb may be NULL.
+// Returns 0 on success (also if already started), -101 runtime missing, -8 internal.
+int32_t surreal_publish_async_start(SurHandle* handle,
+                                    uint32_t queue_capacity,
+                                    uint32_t max_batch,
+                                    surreal_publish_cb cb,
+                                    void* user_data);
+
+// Queues a JSON document for async insertion; never blocks on the network.
+// Returns 0 when queued, -3 invalid table, -4 invalid json ptr, -5 json parse failed,
+// -8 async mode not started, -9 queue full (caller decides whether to drop or retry).
+// The completion callback receives -2 reconnect failed, -6 insert failed, -7 row rejected,
+// -10 spool write failed, or 1 when the document was spooled. With a spool enabled, a
+// full queue spools instead of -9.
+int32_t surreal_publish_async(SurHandle* handle,
+                              const char* table_or_topic,
+                              const char* json_payload);
+
+// Returns the number of documents waiting in the async queue (0 if not started), or -1.
+int64_t surreal_publish_queue_depth(SurHandle* handle);
+
+// Enables a durable on-disk spool under dir. Documents that cannot be published
+// (connection down, queue full) are appended to segment files and replayed in order
+// in the background once the connection is back; later documents queue behind them
+// to keep ordering. Appends are synced to disk before they count as spooled.
+// max_bytes bounds the spool size (0 = 1 GiB; oldest segments are evicted first);
+// max_age_secs drops records older than that on replay (0 = keep forever).
+// Records left by a previous run are picked up. The directory is locked for the
+// handle's lifetime. Returns 0 on success, -2 if it cannot be opened or is in use.
+int32_t surreal_spool_enable(SurHandle* handle,
+                             const char* dir,
+                             uint64_t max_bytes,
+                             uint32_t max_age_secs);
+
+// Number of documents waiting in the spool (0 if disabled), or -1.
+int64_t surreal_spool_depth(SurHandle* handle);
+// Bytes of unreplayed spool data (0 if disabled), or -1.
+int64_t surreal_spool_bytes(SurHandle* handle);
+// Documents discarded by the size/age bounds or as unreadable since the spool was enabled.
+int64_t surreal_spool_dropped(SurHandle* handle);
+
+// Sizes the handle's connection pool used by publish, select, get, update and query.
+// Calls go round-robin to an idle connection; when all are busy the pool grows up to
+// max_size (capped at 64), so a slow query does not hold up other callers. Every
+// health_interval_ms (0 disables) open connections are pinged, dead ones dropped,
+// min_size kept open and idle extras closed after 60s. Defaults: 1/1/30000.
+// Opens min_size connections before returning; returns -2 if some could not be opened.
+int32_t surreal_pool_config(SurHandle* handle,
+                            uint32_t min_size,
+                            uint32_t max_size,
+                            uint32_t health_interval_ms);
+
+// Writes pool statistics as a JSON object into out_json:
+// {"min","max","size","open","busy","checkouts","connects","connect_failures",
+//  "health_checks","health_failures"}. Returns 0, or -20 if out_len is too small.
+int32_t surreal_pool_stats_json(SurHandle* handle, char* out_json, uint32_t out_len);
+
+// Returns the handle's metrics as a JSON object: "state"; "publish" document counts
+// ("ok","spooled","failed") and "latency_ms" histogram; "query" latency_ms (select,
+// get, update, query); "errors" per category name; "connections" ("open","busy",
+// "connects","connect_failures","reconnects"); "subscriptions" ("active",
+// "notifications","claims","claim_failures","claim_conflicts","claims_expired");
+// "queue" ("async_depth","spool_depth","spool_bytes","spool_dropped"). Histograms
+// carry "count", "sum_ms" and cumulative "buckets" keyed by upper bound in ms.
+// Release with surreal_free_string().
+// NULL for a NULL handle. The stub reports only {"state","stub":true}.
+char* surreal_stats_json(SurHandle* handle);
+
+// The same metrics in Prometheus text exposition format (surrealdb_ffi_* names).
+// Release with surreal_free_string().
+char* surreal_stats_prometheus(SurHandle* handle);
+
+// Registers a callback for connection state changes. A background supervisor owns
+// reconnection: while the server is down, calls fail fast (or spool) instead of blocking
+// on a connect, and the supervisor retries with exponential backoff and jitter.
+// Auth failures are reported as SURREAL_STATE_AUTH_FAILED and retried as well.
+int32_t surreal_set_state_callback(SurHandle* handle, surreal_state_cb cb, void* user_data);
+
+// Returns the current SURREAL_STATE_* of the handle, or -1.
+int32_t surreal_connection_state(SurHandle* handle);
+
+// Command claims are a compare-and-set: a row is claimed only if its status is still
+// 'new', so with several switches or workers on one table each command is delivered
+// once. The claim writes claimed_by = node_id and lease_until = now + lease_ms, and
+// the lease is extended every lease_ms/3 while the command callback runs, so only a
+// node that died or hung lets it expire.
+// NULL or "" keeps the node id (default: the host name); 0 keeps the lease (60000 ms).
+int32_t surreal_set_claim_options(SurHandle* handle, const char* node_id, uint32_t lease_ms);
+
+// Command subscriptions recover claims whose lease_until has passed (the claimant
+// crashed or hung) every reap_interval_ms: the row goes back to 'new', or to 'failed'
+// once it has expired max_attempts times, and attempts is incremented. 0 keeps the
+// value (defaults: 10000 ms, 3 attempts). Recovery runs inside each active command
+// subscription (surreal_subscribe/surreal_subscribe_where) on the table; with none
+// running in any process (switch or worker), expired claims stay 'processing'.
+int32_t surreal_set_claim_recovery(SurHandle* handle, uint32_t reap_interval_ms, uint32_t max_attempts);
+
+// Sets the reconnect backoff: delays double from initial_ms up to max_ms, each picked at
+// random from [d/2, d]. 0 keeps the defaults (250 ms, 30000 ms). Applies to the supervisor,
+// subscriptions and the async publish queue started after the call.
+int32_t surreal_set_reconnect_backoff(SurHandle* handle, uint32_t initial_ms, uint32_t max_ms);
+
+// Deadlines for connect (connect, signin, use ns/db), query (select, get, update, query)
+// and publish (inserts, batches, spool replay). A call that expires returns -21 and its
+// connection is dropped and reopened; a connect that expires returns NULL with
+// surreal_last_error_global() == -108.
+// surreal_set_default_timeouts applies to handles connected after the call; 0 keeps the
+// built-in value (10000, 30000, 10000 ms). surreal_set_timeouts changes one handle;
+// 0 leaves that value unchanged.
+int32_t surreal_set_default_timeouts(uint32_t connect_ms, uint32_t query_ms, uint32_t publish_ms);
+int32_t surreal_set_timeouts(SurHandle* handle, uint32_t connect_ms, uint32_t query_ms, uint32_t publish_ms);
+
+// Token renewal for token-authenticated handles. When the server rejects the
+// token (e.g. the JWT expired), the FFI asks the provider for a fresh one, or
+// re-reads the token file if no provider is set, then re-authenticates pooled
+// connections, subscriptions and the async publisher in place. The failing call
+// still returns its error; later calls use the new token. Renewals are logged via
+// surreal_set_logger. cb = NULL / path = NULL disables the source.
+// A token_file can also be given in surreal_connect_opts ("auth":{"level":"token",
+// "token_file":"..."}); the token is then read from it when "token" is empty.
+int32_t surreal_set_token_provider(SurHandle* handle, surreal_token_provider_cb cb, void* user_data);
+int32_t surreal_set_token_file(SurHandle* handle, const char* path);
+
+// Frees the handle and closes connections. Pending async publishes are flushed
+// for up to 5 seconds before the queue is dropped.
 void surreal_close(SurHandle* handle);
 
 // Returns last error code for the handle (implementation-defined).
@@ -42,25 +279,141 @@ int32_t surreal_is_stub(void);
 
 // Registers a logger callback that the FFI may use to emit informational
 // messages (e.g., subscribe row counts). Passing NULL disables logging.
+// Receives INFO and above only; replaces any handler set with surreal_set_log_handler.
 int32_t surreal_set_logger(surreal_log_cb cb, void* user_data);
 
+#define SURREAL_LOG_ERROR 1
+#define SURREAL_LOG_WARN  2
+#define SURREAL_LOG_INFO  3
+#define SURREAL_LOG_DEBUG 4
+#define SURREAL_LOG_TRACE 5
+
+// Registers a leveled log handler for FFI messages and SurrealDB SDK events.
+// Records more verbose than max_level are dropped before they are formatted.
+// Replaces any surreal_set_logger callback; NULL disables logging. With no
+// callback, nothing is printed unless SURREALDB_FFI_TRACE=1 (then stderr).
+int32_t surreal_set_log_handler(surreal_log_handler_cb cb, int32_t max_level, void* user_data);
+
+// Error categories. Every recorded failure is classified into one of these, reported
+// as "kind" by surreal_last_error_json(). Each entry point follows one convention:
+//
+// Category codes: these return the code itself, so each code has one meaning
+// (SURREAL_ERR_INVALID_ARGUMENT also for a NULL handle): surreal_subscribe_where,
+// surreal_subscribe_raw, surreal_subscribe_changefeed, surreal_unsubscribe_kind,
+// surreal_set_claim_options, surreal_set_claim_recovery.
+//
+// Publish return codes: surreal_publish, surreal_publish_batch, surreal_publish_async,
+// surreal_publish_async_start and the surreal_publish_cb rc share one set:
+//    1 spooled                       -6 server or transport failure (query)
+//   -1 NULL handle (invalid_argument) -7 rows rejected by the server (query)
+//   -2 not connected                 -8 async publisher not running (invalid_argument)
+//   -3 NULL or invalid table         -9 async queue full (queue_full)
+//   -4 NULL payload                  -10 spool write failed (spool)
+//   -5 JSON did not parse (json)     -21 timeout, -101 runtime missing
+//
+// Every other function keeps its historical per-function codes (-3 is a bad id in
+// surreal_get); the codes with one meaning everywhere are listed below.
+#define SURREAL_ERR_INVALID_ARGUMENT -301 // NULL or malformed table, id, options
+#define SURREAL_ERR_NOT_CONNECTED    -302 // no usable connection
+#define SURREAL_ERR_AUTH             -303 // signin, signup or token rejected
+#define SURREAL_ERR_QUERY            -304 // the server rejected a statement
+#define SURREAL_ERR_TIMEOUT          -305 // operation did not finish in time (-21, -108)
+#define SURREAL_ERR_TRUNCATED        -306 // result did not fit the buffer (-20)
+#define SURREAL_ERR_JSON             -307 // document or vars did not parse
+#define SURREAL_ERR_SPOOL            -308 // durable spool open/write failed
+#define SURREAL_ERR_QUEUE_FULL       -309 // async publish queue is full
+#define SURREAL_ERR_RUNTIME          -310 // async runtime missing (-100, -101)
+#define SURREAL_ERR_TLS              -311 // TLS files or settings (-106)
+#define SURREAL_ERR_UNSUPPORTED      -312 // not available in this build/transport
+#define SURREAL_ERR_INTERNAL         -313 // poisoned lock or other internal failure
+
+// Returns a static name ("auth", "query", ...) for a category code or for a code
+// with one meaning everywhere (-20, -21, -100..-108); "ok" for 0, "unknown" otherwise.
+const char* surreal_error_name(int32_t code);
+
+// Returns the last failure as a JSON object, e.g.
+//   {"code":-6,"kind":-304,"name":"query","op":"query",
+//    "message":"Parse error: ...","statement":"SELEC * FROM x"}
+// code is the value the call returned, kind/name its category, message the
+// server or library text, and statement the (possibly shortened) SurrealQL sent,
+// or null. With a NULL handle it reports the last connect failure (see
+// surreal_last_error_global()). Release with surreal_free_string().
+char* surreal_last_error_json(SurHandle* handle);
+
 // Copies the last error text for the handle into the provided buffer.
 // Returns the number of bytes written (excluding NUL), or a negative error code on failure.
 // If no error text is available, writes an empty string.
 int32_t surreal_last_error_text(SurHandle* handle, char* buf, uint32_t len);
 
-// Subscribes to incoming commands/messages on a topic (stubbed).
+// Subscribes to incoming commands/messages on a topic: rows with status 'new' are
+// claimed (status 'processing') and passed to cb. Uses a LIVE query over
+// WebSocket and polling over HTTP. After each (re)connect, rows already 'new' are
+// claimed first, ordered by created_at.
 int32_t surreal_subscribe(SurHandle* handle,
                           const char* topic,
                           surreal_command_cb cb,
                           void* user_data);
 
-// Unsubscribes from a topic (stubbed).
+// Like surreal_subscribe, but the server only sends rows matching condition, a
+// SurrealQL expression: LIVE SELECT * FROM topic WHERE condition (polling adds it to
+// the status filter). Values go in vars_json as $name bindings, e.g. condition
+// "target = $host" with vars {"host":"fs1"}; the server does not keep variables
+// with a live query, so they are written into its condition as literals. topic must be a plain identifier and
+// condition a single expression (no ';'). The condition is checked against the
+// server when connected. Returns 0 or a SURREAL_ERR_* code: QUERY if the server
+// rejects the condition (see surreal_last_error_json), INVALID_ARGUMENT for a bad
+// topic or condition, JSON if vars_json is not an object.
+int32_t surreal_subscribe_where(SurHandle* handle,
+                                const char* topic,
+                                const char* condition,
+                                const char* vars_json,
+                                surreal_command_cb cb,
+                                void* user_data);
+
+// Delivers every create, update and delete on topic to cb, without claiming and
+// without looking at status. condition/vars_json optionally filter on the server as
+// in surreal_subscribe_where (NULL = whole table), and a condition is checked against
+// the server the same way. Needs a WebSocket connection: returns
+// SURREAL_ERR_UNSUPPORTED over HTTP. Subscribing again replaces an earlier raw
+// subscription on the topic; a command subscription on it keeps running.
+// Returns 0 or a SURREAL_ERR_* code, as for surreal_subscribe_where.
+int32_t surreal_subscribe_raw(SurHandle* handle,
+                              const char* topic,
+                              const char* condition,
+                              const char* vars_json,
+                              surreal_change_cb cb,
+                              void* user_data);
+
+// Reads the change feed of topic (a table defined with CHANGEFEED) with SHOW CHANGES
+// every poll_ms (0 = 1000) and delivers each change to cb as surreal_subscribe_raw does.
+// The last delivered versionstamp is saved once per batch read, so a restart resumes
+// right after it (a crash mid-batch redelivers that batch). checkpoint: NULL = memory only; "table:<name>" = a record in that table keyed
+// by [node id, topic] (see surreal_set_claim_options); otherwise a local file path.
+// Works over WebSocket and HTTP. Returns 0 or a SURREAL_ERR_* code: QUERY if the server
+// rejects SHOW CHANGES (e.g. no CHANGEFEED on the table), INVALID_ARGUMENT for a bad
+// topic or checkpoint.
+int32_t surreal_subscribe_changefeed(SurHandle* handle,
+                                     const char* topic,
+                                     const char* checkpoint,
+                                     uint32_t poll_ms,
+                                     surreal_change_cb cb,
+                                     void* user_data);
+
+// Stops every subscription on a topic, of any kind. Returns 0, -4 if there is none.
 int32_t surreal_unsubscribe(SurHandle* handle, const char* topic);
 
+// Stops only the subscription of kind (SURREAL_SUB_*) on a topic. Returns 0, or
+// SURREAL_ERR_INVALID_ARGUMENT if there is no such subscription.
+int32_t surreal_unsubscribe_kind(SurHandle* handle, const char* topic, int32_t kind);
+
 // Testing helper: trigger a callback invocation (stub only).
 int32_t surreal_debug_emit(SurHandle* handle, const char* topic, const char* json);
 
+// Record ids (update/get) may be bare keys or "table:key". Keys use SurrealQL syntax:
+// numeric (42), escaped (⟨a-b⟩), array ([1,"x"]) and object ({a:1}) keys keep their type;
+// anything else (e.g. a FreeSWITCH UUID) is taken as a string key. Ids delivered by
+// surreal_subscribe are in this format and can be passed back unchanged.
+
 // Updates a record by id with a JSON object (merge/content semantics defined by implementation).
 // Returns 0 on success.
 int32_t surreal_update(SurHandle* handle,
@@ -68,6 +421,12 @@ int32_t surreal_update(SurHandle* handle,
                        const char* id,
                        const char* json_patch);
 
+// Functions writing into out_json/out_len never truncate: if the result does not fit,
+// they write an empty string and return -20; surreal_last_error_text() then reports
+// the required size. The *_alloc variants instead return a NUL-terminated string
+// allocated by the library in *out_json, which the caller releases with
+// surreal_free_string(). *out_json is NULL when the call fails.
+
 // Reads rows from a table, writing JSON array into out_json (NUL-terminated).
 // Limit of 0 uses a sensible default; implementation may cap maximum.
 // Returns 0 on success.
@@ -85,4 +444,24 @@ int32_t surreal_get(SurHandle* handle,
                     char* out_json,
                     uint32_t out_len);
 
+// Runs arbitrary SurrealQL with variables bound from a JSON object (vars_json may be
+// NULL or "{}"), e.g. sql "SELECT * FROM fs_cdr WHERE billsec > $min" with {"min":60}.
+// Writes a JSON array with one entry per statement into out_json:
+//   [{"status":"OK","result":...},{"status":"ERR","result":"error text"}]
+// Statement errors are reported in place and still return 0.
+// Returns -2 empty sql, -3 vars_json is not an object, -4 reconnect failed, -6 request failed.
+int32_t surreal_query(SurHandle* handle,
+                      const char* sql,
+                      const char* vars_json,
+                      char* out_json,
+                      uint32_t out_len);
+
+// Allocating variants of surreal_select/surreal_get/surreal_query.
+int32_t surreal_select_alloc(SurHandle* handle, const char* table, uint32_t limit, char** out_json);
+int32_t surreal_get_alloc(SurHandle* handle, const char* table, const char* id, char** out_json);
+int32_t surreal_query_alloc(SurHandle* handle, const char* sql, const char* vars_json, char** out_json);
+
+// Releases a string returned by a *_alloc function. NULL is ignored.
+void surreal_free_string(char* s);
+
 #endif // SURREALDB_FFI_H
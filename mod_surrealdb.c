@@ -7,12 +7,19 @@
  *
  * Configuration: read from surrealdb.conf.xml (autoload_configs)
  *   <param name="url" value="127.0.0.1:8000"/>
+ *   <param name="transport" value="ws"/>  <!-- ws or http, for URLs without scheme -->
  *   <param name="namespace" value="test"/>
  *   <param name="database" value="test"/>
  *   <param name="username" value="root"/>
  *   <param name="password" value="root"/>
  *   <!-- Or use token instead of username/password -->
  *   <param name="token" value=""/>
+ *   <!-- Re-read when the token is rejected (e.g. expired); may replace token -->
+ *   <param name="token-file" value=""/>
+ *   <!-- Least privilege: root (default), namespace, database or record -->
+ *   <param name="auth-level" value="database"/>
+ *   <!-- wss:// URLs: optional private CA, client certificate and server name -->
+ *   <param name="tls-ca-file" value=""/>
  *   <param name="connect-on-load" value="true"/>
  */
 
@@ -30,11 +37,58 @@ static void surreal_ffi_log_cb(const char* msg, void* user_data)
     if (!msg) return;
     switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_INFO, "mod_surrealdb: %s\n", msg);
 }
+/* Leveled FFI log records, including SurrealDB SDK internals, at the matching FreeSWITCH level */
+static void surreal_ffi_log_handler_cb(int32_t level, const char* target, const char* msg, const char* fields_json, void* user_data)
+{
+    switch_log_level_t fs_level;
+    (void)user_data;
+    if (!msg) return;
+    switch (level) {
+    case SURREAL_LOG_ERROR: fs_level = SWITCH_LOG_ERROR; break;
+    case SURREAL_LOG_WARN: fs_level = SWITCH_LOG_WARNING; break;
+    case SURREAL_LOG_INFO: fs_level = SWITCH_LOG_INFO; break;
+    case SURREAL_LOG_DEBUG: fs_level = SWITCH_LOG_DEBUG; break;
+    default: fs_level = SWITCH_LOG_DEBUG10; break;
+    }
+    if (fields_json && strcmp(fields_json, "{}")) {
+        switch_log_printf(SWITCH_CHANNEL_LOG, fs_level, "mod_surrealdb: [%s] %s %s\n", target ? target : "", msg, fields_json);
+    } else {
+        switch_log_printf(SWITCH_CHANNEL_LOG, fs_level, "mod_surrealdb: [%s] %s\n", target ? target : "", msg);
+    }
+}
 /* Allow running with older libsurrealdb_ffi without failing to load. */
 extern int32_t surreal_last_error_text(SurHandle* handle, char* buf, uint32_t len) __attribute__((weak));
+extern int32_t surreal_publish_async_start(SurHandle* handle, uint32_t queue_capacity, uint32_t max_batch, surreal_publish_cb cb, void* user_data) __attribute__((weak));
+extern int32_t surreal_publish_async(SurHandle* handle, const char* table_or_topic, const char* json_payload) __attribute__((weak));
+extern int64_t surreal_publish_queue_depth(SurHandle* handle) __attribute__((weak));
+extern int32_t surreal_spool_enable(SurHandle* handle, const char* dir, uint64_t max_bytes, uint32_t max_age_secs) __attribute__((weak));
+extern int32_t surreal_query(SurHandle* handle, const char* sql, const char* vars_json, char* out_json, uint32_t out_len) __attribute__((weak));
+extern int32_t surreal_select_alloc(SurHandle* handle, const char* table, uint32_t limit, char** out_json) __attribute__((weak));
+extern int32_t surreal_get_alloc(SurHandle* handle, const char* table, const char* id, char** out_json) __attribute__((weak));
+extern int32_t surreal_query_alloc(SurHandle* handle, const char* sql, const char* vars_json, char** out_json) __attribute__((weak));
+extern void surreal_free_string(char* s) __attribute__((weak));
+extern char* surreal_last_error_json(SurHandle* handle) __attribute__((weak));
+extern int32_t surreal_pool_config(SurHandle* handle, uint32_t min_size, uint32_t max_size, uint32_t health_interval_ms) __attribute__((weak));
+extern int32_t surreal_pool_stats_json(SurHandle* handle, char* out_json, uint32_t out_len) __attribute__((weak));
+extern char* surreal_stats_json(SurHandle* handle) __attribute__((weak));
+extern char* surreal_stats_prometheus(SurHandle* handle) __attribute__((weak));
+extern int32_t surreal_set_state_callback(SurHandle* handle, surreal_state_cb cb, void* user_data) __attribute__((weak));
+extern int32_t surreal_set_claim_options(SurHandle* handle, const char* node_id, uint32_t lease_ms) __attribute__((weak));
+extern int32_t surreal_set_claim_recovery(SurHandle* handle, uint32_t reap_interval_ms, uint32_t max_attempts) __attribute__((weak));
+extern int32_t surreal_set_reconnect_backoff(SurHandle* handle, uint32_t initial_ms, uint32_t max_ms) __attribute__((weak));
+extern int32_t surreal_set_default_timeouts(uint32_t connect_ms, uint32_t query_ms, uint32_t publish_ms) __attribute__((weak));
+extern int32_t surreal_set_token_file(SurHandle* handle, const char* path) __attribute__((weak));
+extern SurHandle* surreal_connect_opts(const char* json_options) __attribute__((weak));
+extern int32_t surreal_init_runtime_ex(uint32_t worker_threads, const char* thread_name_prefix) __attribute__((weak));
+extern int32_t surreal_shutdown_runtime(uint32_t timeout_ms) __attribute__((weak));
+extern int32_t surreal_subscribe_where(SurHandle* handle, const char* topic, const char* condition, const char* vars_json, surreal_command_cb cb, void* user_data) __attribute__((weak));
+extern int32_t surreal_set_log_handler(surreal_log_handler_cb cb, int32_t max_level, void* user_data) __attribute__((weak));
+extern SurHandle* surreal_connect_tls(const char* url, const char* ns, const char* db, const char* user, const char* pass, const char* token, const SurTlsOptions* tls) __attribute__((weak));
 #endif
 
 #define MOD_SURREALDB_NAME "mod_surrealdb"
+/* CUSTOM event fired on FFI connection state changes (headers: State, Detail) */
+#define MOD_SURREALDB_STATE_EVENT "surrealdb::state"
 
 SWITCH_MODULE_LOAD_FUNCTION(mod_surrealdb_load);
 SWITCH_MODULE_SHUTDOWN_FUNCTION(mod_surrealdb_shutdown);
@@ -48,19 +102,43 @@ SWITCH_STANDARD_API(mod_surrealdb_select_api);
 SWITCH_STANDARD_API(mod_surrealdb_get_api);
 /* API: surrealdb.update <table> <id> <json> */
 SWITCH_STANDARD_API(mod_surrealdb_update_api);
+/* API: surrealdb.query [vars_json] <sql> */
+SWITCH_STANDARD_API(mod_surrealdb_query_api);
+/* API: surrealdb.pool */
+SWITCH_STANDARD_API(mod_surrealdb_pool_api);
+/* API: surrealdb.stats */
+SWITCH_STANDARD_API(mod_surrealdb_stats_api);
 
 /* internal helpers */
 static char *mod_surrealdb_ltrim(char *s);
+#ifdef HAVE_SURREALDB_FFI
+static void mod_surrealdb_print_result(switch_stream_handle_t *stream, const char *what, int rc, const char *out);
+#endif
 
 typedef struct mod_surrealdb_cfg_s {
 	char *url;
+	char *transport;
 	char *ns;
 	char *db;
 	char *user;
 	char *pass;
 	char *token;
+	char *token_file;
+	/* Sign-in level: root (default), namespace, database or record */
+	char *auth_level;
+	char *auth_access;
+	char *auth_params;
+	switch_bool_t auth_signup;
 	switch_bool_t connect_on_load;
 	char *command_table;
+	/* Optional SurrealQL condition for the command subscription ($hostname, $switchname bound) */
+	char *command_filter;
+	/* Claimant recorded on command rows (default: switchname) and claim lease (0 = FFI default) */
+	char *node_id;
+	uint32_t claim_lease_ms;
+	/* Recovery of expired claims (0 = FFI defaults) */
+	uint32_t claim_reap_ms;
+	uint32_t claim_max_attempts;
 	switch_bool_t enable_commands;
 	/* Event sink */
 	switch_bool_t enable_events;
@@ -71,6 +149,30 @@ typedef struct mod_surrealdb_cfg_s {
     /* CDR sink */
     switch_bool_t enable_cdr;
     char *cdr_table;
+	/* Durable spool for publishes during outages */
+	char *spool_dir;
+	uint32_t spool_max_mb;
+	uint32_t spool_max_age_s;
+	/* FFI connection pool */
+	uint32_t pool_min;
+	uint32_t pool_max;
+	uint32_t pool_health_ms;
+	/* FFI runtime worker threads (0 = one per CPU) */
+	uint32_t runtime_threads;
+	/* Most verbose FFI log level forwarded (SURREAL_LOG_*) */
+	int32_t log_level;
+	/* Reconnect backoff (0 = FFI defaults) */
+	uint32_t reconnect_initial_ms;
+	uint32_t reconnect_max_ms;
+	/* Per-operation timeouts (0 = FFI defaults) */
+	uint32_t connect_timeout_ms;
+	uint32_t query_timeout_ms;
+	uint32_t publish_timeout_ms;
+	/* TLS for wss:// URLs (PEM files) */
+	char *tls_ca_file;
+	char *tls_cert_file;
+	char *tls_key_file;
+	char *tls_server_name;
 } mod_surrealdb_cfg_t;
 
 typedef struct mod_surrealdb_state_s {
@@ -82,6 +184,7 @@ typedef struct mod_surrealdb_state_s {
 	mod_surrealdb_cfg_t cfg;
 	/* Event sink state */
 	switch_bool_t events_running;
+	switch_bool_t async_publish;
 	switch_queue_t *send_queue;
 	switch_thread_t *event_thread;
 	switch_time_t cb_reset_time;
@@ -115,6 +218,7 @@ static void mod_surrealdb_read_config(switch_memory_pool_t *pool)
 	g_mod.cfg.send_queue_size = 1000;
 	g_mod.cfg.circuit_breaker_ms = 10000;
     g_mod.cfg.enable_cdr = SWITCH_FALSE;
+	g_mod.cfg.log_level = 3; /* info */
 
 	if (!(xml = switch_xml_open_cfg("surrealdb.conf", &cfg, NULL))) {
 		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: Failed to open surrealdb.conf\n", MOD_SURREALDB_NAME);
@@ -129,6 +233,8 @@ static void mod_surrealdb_read_config(switch_memory_pool_t *pool)
 			if (!zstr(value)) {
 				if (!strcasecmp(name, "url")) {
 					g_mod.cfg.url = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "transport")) {
+					g_mod.cfg.transport = switch_core_strdup(pool, value);
 				} else if (!strcasecmp(name, "namespace")) {
 					g_mod.cfg.ns = switch_core_strdup(pool, value);
 				} else if (!strcasecmp(name, "database")) {
@@ -139,10 +245,30 @@ static void mod_surrealdb_read_config(switch_memory_pool_t *pool)
 					g_mod.cfg.pass = switch_core_strdup(pool, value);
 				} else if (!strcasecmp(name, "token")) {
 					g_mod.cfg.token = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "token-file")) {
+					g_mod.cfg.token_file = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "auth-level")) {
+					g_mod.cfg.auth_level = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "auth-access")) {
+					g_mod.cfg.auth_access = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "auth-params")) {
+					g_mod.cfg.auth_params = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "auth-signup")) {
+					g_mod.cfg.auth_signup = switch_true(value);
 				} else if (!strcasecmp(name, "connect-on-load")) {
 					g_mod.cfg.connect_on_load = switch_true(value);
 				} else if (!strcasecmp(name, "command-table")) {
 					g_mod.cfg.command_table = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "node-id")) {
+					if (!zstr(value)) g_mod.cfg.node_id = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "claim-lease-ms")) {
+					g_mod.cfg.claim_lease_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "claim-reap-ms")) {
+					g_mod.cfg.claim_reap_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "claim-max-attempts")) {
+					g_mod.cfg.claim_max_attempts = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "command-filter")) {
+					if (!zstr(value)) g_mod.cfg.command_filter = switch_core_strdup(pool, value);
 				} else if (!strcasecmp(name, "enable-commands")) {
 					g_mod.cfg.enable_commands = switch_true(value);
 				} else if (!strcasecmp(name, "enable-events")) {
@@ -161,6 +287,45 @@ static void mod_surrealdb_read_config(switch_memory_pool_t *pool)
 					g_mod.cfg.enable_cdr = switch_true(value);
 				} else if (!strcasecmp(name, "cdr-table")) {
 					g_mod.cfg.cdr_table = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "spool-dir")) {
+					g_mod.cfg.spool_dir = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "spool-max-mb")) {
+					g_mod.cfg.spool_max_mb = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "spool-max-age-s")) {
+					g_mod.cfg.spool_max_age_s = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "pool-min")) {
+					g_mod.cfg.pool_min = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "pool-max")) {
+					g_mod.cfg.pool_max = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "pool-health-interval-ms")) {
+					g_mod.cfg.pool_health_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "runtime-threads")) {
+					g_mod.cfg.runtime_threads = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "log-level")) {
+					if (!strcasecmp(value, "error")) g_mod.cfg.log_level = 1;
+					else if (!strcasecmp(value, "warning") || !strcasecmp(value, "warn")) g_mod.cfg.log_level = 2;
+					else if (!strcasecmp(value, "info")) g_mod.cfg.log_level = 3;
+					else if (!strcasecmp(value, "debug")) g_mod.cfg.log_level = 4;
+					else if (!strcasecmp(value, "trace")) g_mod.cfg.log_level = 5;
+					else switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: unknown log-level '%s', using info\n", MOD_SURREALDB_NAME, value);
+				} else if (!strcasecmp(name, "reconnect-initial-ms")) {
+					g_mod.cfg.reconnect_initial_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "reconnect-max-ms")) {
+					g_mod.cfg.reconnect_max_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "connect-timeout-ms")) {
+					g_mod.cfg.connect_timeout_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "query-timeout-ms")) {
+					g_mod.cfg.query_timeout_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "publish-timeout-ms")) {
+					g_mod.cfg.publish_timeout_ms = (uint32_t)atoi(value);
+				} else if (!strcasecmp(name, "tls-ca-file")) {
+					g_mod.cfg.tls_ca_file = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "tls-cert-file")) {
+					g_mod.cfg.tls_cert_file = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "tls-key-file")) {
+					g_mod.cfg.tls_key_file = switch_core_strdup(pool, value);
+				} else if (!strcasecmp(name, "tls-server-name")) {
+					g_mod.cfg.tls_server_name = switch_core_strdup(pool, value);
 				}
 			}
 		}
@@ -170,6 +335,77 @@ static void mod_surrealdb_read_config(switch_memory_pool_t *pool)
 }
 
 #ifdef HAVE_SURREALDB_FFI
+/* Called from an FFI runtime thread whenever the connection state changes */
+static void mod_surrealdb_state_cb(int32_t state, const char *detail, void *user_data)
+{
+	const char *name = "unknown";
+	switch_log_level_t level = SWITCH_LOG_WARNING;
+	switch_event_t *event = NULL;
+	(void)user_data;
+
+	switch (state) {
+		case SURREAL_STATE_CONNECTED: name = "connected"; level = SWITCH_LOG_INFO; break;
+		case SURREAL_STATE_DISCONNECTED: name = "disconnected"; break;
+		case SURREAL_STATE_RECONNECTING: name = "reconnecting"; break;
+		case SURREAL_STATE_AUTH_FAILED: name = "auth-failed"; level = SWITCH_LOG_ERROR; break;
+	}
+	switch_log_printf(SWITCH_CHANNEL_LOG, level, "%s: connection %s (%s)\n", MOD_SURREALDB_NAME, name, detail ? detail : "");
+
+	if (switch_event_create_subclass(&event, SWITCH_EVENT_CUSTOM, MOD_SURREALDB_STATE_EVENT) == SWITCH_STATUS_SUCCESS) {
+		switch_event_add_header_string(event, SWITCH_STACK_BOTTOM, "State", name);
+		switch_event_add_header_string(event, SWITCH_STACK_BOTTOM, "Detail", detail ? detail : "");
+		switch_event_fire(&event);
+	}
+}
+
+/* Builds the surreal_connect_opts JSON from the config; caller frees the result with free() */
+static char *mod_surrealdb_connect_opts_json(const char *url)
+{
+	cJSON *opts = cJSON_CreateObject();
+	cJSON *auth = cJSON_CreateObject();
+	/* auth-level is optional (token-file alone also ends up here); unset means root */
+	const char *level = !zstr(g_mod.cfg.auth_level) ? g_mod.cfg.auth_level : "root";
+	char *out;
+
+	cJSON_AddItemToObject(opts, "url", cJSON_CreateString(url));
+	cJSON_AddItemToObject(opts, "ns", cJSON_CreateString(g_mod.cfg.ns));
+	cJSON_AddItemToObject(opts, "db", cJSON_CreateString(g_mod.cfg.db));
+
+	if (!strcasecmp(level, "record")) {
+		cJSON *params = !zstr(g_mod.cfg.auth_params) ? cJSON_Parse(g_mod.cfg.auth_params) : NULL;
+		if (!zstr(g_mod.cfg.auth_params) && !params) {
+			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: auth-params is not valid JSON; ignoring\n", MOD_SURREALDB_NAME);
+		}
+		cJSON_AddItemToObject(auth, "level", cJSON_CreateString("record"));
+		cJSON_AddItemToObject(auth, "access", cJSON_CreateString(g_mod.cfg.auth_access ? g_mod.cfg.auth_access : ""));
+		cJSON_AddItemToObject(auth, "params", params ? params : cJSON_CreateObject());
+		cJSON_AddItemToObject(auth, "signup", cJSON_CreateBool(g_mod.cfg.auth_signup == SWITCH_TRUE));
+	} else if (!zstr(g_mod.cfg.token) || !zstr(g_mod.cfg.token_file)) {
+		cJSON_AddItemToObject(auth, "level", cJSON_CreateString("token"));
+		cJSON_AddItemToObject(auth, "token", cJSON_CreateString(g_mod.cfg.token ? g_mod.cfg.token : ""));
+		if (!zstr(g_mod.cfg.token_file)) cJSON_AddItemToObject(auth, "token_file", cJSON_CreateString(g_mod.cfg.token_file));
+	} else {
+		/* Unknown levels are passed through and rejected by the FFI (-107) */
+		cJSON_AddItemToObject(auth, "level", cJSON_CreateString(!strcasecmp(level, "namespace") ? "namespace" : !strcasecmp(level, "database") ? "database" : level));
+		cJSON_AddItemToObject(auth, "user", cJSON_CreateString(g_mod.cfg.user ? g_mod.cfg.user : ""));
+		cJSON_AddItemToObject(auth, "pass", cJSON_CreateString(g_mod.cfg.pass ? g_mod.cfg.pass : ""));
+	}
+	cJSON_AddItemToObject(opts, "auth", auth);
+
+	if (!zstr(g_mod.cfg.tls_ca_file) || !zstr(g_mod.cfg.tls_cert_file) || !zstr(g_mod.cfg.tls_server_name)) {
+		cJSON *tls = cJSON_CreateObject();
+		if (g_mod.cfg.tls_ca_file) cJSON_AddItemToObject(tls, "ca_file", cJSON_CreateString(g_mod.cfg.tls_ca_file));
+		if (g_mod.cfg.tls_cert_file) cJSON_AddItemToObject(tls, "cert_file", cJSON_CreateString(g_mod.cfg.tls_cert_file));
+		if (g_mod.cfg.tls_key_file) cJSON_AddItemToObject(tls, "key_file", cJSON_CreateString(g_mod.cfg.tls_key_file));
+		if (g_mod.cfg.tls_server_name) cJSON_AddItemToObject(tls, "server_name", cJSON_CreateString(g_mod.cfg.tls_server_name));
+		cJSON_AddItemToObject(opts, "tls", tls);
+	}
+
+	out = cJSON_PrintUnformatted(opts);
+	cJSON_Delete(opts);
+	return out;
+}
+
 static void mod_surrealdb_try_connect(void)
 {
 	const char *url = g_mod.cfg.url;
@@ -178,15 +414,56 @@ static void mod_surrealdb_try_connect(void)
 	const char *user = g_mod.cfg.user;
 	const char *pass = g_mod.cfg.pass;
 	const char *token = g_mod.cfg.token;
-
-	if (!url || !ns || !db || (!token && !(user && pass))) {
+	char urlbuf[512];
+	switch_bool_t least_priv = !zstr(g_mod.cfg.auth_level) && strcasecmp(g_mod.cfg.auth_level, "root");
+	switch_bool_t record = least_priv && !strcasecmp(g_mod.cfg.auth_level, "record");
+	/* Without an inline token, the initial token comes from token-file (needs surreal_connect_opts) */
+	switch_bool_t use_opts = least_priv || (zstr(token) && !zstr(g_mod.cfg.token_file));
+
+	if (record && zstr(g_mod.cfg.auth_access)) {
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: auth-level=record needs auth-access. Skipping connect.\n", MOD_SURREALDB_NAME);
+		return;
+	}
+	if (!url || !ns || !db || (!record && !token && zstr(g_mod.cfg.token_file) && !(user && pass))) {
 		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING,
 			"%s: missing configuration; need url, namespace, database and (username+password) or token. Skipping connect.\n",
 			MOD_SURREALDB_NAME);
 		return;
 	}
 
-    if (token && *token) {
+	/* An explicit transport applies to URLs given as plain host:port */
+	if (!zstr(g_mod.cfg.transport) && !strstr(url, "://")) {
+		if (!strcasecmp(g_mod.cfg.transport, "http") || !strcasecmp(g_mod.cfg.transport, "https")
+			|| !strcasecmp(g_mod.cfg.transport, "ws") || !strcasecmp(g_mod.cfg.transport, "wss")) {
+			switch_snprintf(urlbuf, sizeof(urlbuf), "%s://%s", g_mod.cfg.transport, url);
+			url = urlbuf;
+		} else {
+			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: unknown transport '%s', using ws\n", MOD_SURREALDB_NAME, g_mod.cfg.transport);
+		}
+	}
+
+	/* Set before connecting so the connect itself is bounded too */
+	if (surreal_set_default_timeouts) {
+		surreal_set_default_timeouts(g_mod.cfg.connect_timeout_ms, g_mod.cfg.query_timeout_ms, g_mod.cfg.publish_timeout_ms);
+	}
+
+    if (use_opts) {
+        char *opts;
+        if (!surreal_connect_opts) {
+            switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: auth-level/token-file need a libsurrealdb_ffi with surreal_connect_opts\n", MOD_SURREALDB_NAME);
+            return;
+        }
+        opts = mod_surrealdb_connect_opts_json(url);
+        g_mod.handle = surreal_connect_opts(opts);
+        switch_safe_free(opts);
+    } else if (!zstr(g_mod.cfg.tls_ca_file) || !zstr(g_mod.cfg.tls_cert_file) || !zstr(g_mod.cfg.tls_server_name)) {
+        SurTlsOptions tls = { g_mod.cfg.tls_ca_file, g_mod.cfg.tls_cert_file, g_mod.cfg.tls_key_file, g_mod.cfg.tls_server_name };
+        if (!surreal_connect_tls) {
+            switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: tls-* params set but libsurrealdb_ffi has no TLS options support\n", MOD_SURREALDB_NAME);
+            return;
+        }
+        g_mod.handle = surreal_connect_tls(url, ns, db, user, pass, token, &tls);
+    } else if (token && *token) {
         g_mod.handle = surreal_connect_with_token(url, ns, db, token);
     } else {
         g_mod.handle = surreal_connect(url, ns, db, user, pass);
@@ -194,6 +471,35 @@ static void mod_surrealdb_try_connect(void)
 
     if (g_mod.handle) {
         switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_INFO, "%s: connected to SurrealDB.\n", MOD_SURREALDB_NAME);
+        if (surreal_set_state_callback) {
+            surreal_set_state_callback(g_mod.handle, mod_surrealdb_state_cb, NULL);
+        }
+        if (!zstr(g_mod.cfg.token_file) && surreal_set_token_file) {
+            surreal_set_token_file(g_mod.handle, g_mod.cfg.token_file);
+        }
+        if ((g_mod.cfg.reconnect_initial_ms || g_mod.cfg.reconnect_max_ms) && surreal_set_reconnect_backoff) {
+            surreal_set_reconnect_backoff(g_mod.handle, g_mod.cfg.reconnect_initial_ms, g_mod.cfg.reconnect_max_ms);
+        }
+        if (surreal_set_claim_options) {
+            surreal_set_claim_options(g_mod.handle, g_mod.cfg.node_id ? g_mod.cfg.node_id : switch_core_get_switchname(), g_mod.cfg.claim_lease_ms);
+        }
+        if ((g_mod.cfg.claim_reap_ms || g_mod.cfg.claim_max_attempts) && surreal_set_claim_recovery) {
+            surreal_set_claim_recovery(g_mod.handle, g_mod.cfg.claim_reap_ms, g_mod.cfg.claim_max_attempts);
+        }
+        if (g_mod.cfg.pool_max > 1 && surreal_pool_config) {
+            int prc = surreal_pool_config(g_mod.handle, g_mod.cfg.pool_min, g_mod.cfg.pool_max, g_mod.cfg.pool_health_ms);
+            switch_log_printf(SWITCH_CHANNEL_LOG, prc == 0 ? SWITCH_LOG_INFO : SWITCH_LOG_WARNING,
+                "%s: connection pool min=%u max=%u (rc=%d)\n", MOD_SURREALDB_NAME, g_mod.cfg.pool_min, g_mod.cfg.pool_max, prc);
+        }
+        if (!zstr(g_mod.cfg.spool_dir)) {
+            if (!surreal_spool_enable) {
+                switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: spool-dir set but libsurrealdb_ffi has no spool support\n", MOD_SURREALDB_NAME);
+            } else if (surreal_spool_enable(g_mod.handle, g_mod.cfg.spool_dir, (uint64_t)g_mod.cfg.spool_max_mb * 1024 * 1024, g_mod.cfg.spool_max_age_s) == 0) {
+                switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_INFO, "%s: spool enabled at %s\n", MOD_SURREALDB_NAME, g_mod.cfg.spool_dir);
+            } else {
+                switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: cannot enable spool at %s\n", MOD_SURREALDB_NAME, g_mod.cfg.spool_dir);
+            }
+        }
     } else {
         int32_t gcode = surreal_last_error_global();
         const char *why = "unknown";
@@ -204,8 +510,18 @@ static void mod_surrealdb_try_connect(void)
             case -103: why = "signin failed"; break;
             case -104: why = "token authenticate failed"; break;
             case -105: why = "use_ns/use_db failed"; break;
+            case -106: why = "tls configuration failed"; break;
+            case -107: why = "invalid connect options"; break;
+            case -108: why = "connect timed out"; break;
         }
         switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: SurrealDB connect failed (code %d: %s).\n", MOD_SURREALDB_NAME, (int)gcode, why);
+        if (surreal_last_error_json && surreal_free_string) {
+            char *detail = surreal_last_error_json(NULL);
+            if (detail) {
+                switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: connect error detail: %s\n", MOD_SURREALDB_NAME, detail);
+                surreal_free_string(detail);
+            }
+        }
     }
 }
 #endif
@@ -392,6 +708,46 @@ static void mod_surrealdb_evtmsg_destroy(mod_surrealdb_evtmsg_t **pmsg)
 	*pmsg = NULL;
 }
 
+/* Completion callback for async publishes; runs on an FFI runtime thread. */
+static void mod_surrealdb_publish_done(int32_t rc, const char *table, const char *json, const char *error, void *user_data)
+{
+	(void)json;
+	(void)user_data;
+	if (rc == 0 || rc == 1) return; /* 1: spooled for replay */
+	switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING,
+		"%s: async publish to %s failed rc=%d%s%s\n", MOD_SURREALDB_NAME, table ? table : "(null)", (int)rc,
+		zstr(error) ? "" : ": ", zstr(error) ? "" : error);
+}
+
+/* Hands a message to the FFI async queue, or to the local send queue when async mode is unavailable.
+ * Takes ownership of msg. Trips the circuit breaker when the queue is full. */
+static void mod_surrealdb_enqueue(mod_surrealdb_state_t *st, mod_surrealdb_evtmsg_t *msg, const char *what, switch_time_t now)
+{
+	if (st->async_publish) {
+		int32_t rc = surreal_publish_async(st->handle, msg->table, msg->json);
+		if (rc == -9) {
+			int64_t depth = surreal_publish_queue_depth ? surreal_publish_queue_depth(st->handle) : -1;
+			st->cb_reset_time = now + (switch_time_t)g_mod.cfg.circuit_breaker_ms * 1000;
+			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR,
+				"%s: %s FFI queue full (cap %u, depth %lld). Dropping for %.1fs\n",
+				MOD_SURREALDB_NAME, what, g_mod.cfg.send_queue_size, (long long)depth, g_mod.cfg.circuit_breaker_ms / 1000.0);
+		} else if (rc < 0) {
+			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: %s enqueue failed rc=%d\n", MOD_SURREALDB_NAME, what, (int)rc);
+		}
+		mod_surrealdb_evtmsg_destroy(&msg);
+		return;
+	}
+
+	if (switch_queue_trypush(st->send_queue, msg) != SWITCH_STATUS_SUCCESS) {
+		unsigned int qsz = switch_queue_size(st->send_queue);
+		st->cb_reset_time = now + (switch_time_t)g_mod.cfg.circuit_breaker_ms * 1000;
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR,
+			"%s: %s queue full (cap %u, size %u). Dropping for %.1fs\n",
+			MOD_SURREALDB_NAME, what, g_mod.cfg.send_queue_size, qsz, g_mod.cfg.circuit_breaker_ms / 1000.0);
+		mod_surrealdb_evtmsg_destroy(&msg);
+	}
+}
+
 static void mod_surrealdb_event_handler(switch_event_t *evt)
 {
 	mod_surrealdb_state_t *st = (mod_surrealdb_state_t *)evt->bind_user_data;
@@ -409,14 +765,7 @@ static void mod_surrealdb_event_handler(switch_event_t *evt)
 	msg->json = pjson;
 	msg->table = g_mod.cfg.event_table ? strdup(g_mod.cfg.event_table) : strdup("fs_events");
 
-	if (switch_queue_trypush(st->send_queue, msg) != SWITCH_STATUS_SUCCESS) {
-		unsigned int qsz = switch_queue_size(st->send_queue);
-		st->cb_reset_time = now + (switch_time_t)g_mod.cfg.circuit_breaker_ms * 1000;
-		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR,
-			"%s: event queue full (cap %u, size %u). Dropping events for %.1fs\n",
-			MOD_SURREALDB_NAME, g_mod.cfg.send_queue_size, qsz, g_mod.cfg.circuit_breaker_ms / 1000.0);
-		mod_surrealdb_evtmsg_destroy(&msg);
-	}
+	mod_surrealdb_enqueue(st, msg, "event", now);
 }
 
 static void mod_surrealdb_cdr_handler(switch_event_t *evt)
@@ -464,14 +813,7 @@ static void mod_surrealdb_cdr_handler(switch_event_t *evt)
 	msg->json = json;
 	msg->table = g_mod.cfg.cdr_table ? strdup(g_mod.cfg.cdr_table) : strdup("fs_cdr");
 
-	if (switch_queue_trypush(st->send_queue, msg) != SWITCH_STATUS_SUCCESS) {
-		unsigned int qsz = switch_queue_size(st->send_queue);
-		st->cb_reset_time = now + (switch_time_t)g_mod.cfg.circuit_breaker_ms * 1000;
-		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR,
-			"%s: CDR queue full (cap %u, size %u). Dropping for %.1fs\n",
-			MOD_SURREALDB_NAME, g_mod.cfg.send_queue_size, qsz, g_mod.cfg.circuit_breaker_ms / 1000.0);
-		mod_surrealdb_evtmsg_destroy(&msg);
-	}
+	mod_surrealdb_enqueue(st, msg, "CDR", now);
 }
 
 static void *SWITCH_THREAD_FUNC mod_surrealdb_event_thread(switch_thread_t *thread, void *obj)
@@ -487,7 +829,7 @@ static void *SWITCH_THREAD_FUNC mod_surrealdb_event_thread(switch_thread_t *thre
 		if (!msg) continue;
 		const char *table = msg->table ? msg->table : (g_mod.cfg.event_table ? g_mod.cfg.event_table : "fs_events");
 		int rc = surreal_publish(st->handle, table, msg->json);
-		if (rc != 0) {
+		if (rc < 0) {
 			char why[512] = {0};
 			int32_t n = surreal_last_error_text(st->handle, why, sizeof(why));
 			if (n < 0) why[0] = '\0';
@@ -514,21 +856,30 @@ static void mod_surrealdb_start_event_sink(switch_memory_pool_t *pool)
 		g_mod.cfg.enable_events = SWITCH_FALSE;
 	}
 
-	/* Create bounded queue */
-	if (switch_queue_create(&g_mod.send_queue, g_mod.cfg.send_queue_size, pool) != SWITCH_STATUS_SUCCESS) {
-		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: failed to create event queue size %u\n", MOD_SURREALDB_NAME, g_mod.cfg.send_queue_size);
-		return;
-	}
+	/* Prefer the FFI's own bounded queue (non-blocking, batched); fall back to a local queue + sender thread */
+	g_mod.async_publish = SWITCH_FALSE;
+	if (surreal_publish_async_start && surreal_publish_async &&
+		surreal_publish_async_start(g_mod.handle, g_mod.cfg.send_queue_size, 0, mod_surrealdb_publish_done, &g_mod) == 0) {
+		g_mod.async_publish = SWITCH_TRUE;
+		g_mod.events_running = SWITCH_TRUE;
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_INFO, "%s: using FFI async publish queue (cap %u)\n", MOD_SURREALDB_NAME, g_mod.cfg.send_queue_size);
+	} else {
+		/* Create bounded queue */
+		if (switch_queue_create(&g_mod.send_queue, g_mod.cfg.send_queue_size, pool) != SWITCH_STATUS_SUCCESS) {
+			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: failed to create event queue size %u\n", MOD_SURREALDB_NAME, g_mod.cfg.send_queue_size);
+			return;
+		}
 
-	/* Start worker thread */
-	switch_threadattr_t *thd_attr = NULL;
-	switch_threadattr_create(&thd_attr, pool);
-	switch_threadattr_stacksize_set(thd_attr, SWITCH_THREAD_STACKSIZE);
-	g_mod.events_running = SWITCH_TRUE;
-	if (switch_thread_create(&g_mod.event_thread, thd_attr, mod_surrealdb_event_thread, &g_mod, pool)) {
-		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: cannot create event sender thread\n", MOD_SURREALDB_NAME);
-		g_mod.events_running = SWITCH_FALSE;
-		return;
+		/* Start worker thread */
+		switch_threadattr_t *thd_attr = NULL;
+		switch_threadattr_create(&thd_attr, pool);
+		switch_threadattr_stacksize_set(thd_attr, SWITCH_THREAD_STACKSIZE);
+		g_mod.events_running = SWITCH_TRUE;
+		if (switch_thread_create(&g_mod.event_thread, thd_attr, mod_surrealdb_event_thread, &g_mod, pool)) {
+			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: cannot create event sender thread\n", MOD_SURREALDB_NAME);
+			g_mod.events_running = SWITCH_FALSE;
+			return;
+		}
 	}
 
 	/* Bind events */
@@ -606,6 +957,35 @@ static void mod_surrealdb_stop_event_sink(void)
 		g_mod.send_queue = NULL;
 	}
     }
+
+/* Subscribes to the command table, filtered server-side when command-filter is set */
+static int32_t mod_surrealdb_subscribe_commands(void)
+{
+	cJSON *vars;
+	char *vars_json;
+	int32_t rc;
+
+	if (!g_mod.cfg.command_filter) {
+		return surreal_subscribe(g_mod.handle, g_mod.cfg.command_table, on_command_cb, &g_mod);
+	}
+	if (!surreal_subscribe_where) {
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: surrealdb_ffi has no surreal_subscribe_where; ignoring command-filter\n", MOD_SURREALDB_NAME);
+		return surreal_subscribe(g_mod.handle, g_mod.cfg.command_table, on_command_cb, &g_mod);
+	}
+	vars = cJSON_CreateObject();
+	cJSON_AddStringToObject(vars, "hostname", switch_core_get_hostname());
+	cJSON_AddStringToObject(vars, "switchname", switch_core_get_switchname());
+	vars_json = cJSON_PrintUnformatted(vars);
+	cJSON_Delete(vars);
+	rc = surreal_subscribe_where(g_mod.handle, g_mod.cfg.command_table, g_mod.cfg.command_filter, vars_json, on_command_cb, &g_mod);
+	switch_safe_free(vars_json);
+	if (rc != 0) {
+		char reason[256] = "";
+		if (surreal_last_error_text) surreal_last_error_text(g_mod.handle, reason, sizeof(reason));
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: command-filter rejected (%d): %s\n", MOD_SURREALDB_NAME, rc, reason);
+	}
+	return rc;
+}
 #endif /* HAVE_SURREALDB_FFI */
 
 SWITCH_MODULE_LOAD_FUNCTION(mod_surrealdb_load)
@@ -617,11 +997,21 @@ SWITCH_MODULE_LOAD_FUNCTION(mod_surrealdb_load)
 	mod_surrealdb_read_config(pool);
 	mod_surrealdb_log_cfg();
 
+	if (switch_event_reserve_subclass(MOD_SURREALDB_STATE_EVENT) != SWITCH_STATUS_SUCCESS) {
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: could not reserve event subclass %s\n", MOD_SURREALDB_NAME, MOD_SURREALDB_STATE_EVENT);
+	}
+
 #ifdef HAVE_SURREALDB_FFI
-	if (surreal_init_runtime() != 0) {
+	if ((surreal_init_runtime_ex ? surreal_init_runtime_ex(g_mod.cfg.runtime_threads, "surrealdb") : surreal_init_runtime()) != 0) {
 		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_ERROR, "%s: runtime init failed.\n", MOD_SURREALDB_NAME);
 		return SWITCH_STATUS_FALSE;
 	}
+	/* Wire FFI logging into FreeSWITCH logs before connecting, so connect failures show up */
+	if (surreal_set_log_handler) {
+		surreal_set_log_handler(surreal_ffi_log_handler_cb, g_mod.cfg.log_level, NULL);
+	} else {
+		surreal_set_logger(surreal_ffi_log_cb, NULL);
+	}
 	if (g_mod.cfg.connect_on_load) {
 		mod_surrealdb_try_connect();
 	}
@@ -638,11 +1028,8 @@ SWITCH_MODULE_LOAD_FUNCTION(mod_surrealdb_load)
 	}
 
 #ifdef HAVE_SURREALDB_FFI
-    /* Wire FFI logger into FreeSWITCH logs */
-    surreal_set_logger(surreal_ffi_log_cb, NULL);
-
     if (g_mod.cfg.enable_commands && g_mod.cfg.command_table && g_mod.handle) {
-		if (surreal_subscribe(g_mod.handle, g_mod.cfg.command_table, on_command_cb, &g_mod) == 0) {
+		if (mod_surrealdb_subscribe_commands() == 0) {
 			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_INFO, "%s: subscribed to command table %s\n", MOD_SURREALDB_NAME, g_mod.cfg.command_table);
 		} else {
 			switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: failed to subscribe to command table %s\n", MOD_SURREALDB_NAME, g_mod.cfg.command_table);
@@ -664,6 +1051,9 @@ SWITCH_MODULE_LOAD_FUNCTION(mod_surrealdb_load)
 	SWITCH_ADD_API(api_interface, "surrealdb.select", "Select rows from a table", mod_surrealdb_select_api, "<table> [limit]");
 	SWITCH_ADD_API(api_interface, "surrealdb.get", "Get a single row by id", mod_surrealdb_get_api, "<table> <id>");
 	SWITCH_ADD_API(api_interface, "surrealdb.update", "Update a record by id with JSON patch", mod_surrealdb_update_api, "<table> <id> <json>");
+	SWITCH_ADD_API(api_interface, "surrealdb.query", "Run SurrealQL with bound variables", mod_surrealdb_query_api, "[vars_json] <sql>");
+	SWITCH_ADD_API(api_interface, "surrealdb.pool", "Show FFI connection pool statistics", mod_surrealdb_pool_api, "");
+	SWITCH_ADD_API(api_interface, "surrealdb.stats", "Show FFI metrics", mod_surrealdb_stats_api, "[json|prometheus]");
 
 	return SWITCH_STATUS_SUCCESS;
 }
@@ -677,7 +1067,15 @@ SWITCH_MODULE_SHUTDOWN_FUNCTION(mod_surrealdb_shutdown)
 		surreal_close(g_mod.handle);
 		g_mod.handle = NULL;
 	}
+	/* Stop the FFI runtime threads so a reload starts clean */
+	if (surreal_shutdown_runtime && surreal_shutdown_runtime(5000) != 0) {
+		switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: FFI runtime still busy at shutdown deadline\n", MOD_SURREALDB_NAME);
+	}
+	/* The log callbacks point into this module; drop them before the module is unmapped */
+	if (surreal_set_log_handler) surreal_set_log_handler(NULL, 0, NULL);
+	surreal_set_logger(NULL, NULL);
 #endif
+	switch_event_free_subclass(MOD_SURREALDB_STATE_EVENT);
 	return SWITCH_STATUS_SUCCESS;
 }
 
@@ -792,28 +1190,24 @@ SWITCH_STANDARD_API(mod_surrealdb_select_api)
 		stream->write_function(stream, "-ERR not connected\n");
 		return SWITCH_STATUS_SUCCESS;
 	}
-	/* Allocate output buffer */
-	uint32_t out_len = 65536; /* 64KB cap */
-	char *out = (char *)malloc(out_len);
-	if (!out) {
-		free(args_copy);
-		stream->write_function(stream, "-ERR memory error\n");
-		return SWITCH_STATUS_SUCCESS;
-	}
-	int rc = surreal_select(g_mod.handle, table, limit, out, out_len);
-	if (rc == 0) {
-		stream->write_function(stream, "%s\n", out);
+	/* Prefer a library-allocated result so large outputs are never cut */
+	switch_bool_t owned = (surreal_select_alloc && surreal_free_string) ? SWITCH_TRUE : SWITCH_FALSE;
+	char *out = NULL;
+	int rc;
+	if (owned) {
+		rc = surreal_select_alloc(g_mod.handle, table, limit, &out);
 	} else {
-		char errtxt[512] = {0};
-		int32_t n = surreal_last_error_text(g_mod.handle, errtxt, sizeof(errtxt));
-		if (n < 0) errtxt[0] = '\0';
-		if (!zstr(errtxt)) {
-			stream->write_function(stream, "-ERR select failed (%d: %s)\n", rc, errtxt);
-		} else {
-			stream->write_function(stream, "-ERR select failed (%d)\n", rc);
+		uint32_t out_len = 65536; /* 64KB cap */
+		out = (char *)malloc(out_len);
+		if (!out) {
+			free(args_copy);
+			stream->write_function(stream, "-ERR memory error\n");
+			return SWITCH_STATUS_SUCCESS;
 		}
+		rc = surreal_select(g_mod.handle, table, limit, out, out_len);
 	}
-	free(out);
+	mod_surrealdb_print_result(stream, "select", rc, out);
+	if (owned) surreal_free_string(out); else free(out);
 #else
 	stream->write_function(stream, "-ERR built without FFI (no-op)\n");
 #endif
@@ -861,27 +1255,24 @@ SWITCH_STANDARD_API(mod_surrealdb_get_api)
 		stream->write_function(stream, "-ERR not connected\n");
 		return SWITCH_STATUS_SUCCESS;
 	}
-	uint32_t out_len = 65536;
-	char *out = (char *)malloc(out_len);
-	if (!out) {
-		free(args_copy);
-		stream->write_function(stream, "-ERR memory error\n");
-		return SWITCH_STATUS_SUCCESS;
-	}
-	int rc = surreal_get(g_mod.handle, table, id, out, out_len);
-	if (rc == 0) {
-		stream->write_function(stream, "%s\n", out);
+	/* Prefer a library-allocated result so large outputs are never cut */
+	switch_bool_t owned = (surreal_get_alloc && surreal_free_string) ? SWITCH_TRUE : SWITCH_FALSE;
+	char *out = NULL;
+	int rc;
+	if (owned) {
+		rc = surreal_get_alloc(g_mod.handle, table, id, &out);
 	} else {
-		char errtxt[512] = {0};
-		int32_t n = surreal_last_error_text(g_mod.handle, errtxt, sizeof(errtxt));
-		if (n < 0) errtxt[0] = '\0';
-		if (!zstr(errtxt)) {
-			stream->write_function(stream, "-ERR get failed (%d: %s)\n", rc, errtxt);
-		} else {
-			stream->write_function(stream, "-ERR get failed (%d)\n", rc);
+		uint32_t out_len = 65536;
+		out = (char *)malloc(out_len);
+		if (!out) {
+			free(args_copy);
+			stream->write_function(stream, "-ERR memory error\n");
+			return SWITCH_STATUS_SUCCESS;
 		}
+		rc = surreal_get(g_mod.handle, table, id, out, out_len);
 	}
-	free(out);
+	mod_surrealdb_print_result(stream, "get", rc, out);
+	if (owned) surreal_free_string(out); else free(out);
 #else
 	stream->write_function(stream, "-ERR built without FFI (no-op)\n");
 #endif
@@ -896,6 +1287,48 @@ static char *mod_surrealdb_ltrim(char *s)
 	return s;
 }
 
+#ifdef HAVE_SURREALDB_FFI
+/* Copies the category name of the last failure ("auth", "query", ...) into buf; empty with older FFI builds */
+static void mod_surrealdb_error_kind(SurHandle *handle, char *buf, size_t len)
+{
+	char *detail;
+	cJSON *json;
+	cJSON *name;
+
+	buf[0] = '\0';
+	if (!surreal_last_error_json || !surreal_free_string) return;
+	if (!(detail = surreal_last_error_json(handle))) return;
+	if ((json = cJSON_Parse(detail))) {
+		name = cJSON_GetObjectItem(json, "name");
+		if (name && name->type == cJSON_String && name->valuestring) {
+			switch_copy_string(buf, name->valuestring, len);
+		}
+		cJSON_Delete(json);
+	}
+	surreal_free_string(detail);
+}
+
+/* Prints a read result, or the FFI error text when rc != 0 (-20 means the buffer was too small) */
+static void mod_surrealdb_print_result(switch_stream_handle_t *stream, const char *what, int rc, const char *out)
+{
+	if (rc == 0) {
+		stream->write_function(stream, "%s\n", out ? out : "null");
+		return;
+	}
+	char errtxt[512] = {0};
+	char kind[32];
+	int32_t n = surreal_last_error_text ? surreal_last_error_text(g_mod.handle, errtxt, sizeof(errtxt)) : -1;
+	if (n < 0) errtxt[0] = '\0';
+	mod_surrealdb_error_kind(g_mod.handle, kind, sizeof(kind));
+	if (zstr(kind)) switch_copy_string(kind, "error", sizeof(kind));
+	if (!zstr(errtxt)) {
+		stream->write_function(stream, "-ERR %s failed (%d %s: %s)\n", what, rc, kind, errtxt);
+	} else {
+		stream->write_function(stream, "-ERR %s failed (%d %s)\n", what, rc, kind);
+	}
+}
+#endif
+
 SWITCH_STANDARD_API(mod_surrealdb_publish_api)
 {
 	char *args_copy = NULL;
@@ -938,6 +1371,8 @@ SWITCH_STANDARD_API(mod_surrealdb_publish_api)
     int rc = surreal_publish(g_mod.handle, table, json);
     if (rc == 0) {
         stream->write_function(stream, "+OK published\n");
+    } else if (rc == 1) {
+        stream->write_function(stream, "+OK spooled\n");
     } else {
         int32_t hcode = surreal_last_error_code(g_mod.handle);
         const char *why = "unknown";
@@ -948,10 +1383,14 @@ SWITCH_STANDARD_API(mod_surrealdb_publish_api)
             case -4: why = "invalid json ptr"; break;
             case -5: why = "json parse failed"; break;
             case -6: why = "insert failed"; break;
+            case -21: why = "timed out"; break;
         }
         char errtxt[512] = {0};
+        char kind[32];
         int32_t n = surreal_last_error_text(g_mod.handle, errtxt, sizeof(errtxt));
         if (n < 0) errtxt[0] = '\0';
+        mod_surrealdb_error_kind(g_mod.handle, kind, sizeof(kind));
+        if (!zstr(kind)) why = kind;
         if (!zstr(errtxt)) {
             switch_log_printf(SWITCH_CHANNEL_LOG, SWITCH_LOG_WARNING, "%s: publish failed table=%s rc=%d last=%d (%s): %s\n", MOD_SURREALDB_NAME, table, rc, (int)hcode, why, errtxt);
             stream->write_function(stream, "-ERR publish failed (%d: %s: %s)\n", rc, why, errtxt);
@@ -967,3 +1406,132 @@ SWITCH_STANDARD_API(mod_surrealdb_publish_api)
 	free(args_copy);
 	return SWITCH_STATUS_SUCCESS;
 }
+
+SWITCH_STANDARD_API(mod_surrealdb_query_api)
+{
+	char *args_copy = NULL;
+	char *sql = NULL;
+	char *vars = NULL;
+
+	if (zstr(cmd)) {
+		stream->write_function(stream, "-ERR Usage: surrealdb.query [vars_json] <sql>\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+
+	args_copy = strdup(cmd);
+	if (!args_copy) {
+		stream->write_function(stream, "-ERR memory error\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+
+	sql = mod_surrealdb_ltrim(args_copy);
+	if (*sql == '{') {
+		/* Leading JSON object holds the bound variables; the rest is SurrealQL */
+		const char *end = NULL;
+		cJSON *parsed = cJSON_ParseWithOpts(sql, &end, 0);
+		if (!parsed || !cJSON_IsObject(parsed) || !end) {
+			if (parsed) cJSON_Delete(parsed);
+			free(args_copy);
+			stream->write_function(stream, "-ERR vars must be a JSON object\n");
+			return SWITCH_STATUS_SUCCESS;
+		}
+		cJSON_Delete(parsed);
+		vars = sql;
+		sql = (char *)end;
+		if (*sql) *sql++ = '\0';
+		sql = mod_surrealdb_ltrim(sql);
+	}
+
+	if (zstr(sql)) {
+		free(args_copy);
+		stream->write_function(stream, "-ERR missing sql\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+
+#ifdef HAVE_SURREALDB_FFI
+	if (!surreal_query) {
+		free(args_copy);
+		stream->write_function(stream, "-ERR FFI library has no query support\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	if (!g_mod.handle) {
+		free(args_copy);
+		stream->write_function(stream, "-ERR not connected\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	/* Prefer a library-allocated result so large outputs are never cut */
+	switch_bool_t owned = (surreal_query_alloc && surreal_free_string) ? SWITCH_TRUE : SWITCH_FALSE;
+	char *out = NULL;
+	int rc;
+	if (owned) {
+		rc = surreal_query_alloc(g_mod.handle, sql, vars, &out);
+	} else {
+		uint32_t out_len = 65536;
+		out = (char *)malloc(out_len);
+		if (!out) {
+			free(args_copy);
+			stream->write_function(stream, "-ERR memory error\n");
+			return SWITCH_STATUS_SUCCESS;
+		}
+		rc = surreal_query(g_mod.handle, sql, vars, out, out_len);
+	}
+	mod_surrealdb_print_result(stream, "query", rc, out);
+	if (owned) surreal_free_string(out); else free(out);
+#else
+	stream->write_function(stream, "-ERR built without FFI (no-op)\n");
+#endif
+
+	free(args_copy);
+	return SWITCH_STATUS_SUCCESS;
+}
+
+SWITCH_STANDARD_API(mod_surrealdb_pool_api)
+{
+#ifdef HAVE_SURREALDB_FFI
+	char out[1024] = {0};
+	if (!surreal_pool_stats_json) {
+		stream->write_function(stream, "-ERR FFI library has no pool support\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	if (!g_mod.handle) {
+		stream->write_function(stream, "-ERR not connected\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	mod_surrealdb_print_result(stream, "pool", surreal_pool_stats_json(g_mod.handle, out, sizeof(out)), out);
+#else
+	stream->write_function(stream, "-ERR built without FFI (no-op)\n");
+#endif
+	return SWITCH_STATUS_SUCCESS;
+}
+
+SWITCH_STANDARD_API(mod_surrealdb_stats_api)
+{
+#ifdef HAVE_SURREALDB_FFI
+	char *out = NULL;
+	switch_bool_t prometheus = !zstr(cmd) && !strcasecmp(cmd, "prometheus");
+
+	if (!zstr(cmd) && !prometheus && strcasecmp(cmd, "json")) {
+		stream->write_function(stream, "-ERR Usage: surrealdb.stats [json|prometheus]\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	if (!surreal_stats_json || !surreal_stats_prometheus || !surreal_free_string) {
+		stream->write_function(stream, "-ERR FFI library has no metrics support\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	if (!g_mod.handle) {
+		stream->write_function(stream, "-ERR not connected\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	out = prometheus ? surreal_stats_prometheus(g_mod.handle) : surreal_stats_json(g_mod.handle);
+	if (!out) {
+		stream->write_function(stream, "-ERR stats unavailable\n");
+		return SWITCH_STATUS_SUCCESS;
+	}
+	/* Prometheus text already ends with a newline */
+	stream->write_function(stream, prometheus ? "%s" : "%s\n", out);
+	surreal_free_string(out);
+#else
+	stream->write_function(stream, "-ERR built without FFI (no-op)\n");
+#endif
+	return SWITCH_STATUS_SUCCESS;
+}
@@ -0,0 +1,49 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Local TLS stand-in for testing wss:// connections.
+# Creates a throwaway CA plus server and client certificates, then terminates TLS
+# (requiring a client certificate) in front of a plain SurrealDB.
+# Usage: scripts/tls-standin.sh [listen_port] [backend_host:port]
+# Env: TLS_DIR (default /tmp/surreal-tls), TLS_CN (server name, default surreal.test)
+
+LISTEN_PORT=${1:-8443}
+BACKEND=${2:-127.0.0.1:8000}
+TLS_DIR=${TLS_DIR:-/tmp/surreal-tls}
+TLS_CN=${TLS_CN:-surreal.test}
+
+for bin in openssl socat; do
+  if ! command -v "$bin" >/dev/null 2>&1; then
+    echo "ERROR: $bin not found in PATH" >&2
+    exit 2
+  fi
+done
+
+mkdir -p "$TLS_DIR"
+cd "$TLS_DIR"
+
+if [[ ! -f ca.pem ]]; then
+  openssl req -x509 -newkey rsa:2048 -nodes -days 30 -subj "/CN=mod_surrealdb test CA" \
+    -keyout ca.key -out ca.pem 2>/dev/null
+
+  printf 'subjectAltName=DNS:%s,DNS:localhost,IP:127.0.0.1\n' "$TLS_CN" > server.ext
+  openssl req -newkey rsa:2048 -nodes -subj "/CN=$TLS_CN" -keyout server.key -out server.csr 2>/dev/null
+  openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial -days 30 \
+    -extfile server.ext -out server.pem 2>/dev/null
+
+  printf 'extendedKeyUsage=clientAuth\n' > client.ext
+  openssl req -newkey rsa:2048 -nodes -subj "/CN=freeswitch" -keyout client.key -out client.csr 2>/dev/null
+  openssl x509 -req -in client.csr -CA ca.pem -CAkey ca.key -CAcreateserial -days 30 \
+    -extfile client.ext -out client.pem 2>/dev/null
+fi
+
+echo "Certificates in $TLS_DIR (server name: $TLS_CN)"
+echo "Module config:"
+echo "  <param name=\"url\" value=\"wss://127.0.0.1:$LISTEN_PORT\"/>"
+echo "  <param name=\"tls-ca-file\" value=\"$TLS_DIR/ca.pem\"/>"
+echo "  <param name=\"tls-cert-file\" value=\"$TLS_DIR/client.pem\"/>"
+echo "  <param name=\"tls-key-file\" value=\"$TLS_DIR/client.key\"/>"
+echo "  <param name=\"tls-server-name\" value=\"$TLS_CN\"/>"
+echo "Listening on :$LISTEN_PORT -> $BACKEND (Ctrl-C to stop)"
+
+exec socat "OPENSSL-LISTEN:$LISTEN_PORT,reuseaddr,fork,cert=server.pem,key=server.key,cafile=ca.pem,verify=1" "TCP:$BACKEND"
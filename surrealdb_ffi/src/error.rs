@@ -0,0 +1,239 @@
+//! Error categories shared by the stub and real builds.
+//!
+//! Older functions keep returning their historical codes, which are only meaningful per
+//! function (-3 is a bad table in one call and a bad id in another); newer ones return the
+//! category code itself. The publish family shares one documented set, see `from_op_code`. Every recorded failure is also classified into one `SurError`
+//! category, reported by `surreal_last_error_json` and named by `surreal_error_name`.
+//! Category codes live in their own range so they never collide with a historical code.
+
+use std::os::raw::{c_char, c_int};
+
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurError {
+    /// NULL or malformed argument: table name, record id, options.
+    InvalidArgument = -301,
+    /// No usable connection (server down, reconnect pending).
+    NotConnected = -302,
+    /// Signin, signup or token rejected.
+    Auth = -303,
+    /// The server rejected a statement.
+    Query = -304,
+    /// The operation did not finish in time.
+    Timeout = -305,
+    /// The result did not fit the caller's buffer.
+    Truncated = -306,
+    /// A JSON document or variable set did not parse.
+    Json = -307,
+    /// The durable spool could not be opened or written.
+    Spool = -308,
+    /// The async publish queue is full.
+    QueueFull = -309,
+    /// The async runtime is missing or could not start.
+    Runtime = -310,
+    /// TLS files or settings could not be loaded.
+    Tls = -311,
+    /// Not available with this build or transport.
+    Unsupported = -312,
+    /// Poisoned lock or other internal failure.
+    Internal = -313,
+}
+
+impl SurError {
+    pub(crate) const ALL: [SurError; 13] = [
+        SurError::InvalidArgument, SurError::NotConnected, SurError::Auth, SurError::Query,
+        SurError::Timeout, SurError::Truncated, SurError::Json, SurError::Spool,
+        SurError::QueueFull, SurError::Runtime, SurError::Tls, SurError::Unsupported,
+        SurError::Internal,
+    ];
+
+    pub fn code(self) -> c_int { self as c_int }
+
+    pub fn name(self) -> &'static str {
+        let n = self.name_nul();
+        &n[..n.len() - 1]
+    }
+
+    fn name_nul(self) -> &'static str {
+        match self {
+            SurError::InvalidArgument => "invalid_argument\0",
+            SurError::NotConnected => "not_connected\0",
+            SurError::Auth => "auth\0",
+            SurError::Query => "query\0",
+            SurError::Timeout => "timeout\0",
+            SurError::Truncated => "truncated\0",
+            SurError::Json => "json\0",
+            SurError::Spool => "spool\0",
+            SurError::QueueFull => "queue_full\0",
+            SurError::Runtime => "runtime\0",
+            SurError::Tls => "tls\0",
+            SurError::Unsupported => "unsupported\0",
+            SurError::Internal => "internal\0",
+        }
+    }
+
+    /// Classifies a code: category codes map to themselves, and the codes that mean the
+    /// same thing everywhere (-20, -21 and the connect codes -100..-108) to their category.
+    /// Per-function return codes (-1..-19) are ambiguous and yield None.
+    pub fn from_code(code: c_int) -> Option<SurError> {
+        if let Some(e) = SurError::ALL.iter().find(|e| e.code() == code) { return Some(*e); }
+        match code {
+            -20 => Some(SurError::Truncated),
+            -21 | -108 => Some(SurError::Timeout),
+            -100 | -101 => Some(SurError::Runtime),
+            -102 => Some(SurError::NotConnected),
+            -105..=-103 => Some(SurError::Auth),
+            -106 => Some(SurError::Tls),
+            -107 => Some(SurError::InvalidArgument),
+            _ => None,
+        }
+    }
+
+    /// Classifies a code returned by `op`. The publish family (`publish`, `publish_batch`,
+    /// `publish_async`, `publish_async_start` and the async callback) shares one set of
+    /// codes; everything else falls back to `from_code`.
+    pub fn from_op_code(op: &str, code: c_int) -> Option<SurError> {
+        if !op.starts_with("publish") { return SurError::from_code(code); }
+        match code {
+            -1 | -3 | -4 | -8 => Some(SurError::InvalidArgument),
+            -2 => Some(SurError::NotConnected),
+            -5 => Some(SurError::Json),
+            -6 | -7 => Some(SurError::Query),
+            -9 => Some(SurError::QueueFull),
+            -10 => Some(SurError::Spool),
+            _ => SurError::from_code(code),
+        }
+    }
+}
+
+/// Renders one failure for `surreal_last_error_json`. `kind` falls back to classifying `code`.
+pub fn detail_json(code: c_int, kind: Option<SurError>, op: &str, message: &str, statement: Option<&str>) -> String {
+    let kind = kind.or_else(|| SurError::from_op_code(op, code));
+    let (kind_code, name) = match (code, kind) {
+        (0, _) => (0, "ok"),
+        (_, Some(k)) => (k.code(), k.name()),
+        (_, None) => (0, "unknown"),
+    };
+    let mut out = format!("{{\"code\":{},\"kind\":{},\"name\":\"{}\",\"op\":{},\"message\":{}", code, kind_code, name, json_str(op), json_str(message));
+    out.push_str(",\"statement\":");
+    match statement {
+        Some(s) => out.push_str(&json_str(s)),
+        None => out.push_str("null"),
+    }
+    out.push('}');
+    out
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Returns a static name for a category code or an unambiguous legacy code:
+/// "ok" for 0, "unknown" for anything else.
+#[no_mangle]
+pub extern "C" fn surreal_error_name(code: c_int) -> *const c_char {
+    let name = match (code, SurError::from_code(code)) {
+        (0, _) => "ok\0",
+        (_, Some(k)) => k.name_nul(),
+        (_, None) => "unknown\0",
+    };
+    name.as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn category_codes_map_to_themselves() {
+        for e in SurError::ALL {
+            assert_eq!(SurError::from_code(e.code()), Some(e));
+        }
+    }
+
+    #[test]
+    fn legacy_codes_are_classified() {
+        assert_eq!(SurError::from_code(-20), Some(SurError::Truncated));
+        assert_eq!(SurError::from_code(-21), Some(SurError::Timeout));
+        assert_eq!(SurError::from_code(-108), Some(SurError::Timeout));
+        assert_eq!(SurError::from_code(-100), Some(SurError::Runtime));
+        assert_eq!(SurError::from_code(-102), Some(SurError::NotConnected));
+        assert_eq!(SurError::from_code(-103), Some(SurError::Auth));
+        assert_eq!(SurError::from_code(-105), Some(SurError::Auth));
+        assert_eq!(SurError::from_code(-106), Some(SurError::Tls));
+        assert_eq!(SurError::from_code(-107), Some(SurError::InvalidArgument));
+    }
+
+    #[test]
+    fn publish_codes_are_classified() {
+        for op in ["publish", "publish_batch", "publish_async", "publish_async_start"] {
+            assert_eq!(SurError::from_op_code(op, -3), Some(SurError::InvalidArgument));
+            assert_eq!(SurError::from_op_code(op, -2), Some(SurError::NotConnected));
+            assert_eq!(SurError::from_op_code(op, -5), Some(SurError::Json));
+            assert_eq!(SurError::from_op_code(op, -7), Some(SurError::Query));
+            assert_eq!(SurError::from_op_code(op, -9), Some(SurError::QueueFull));
+            assert_eq!(SurError::from_op_code(op, -10), Some(SurError::Spool));
+            assert_eq!(SurError::from_op_code(op, -21), Some(SurError::Timeout));
+            assert_eq!(SurError::from_op_code(op, -101), Some(SurError::Runtime));
+        }
+        assert_eq!(SurError::from_op_code("get", -3), None);
+        assert!(detail_json(-10, None, "publish", "disk full", None).contains("\"name\":\"spool\""));
+    }
+
+    #[test]
+    fn per_function_codes_are_ambiguous() {
+        for code in [0, -1, -3, -19, -22, -109, -300, -314, 1] {
+            assert_eq!(SurError::from_code(code), None, "code {}", code);
+        }
+    }
+
+    #[test]
+    fn error_name_is_nul_terminated() {
+        let name = |code| unsafe { CStr::from_ptr(surreal_error_name(code)) }.to_str().unwrap();
+        assert_eq!(name(0), "ok");
+        assert_eq!(name(-305), "timeout");
+        assert_eq!(name(-21), "timeout");
+        assert_eq!(name(-3), "unknown");
+        assert_eq!(SurError::QueueFull.name(), "queue_full");
+    }
+
+    #[test]
+    fn detail_json_falls_back_to_code() {
+        assert_eq!(
+            detail_json(-21, None, "query", "deadline", Some("SELECT 1")),
+            r#"{"code":-21,"kind":-305,"name":"timeout","op":"query","message":"deadline","statement":"SELECT 1"}"#);
+        assert_eq!(
+            detail_json(-3, Some(SurError::InvalidArgument), "create", "bad id", None),
+            r#"{"code":-3,"kind":-301,"name":"invalid_argument","op":"create","message":"bad id","statement":null}"#);
+        assert_eq!(
+            detail_json(-3, None, "create", "", None),
+            r#"{"code":-3,"kind":0,"name":"unknown","op":"create","message":"","statement":null}"#);
+        assert_eq!(
+            detail_json(0, Some(SurError::Query), "", "", None),
+            r#"{"code":0,"kind":0,"name":"ok","op":"","message":"","statement":null}"#);
+    }
+
+    #[test]
+    fn json_str_escapes() {
+        assert_eq!(json_str("plain"), r#""plain""#);
+        assert_eq!(json_str("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(json_str("l1\nl2\r\tx"), r#""l1\nl2\r\tx""#);
+        assert_eq!(json_str("\u{1}\u{1f}"), r#""\u0001\u001f""#);
+        assert_eq!(json_str("é ✓"), "\"é ✓\"");
+    }
+}
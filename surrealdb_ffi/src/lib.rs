@@ -1,17 +1,32 @@
 //! SurrealDB FFI shim for C callers (FreeSWITCH module).
-//! Default build is a stub for offline development; enable `real` feature for client wiring.
+//! Default build is a stub for offline development; enable `real` feature for client wiring,
+//! or `embedded` to also run an in-memory SurrealDB inside the library (`mem://` URLs).
+
+mod error;
+#[cfg(feature = "real")]
+mod logging;
+#[cfg(feature = "real")]
+mod metrics;
+#[cfg(feature = "real")]
+mod spool;
 
 #[cfg(feature = "stub")]
 mod api {
-    use std::ffi::CStr;
+    use std::ffi::{CStr, CString};
     use std::os::raw::{c_char, c_int, c_void};
     use std::sync::atomic::{AtomicI32, Ordering};
     use std::sync::{Mutex, OnceLock};
     use std::net::TcpStream;
     use std::time::Duration;
+    use crate::error::SurError;
 
     pub type SurCommandCb = Option<extern "C" fn(topic: *const c_char, json: *const c_char, user_data: *mut c_void)>;
     pub type SurLogCb = Option<extern "C" fn(msg: *const c_char, user_data: *mut c_void)>;
+    pub type SurChangeCb = Option<extern "C" fn(topic: *const c_char, action: c_int, json: *const c_char, user_data: *mut c_void)>;
+    pub type SurLogHandlerCb = Option<extern "C" fn(level: c_int, target: *const c_char, msg: *const c_char, fields_json: *const c_char, user_data: *mut c_void)>;
+    pub type SurPublishCb = Option<extern "C" fn(rc: c_int, table: *const c_char, json: *const c_char, error: *const c_char, user_data: *mut c_void)>;
+    pub type SurStateCb = Option<extern "C" fn(state: c_int, detail: *const c_char, user_data: *mut c_void)>;
+    pub type SurTokenProviderCb = Option<extern "C" fn(out: *mut c_char, out_len: u32, user_data: *mut c_void) -> c_int>;
 
     #[repr(C)]
     pub struct SurHandle {
@@ -71,6 +86,12 @@ mod api {
     #[no_mangle]
     pub extern "C" fn surreal_init_runtime() -> c_int { 0 }
 
+    #[no_mangle]
+    pub extern "C" fn surreal_init_runtime_ex(_worker_threads: u32, _thread_name_prefix: *const c_char) -> c_int { 0 }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_shutdown_runtime(_timeout_ms: u32) -> c_int { 0 }
+
     #[no_mangle]
     #[no_mangle]
     #[no_mangle]
@@ -95,16 +116,128 @@ mod api {
         Box::into_raw(handle)
     }
 
+    /// TLS settings passed from C; every field may be NULL.
+    #[repr(C)]
+    pub struct SurTlsOptions {
+        pub ca_file: *const c_char,
+        pub cert_file: *const c_char,
+        pub key_file: *const c_char,
+        pub server_name: *const c_char,
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connect_tls(url: *const c_char, ns: *const c_char, db: *const c_char, user: *const c_char, pass: *const c_char, token: *const c_char, tls: *const SurTlsOptions) -> *mut SurHandle {
+        let url = match cstr_to_str(url) { Some(s) => s, None => return std::ptr::null_mut() };
+        let _ = (cstr_to_str(ns), cstr_to_str(db), cstr_to_str(user), cstr_to_str(pass), cstr_to_str(token));
+        if !tls.is_null() {
+            // Stub: only check that the referenced PEM files are readable
+            let o = unsafe { &*tls };
+            for f in [o.ca_file, o.cert_file, o.key_file] {
+                if let Some(path) = cstr_to_str(f).filter(|p| !p.is_empty()) {
+                    if std::fs::metadata(path).is_err() { return std::ptr::null_mut(); }
+                }
+            }
+        }
+        if !tcp_probe(url) {
+            return std::ptr::null_mut();
+        }
+        let handle = Box::new(SurHandle { is_connected: true, last_error_code: AtomicI32::new(0), callback: Mutex::new(None), last_error_msg: Mutex::new(String::new()) });
+        Box::into_raw(handle)
+    }
+
+    /// Naive lookup of a top-level string field; enough for the stub's connect probe.
+    fn json_str_field(json: &str, key: &str) -> Option<String> {
+        let pat = format!("\"{}\"", key);
+        let rest = json[json.find(&pat)? + pat.len()..].trim_start().strip_prefix(':')?.trim_start();
+        let rest = rest.strip_prefix('"')?;
+        Some(rest[..rest.find('"')?].to_string())
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connect_opts(json_options: *const c_char) -> *mut SurHandle {
+        let json = match cstr_to_str(json_options) { Some(s) => s, None => return std::ptr::null_mut() };
+        let url = match json_str_field(json, "url") { Some(u) => u, None => return std::ptr::null_mut() };
+        if !tcp_probe(&url) {
+            return std::ptr::null_mut();
+        }
+        let handle = Box::new(SurHandle { is_connected: true, last_error_code: AtomicI32::new(0), callback: Mutex::new(None), last_error_msg: Mutex::new(String::new()) });
+        Box::into_raw(handle)
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_publish(handle: *mut SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
         if handle.is_null() { return -1; }
         let h = unsafe { &*handle };
         if !h.is_connected { return -2; }
-        let _ = (cstr_to_str(table_or_topic), cstr_to_str(json_payload));
+        if cstr_to_str(table_or_topic).is_none() { return -3; }
+        if cstr_to_str(json_payload).is_none() { return -4; }
         if let Ok(mut msg) = h.last_error_msg.lock() { msg.clear(); }
         0
     }
 
+    #[no_mangle]
+    pub extern "C" fn surreal_publish_batch(handle: *mut SurHandle, table: *const c_char, json_array: *const c_char) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        if !h.is_connected { return -2; }
+        if cstr_to_str(table).is_none() { return -3; }
+        if cstr_to_str(json_array).is_none() { return -4; }
+        if let Ok(mut msg) = h.last_error_msg.lock() { msg.clear(); }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_publish_async_start(handle: *mut SurHandle, _queue_capacity: u32, _max_batch: u32, _cb: SurPublishCb, _user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return -1; }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_publish_async(handle: *mut SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
+        if handle.is_null() { return -1; }
+        if cstr_to_str(table_or_topic).is_none() { return -3; }
+        if cstr_to_str(json_payload).is_none() { return -4; }
+        // Nothing is queued in stub mode
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_publish_queue_depth(handle: *mut SurHandle) -> i64 { if handle.is_null() { return -1; } 0 }
+
+    /// Nothing is measured in stub mode; reports the connection flag only.
+    #[no_mangle]
+    pub extern "C" fn surreal_stats_json(handle: *mut SurHandle) -> *mut c_char {
+        if handle.is_null() { return std::ptr::null_mut(); }
+        let h = unsafe { &*handle };
+        let state = if h.is_connected { "connected" } else { "disconnected" };
+        CString::new(format!("{{\"state\":\"{}\",\"stub\":true}}", state)).unwrap_or_default().into_raw()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_stats_prometheus(handle: *mut SurHandle) -> *mut c_char {
+        if handle.is_null() { return std::ptr::null_mut(); }
+        let h = unsafe { &*handle };
+        let text = format!("# HELP surrealdb_ffi_connected 1 while the handle is connected.\n# TYPE surrealdb_ffi_connected gauge\nsurrealdb_ffi_connected {}\n", h.is_connected as u8);
+        CString::new(text).unwrap_or_default().into_raw()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_enable(handle: *mut SurHandle, dir: *const c_char, _max_bytes: u64, _max_age_secs: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        if cstr_to_str(dir).is_none() { return -3; }
+        // Publishes never fail in stub mode, so nothing is ever spooled
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_depth(handle: *mut SurHandle) -> i64 { if handle.is_null() { return -1; } 0 }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_bytes(handle: *mut SurHandle) -> i64 { if handle.is_null() { return -1; } 0 }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_dropped(handle: *mut SurHandle) -> i64 { if handle.is_null() { return -1; } 0 }
+
     #[no_mangle]
     pub extern "C" fn surreal_close(handle: *mut SurHandle) { if handle.is_null() { return; } let _ = unsafe { Box::from_raw(handle) }; }
 
@@ -120,6 +253,44 @@ mod api {
         0
     }
 
+    /// Stub: registers the callback like `surreal_subscribe`; the condition is ignored.
+    #[no_mangle]
+    pub extern "C" fn surreal_subscribe_where(handle: *mut SurHandle, _topic: *const c_char, _condition: *const c_char, _vars_json: *const c_char, cb: SurCommandCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        let mut guard = match h.callback.lock() { Ok(g) => g, Err(_) => return SurError::Internal.code() };
+        *guard = Some((cb, user_data));
+        0
+    }
+
+    /// Stub: accepted, but no changes are ever delivered.
+    #[no_mangle]
+    pub extern "C" fn surreal_subscribe_raw(handle: *mut SurHandle, _topic: *const c_char, _condition: *const c_char, _vars_json: *const c_char, _cb: SurChangeCb, _user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        0
+    }
+
+    /// Stub: nothing is claimed.
+    #[no_mangle]
+    pub extern "C" fn surreal_set_claim_options(handle: *mut SurHandle, _node_id: *const c_char, _lease_ms: u32) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        0
+    }
+
+    /// Stub: accepted, but no changes are ever delivered.
+    #[no_mangle]
+    pub extern "C" fn surreal_subscribe_changefeed(handle: *mut SurHandle, _topic: *const c_char, _checkpoint: *const c_char, _poll_ms: u32, _cb: SurChangeCb, _user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        0
+    }
+
+    /// Stub: nothing is claimed, so nothing expires.
+    #[no_mangle]
+    pub extern "C" fn surreal_set_claim_recovery(handle: *mut SurHandle, _reap_interval_ms: u32, _max_attempts: u32) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        0
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_unsubscribe(handle: *mut SurHandle, _topic: *const c_char) -> c_int {
         if handle.is_null() { return -1; }
@@ -129,6 +300,17 @@ mod api {
         0
     }
 
+    /// Stub: only the command callback is kept, so kind 1 clears it and other kinds do nothing.
+    #[no_mangle]
+    pub extern "C" fn surreal_unsubscribe_kind(handle: *mut SurHandle, _topic: *const c_char, kind: c_int) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        if kind != 1 { return 0; }
+        let mut guard = match h.callback.lock() { Ok(g) => g, Err(_) => return SurError::Internal.code() };
+        *guard = None;
+        0
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_debug_emit(handle: *mut SurHandle, topic: *const c_char, json: *const c_char) -> c_int {
         if handle.is_null() { return -1; }
@@ -137,6 +319,17 @@ mod api {
         if let Some((Some(cb), user)) = guard.as_ref() { cb(topic, json, *user); 0 } else { -3 }
     }
 
+    #[no_mangle]
+    pub extern "C" fn surreal_last_error_json(handle: *mut SurHandle) -> *mut c_char {
+        let (code, msg) = if handle.is_null() {
+            (0, String::new())
+        } else {
+            let h = unsafe { &*handle };
+            (h.last_error_code.load(Ordering::Relaxed), h.last_error_msg.lock().map(|m| m.clone()).unwrap_or_default())
+        };
+        CString::new(crate::error::detail_json(code, None, "", &msg, None)).unwrap_or_default().into_raw()
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_last_error_text(handle: *mut SurHandle, buf: *mut c_char, len: u32) -> c_int {
         if handle.is_null() || buf.is_null() || len == 0 { return -1; }
@@ -161,27 +354,146 @@ mod api {
         }
     }
 
+    /// Stub: accepted and ignored, the stub never logs.
+    #[no_mangle]
+    pub extern "C" fn surreal_set_log_handler(_cb: SurLogHandlerCb, _max_level: c_int, _user_data: *mut c_void) -> c_int { 0 }
+
+    /// Copies a result into the caller's buffer; returns -20 (and writes an empty string) if it does not fit.
+    fn write_out(h: &SurHandle, s: &str, out_json: *mut c_char, out_len: u32) -> c_int {
+        let bytes = s.as_bytes();
+        if bytes.len() >= out_len as usize {
+            unsafe { *out_json = 0 };
+            if let Ok(mut msg) = h.last_error_msg.lock() { *msg = format!("output needs {} bytes, buffer has {}", bytes.len() + 1, out_len); }
+            h.last_error_code.store(-20, Ordering::Relaxed);
+            return -20;
+        }
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_json as *mut u8, bytes.len()) };
+        unsafe { *(out_json.wrapping_add(bytes.len())) = 0 };
+        0
+    }
+
+    /// Hands a result to the caller as a heap string released with `surreal_free_string`.
+    fn alloc_out(s: String, out_json: *mut *mut c_char) -> c_int {
+        unsafe { *out_json = CString::new(s).unwrap_or_default().into_raw() };
+        0
+    }
+
+    fn select_json(table: *const c_char, limit: u32) -> Result<String, c_int> {
+        let _ = (cstr_to_str(table), limit);
+        Ok("[]".to_string())
+    }
+
+    fn get_json(_table: *const c_char, _id: *const c_char) -> Result<String, c_int> {
+        Ok("null".to_string())
+    }
+
+    fn query_json(sql: *const c_char, _vars_json: *const c_char) -> Result<String, c_int> {
+        if cstr_to_str(sql).is_none() { return Err(-2); }
+        Ok("[]".to_string())
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_select(handle: *mut SurHandle, table: *const c_char, limit: u32, out_json: *mut c_char, out_len: u32) -> c_int {
         if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
-        let _ = (cstr_to_str(table), limit);
-        let empty = b"[]";
-        let n = (empty.len().min((out_len - 1) as usize)) as usize;
-        unsafe { std::ptr::copy_nonoverlapping(empty.as_ptr(), out_json as *mut u8, n) };
-        unsafe { *(out_json.wrapping_add(n as usize)) = 0 };
-        0
+        let h = unsafe { &*handle };
+        match select_json(table, limit) { Ok(s) => write_out(h, &s, out_json, out_len), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_select_alloc(handle: *mut SurHandle, table: *const c_char, limit: u32, out_json: *mut *mut c_char) -> c_int {
+        if handle.is_null() || out_json.is_null() { return -1; }
+        unsafe { *out_json = std::ptr::null_mut() };
+        match select_json(table, limit) { Ok(s) => alloc_out(s, out_json), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_get(handle: *mut SurHandle, table: *const c_char, id: *const c_char, out_json: *mut c_char, out_len: u32) -> c_int {
+        if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
+        let h = unsafe { &*handle };
+        match get_json(table, id) { Ok(s) => write_out(h, &s, out_json, out_len), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_get_alloc(handle: *mut SurHandle, table: *const c_char, id: *const c_char, out_json: *mut *mut c_char) -> c_int {
+        if handle.is_null() || out_json.is_null() { return -1; }
+        unsafe { *out_json = std::ptr::null_mut() };
+        match get_json(table, id) { Ok(s) => alloc_out(s, out_json), Err(rc) => rc }
     }
 
     #[no_mangle]
-    pub extern "C" fn surreal_get(handle: *mut SurHandle, _table: *const c_char, _id: *const c_char, out_json: *mut c_char, out_len: u32) -> c_int {
+    pub extern "C" fn surreal_query(handle: *mut SurHandle, sql: *const c_char, vars_json: *const c_char, out_json: *mut c_char, out_len: u32) -> c_int {
         if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
-        let empty = b"null";
-        let n = (empty.len().min((out_len - 1) as usize)) as usize;
-        unsafe { std::ptr::copy_nonoverlapping(empty.as_ptr(), out_json as *mut u8, n) };
-        unsafe { *(out_json.wrapping_add(n as usize)) = 0 };
+        let h = unsafe { &*handle };
+        match query_json(sql, vars_json) { Ok(s) => write_out(h, &s, out_json, out_len), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_query_alloc(handle: *mut SurHandle, sql: *const c_char, vars_json: *const c_char, out_json: *mut *mut c_char) -> c_int {
+        if handle.is_null() || out_json.is_null() { return -1; }
+        unsafe { *out_json = std::ptr::null_mut() };
+        match query_json(sql, vars_json) { Ok(s) => alloc_out(s, out_json), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_pool_config(handle: *mut SurHandle, _min_size: u32, _max_size: u32, _health_interval_ms: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_state_callback(handle: *mut SurHandle, _cb: SurStateCb, _user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return -1; }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connection_state(handle: *mut SurHandle) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        if h.is_connected { 1 } else { 2 }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_reconnect_backoff(handle: *mut SurHandle, _initial_ms: u32, _max_ms: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_default_timeouts(_connect_ms: u32, _query_ms: u32, _publish_ms: u32) -> c_int { 0 }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_timeouts(handle: *mut SurHandle, _connect_ms: u32, _query_ms: u32, _publish_ms: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_token_provider(handle: *mut SurHandle, _cb: SurTokenProviderCb, _user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return -1; }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_token_file(handle: *mut SurHandle, _path: *const c_char) -> c_int {
+        if handle.is_null() { return -1; }
         0
     }
 
+    #[no_mangle]
+    pub extern "C" fn surreal_pool_stats_json(handle: *mut SurHandle, out_json: *mut c_char, out_len: u32) -> c_int {
+        if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
+        let h = unsafe { &*handle };
+        let stats = r#"{"state":"connected","min":1,"max":1,"size":1,"open":1,"busy":0,"checkouts":0,"connects":0,"connect_failures":0,"health_checks":0,"health_failures":0}"#;
+        write_out(h, stats, out_json, out_len)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_free_string(s: *mut c_char) {
+        if s.is_null() { return; }
+        drop(unsafe { CString::from_raw(s) });
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_update(_handle: *mut SurHandle, _table: *const c_char, _id: *const c_char, json_patch: *const c_char) -> c_int {
         if json_patch.is_null() { return -4; }
@@ -192,229 +504,1668 @@ mod api {
 
 #[cfg(feature = "real")]
 mod api {
-    use std::ffi::CStr;
+    use std::ffi::{CStr, CString};
     use std::os::raw::{c_char, c_int, c_void};
     use std::sync::atomic::{AtomicI32, Ordering};
-    use std::sync::{Mutex, OnceLock};
+    use std::sync::{Mutex, OnceLock, RwLock};
 
     use serde_json::Value as JsonValue;
     use tokio::runtime::Runtime;
+    use tokio::sync::{mpsc, watch, Notify};
     use tokio::task::JoinHandle;
     use tokio::time::{sleep, Duration};
     use std::collections::HashMap;
     use std::sync::Arc;
-    use std::sync::atomic::AtomicBool;
-
-    use surrealdb::Surreal;
-    use surrealdb::engine::remote::ws::{Client, Ws};
-    use surrealdb::opt::auth::Root;
-
-    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    use std::ops::Deref;
+    use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize};
+    use std::time::Instant;
+
+    use surrealdb::{RecordId, Surreal};
+    use surrealdb::engine::any::{self, Any};
+    use surrealdb::opt::auth::{Database, Namespace, Record, Root};
+
+    use crate::error::{detail_json, SurError};
+    use crate::logging;
+    use crate::metrics::{Gauges, Metrics};
+    use crate::spool::Spool;
+
+    /// The shared runtime. `surreal_shutdown_runtime` takes it out so a module reload can start
+    /// a fresh one; calls made while it is gone fail with "runtime not initialized".
+    static RUNTIME: RuntimeSlot = RuntimeSlot(RwLock::new(None));
+    /// Every open handle, so `surreal_shutdown_runtime` can stop their background work.
+    static HANDLES: Mutex<Vec<usize>> = Mutex::new(Vec::new());
     static LAST_ERR: AtomicI32 = AtomicI32::new(0);
-    static LOGGER: OnceLock<Mutex<Option<(SurLogCb, usize)>>> = OnceLock::new();
-
-    fn trace_enabled() -> bool {
-        match std::env::var("SURREALDB_FFI_TRACE") {
-            Ok(v) => {
-                let v = v.trim().to_ascii_lowercase();
-                !(v == "0" || v == "false" || v == "off")
-            }
-            Err(_) => true, // default ON
-        }
+    /// Server or library text for the last connect failure, reported by `surreal_last_error_json(NULL)`.
+    static LAST_ERR_MSG: Mutex<String> = Mutex::new(String::new());
+    /// Library-wide deadlines in ms, copied into each handle at connect (`surreal_set_default_timeouts`).
+    static DEFAULT_CONNECT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(10_000);
+    static DEFAULT_QUERY_TIMEOUT_MS: AtomicU64 = AtomicU64::new(30_000);
+    static DEFAULT_PUBLISH_TIMEOUT_MS: AtomicU64 = AtomicU64::new(10_000);
+
+    /// Like `set_err`, keeping the reason for `surreal_last_error_json(NULL)`.
+    fn set_err_msg(code: i32, msg: String) {
+        set_err(code);
+        if let Ok(mut m) = LAST_ERR_MSG.lock() { *m = msg; }
     }
 
     fn set_err(code: i32) {
         LAST_ERR.store(code, Ordering::Relaxed);
-        if trace_enabled() {
-            eprintln!("[surrealdb_ffi] error code {}", code);
-        }
+        if let Ok(mut m) = LAST_ERR_MSG.lock() { m.clear(); }
+        if code != 0 { log_debug(&format!("error code {}", code)); }
     }
 
     pub type SurCommandCb = Option<extern "C" fn(topic: *const c_char, json: *const c_char, user_data: *mut c_void)>;
-    pub type SurLogCb = Option<extern "C" fn(msg: *const c_char, user_data: *mut c_void)>;
-
+    /// Raw subscription callback; `action` is one of the `ACTION_*` values.
+    pub type SurChangeCb = Option<extern "C" fn(topic: *const c_char, action: c_int, json: *const c_char, user_data: *mut c_void)>;
+    pub use crate::logging::{SurLogCb, SurLogHandlerCb};
+    pub type SurPublishCb = Option<extern "C" fn(rc: c_int, table: *const c_char, json: *const c_char, error: *const c_char, user_data: *mut c_void)>;
+    pub type SurStateCb = Option<extern "C" fn(state: c_int, detail: *const c_char, user_data: *mut c_void)>;
+    pub type SurTokenProviderCb = Option<extern "C" fn(out: *mut c_char, out_len: u32, user_data: *mut c_void) -> c_int>;
+
+    /// Sign-in level. Namespace, database and record access sign in against the
+    /// connection's ns/db, so a switch can run with a least-privilege account.
+    #[derive(Clone, serde::Deserialize)]
+    #[serde(tag = "level", rename_all = "lowercase")]
     enum Auth {
-        UserPass { user: String, pass: String },
-        Token(String),
+        #[serde(alias = "userpass")]
+        Root { user: String, pass: String },
+        Namespace { user: String, pass: String },
+        Database { user: String, pass: String },
+        /// Record access (formerly scopes): `params` are passed to the access method's
+        /// SIGNIN/SIGNUP clause. With `signup`, a failed signin falls back to signup.
+        Record {
+            access: String,
+            #[serde(default)]
+            params: JsonValue,
+            #[serde(default)]
+            signup: bool,
+        },
+        /// A JWT; `token_file` is re-read whenever the server rejects the current token.
+        Token {
+            #[serde(default)]
+            token: String,
+            #[serde(default)]
+            token_file: Option<String>,
+        },
     }
 
-    #[repr(C)]
-    pub struct SurHandle {
-        last_error_code: AtomicI32,
-        callback: Mutex<Option<(SurCommandCb, *mut c_void)>>,
-        client: Option<Surreal<Client>>,
+    /// JSON accepted by `surreal_connect_opts`.
+    #[derive(serde::Deserialize)]
+    struct ConnectOpts {
         url: String,
+        #[serde(alias = "namespace")]
         ns: String,
+        #[serde(alias = "database")]
         db: String,
         auth: Auth,
-        subs: Mutex<HashMap<String, Sub>>, // table -> subscription
-        last_error_msg: Mutex<String>,
+        /// Scheme for a URL given as host:port (ws, wss, http, https or mem).
+        #[serde(default)]
+        transport: Option<String>,
+        #[serde(default)]
+        tls: Option<TlsOpts>,
     }
 
-    struct Sub {
-        stop: Arc<AtomicBool>,
-        handle: JoinHandle<()>,
-        cb: SurCommandCb,
-        user: *mut c_void,
+    #[derive(serde::Deserialize)]
+    struct TlsOpts {
+        ca_file: Option<String>,
+        cert_file: Option<String>,
+        key_file: Option<String>,
+        server_name: Option<String>,
     }
 
-    fn cstr_to_str<'a>(ptr: *const c_char) -> Option<&'a str> { if ptr.is_null() { return None; } unsafe { CStr::from_ptr(ptr) }.to_str().ok() }
+    /// Everything needed to open a connection; shared by the pool, subscriptions and the async queue.
+    struct ConnParams {
+        url: String,
+        ns: String,
+        db: String,
+        auth: Auth,
+        /// Custom TLS settings for wss:// and https:// (None = default roots, no client certificate).
+        tls: Option<rustls::ClientConfig>,
+        token: TokenSource,
+        timeouts: Timeouts,
+    }
 
-    #[no_mangle]
-    pub extern "C" fn surreal_init_runtime() -> c_int {
-        if RUNTIME.get().is_some() { return 0; }
-        match Runtime::new() {
-            Ok(rt) => { let _ = RUNTIME.set(rt); LAST_ERR.store(0, Ordering::Relaxed); 0 }
-            Err(_) => { set_err(-100); -1 }
-        }
+    /// Per-handle deadlines in milliseconds. A call that runs past its deadline fails with -21
+    /// (-108 for a connect) and its connection is dropped, since a half-open socket would
+    /// otherwise stall every later call on it.
+    struct Timeouts {
+        connect_ms: AtomicU64,
+        query_ms: AtomicU64,
+        publish_ms: AtomicU64,
     }
 
-    fn log_info(msg: &str) {
-        let (cb_opt, user_usize) = {
-            let m = LOGGER.get_or_init(|| Mutex::new(None));
-            match m.lock() {
-                Ok(g) => g.clone().unwrap_or((None, 0usize)),
-                Err(_) => (None, 0usize),
+    impl Timeouts {
+        fn defaults() -> Timeouts {
+            Timeouts {
+                connect_ms: AtomicU64::new(DEFAULT_CONNECT_TIMEOUT_MS.load(Ordering::Relaxed)),
+                query_ms: AtomicU64::new(DEFAULT_QUERY_TIMEOUT_MS.load(Ordering::Relaxed)),
+                publish_ms: AtomicU64::new(DEFAULT_PUBLISH_TIMEOUT_MS.load(Ordering::Relaxed)),
             }
-        };
-        if let Some(cb) = cb_opt {
-            if let Ok(cmsg) = std::ffi::CString::new(msg) { cb(cmsg.as_ptr(), user_usize as *mut c_void); }
-        } else if trace_enabled() {
-            eprintln!("[surrealdb_ffi] {}", msg);
         }
-    }
 
-    fn normalize_ws_url(url: &str) -> String {
-        let u = url.trim();
-        for p in ["ws://", "wss://", "http://", "https://"] {
-            if let Some(stripped) = u.strip_prefix(p) { return stripped.to_string(); }
-        }
-        u.to_string()
+        fn connect(&self) -> u64 { self.connect_ms.load(Ordering::Relaxed) }
+        fn query(&self) -> u64 { self.query_ms.load(Ordering::Relaxed) }
+        fn publish(&self) -> u64 { self.publish_ms.load(Ordering::Relaxed) }
     }
 
-    fn open_client(url: &str, ns: &str, db: &str, auth: &Auth) -> Result<Surreal<Client>, ()> {
-        let url = normalize_ws_url(url);
-        let rt = match RUNTIME.get() { Some(rt) => rt, None => { set_err(-101); return Err(()); } };
-        rt.block_on(async move {
-            let dbh = match Surreal::new::<Ws>(&url).await {
-                Ok(v) => v,
-                Err(_) => { set_err(-102); return Err(()); }
-            };
-            match auth {
-                Auth::UserPass { user, pass } => {
-                    if dbh.signin(Root { username: &user, password: &pass }).await.is_err() { set_err(-103); return Err(()); }
-                }
-                Auth::Token(token) => {
-                    if dbh.authenticate(token).await.is_err() { set_err(-104); return Err(()); }
-                }
-            }
-            if dbh.use_ns(ns).use_db(db).await.is_err() { set_err(-105); return Err(()); }
-            Ok::<_, ()>(dbh)
-        })
+    /// Runs `fut` with a deadline of `ms`; on expiry returns the message to report.
+    async fn deadline<T>(ms: u64, fut: impl std::future::Future<Output = T>) -> Result<T, String> {
+        tokio::time::timeout(Duration::from_millis(ms), fut).await.map_err(|_| format!("timed out after {} ms", ms))
     }
 
-    #[no_mangle]
-    pub extern "C" fn surreal_connect(url: *const c_char, ns: *const c_char, db: *const c_char, user: *const c_char, pass: *const c_char) -> *mut SurHandle {
-        let url_s = match cstr_to_str(url) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let ns_s = match cstr_to_str(ns) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let db_s = match cstr_to_str(db) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let user_s = match cstr_to_str(user) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let pass_s = match cstr_to_str(pass) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+    /// Current token for `Auth::Token`, and where to fetch a fresh one once the server rejects it.
+    struct TokenSource {
+        current: Mutex<String>,
+        file: Mutex<Option<String>>,
+        provider: Mutex<Option<(SurTokenProviderCb, usize)>>,
+        /// Bumped on every renewal; long-lived connections re-authenticate when it moves.
+        generation: watch::Sender<u64>,
+    }
 
-        let auth = Auth::UserPass { user: user_s, pass: pass_s };
-        match open_client(&url_s, &ns_s, &db_s, &auth) {
-            Ok(client) => {
-                let handle = Box::new(SurHandle {
-                    last_error_code: AtomicI32::new(0),
-                    callback: Mutex::new(None),
-                    client: Some(client),
-                    url: url_s,
-                    ns: ns_s,
-                    db: db_s,
-                    auth,
-                    subs: Mutex::new(HashMap::new()),
-                    last_error_msg: Mutex::new(String::new()),
-                });
-                LAST_ERR.store(0, Ordering::Relaxed);
-                Box::into_raw(handle)
+    impl TokenSource {
+        fn new(token: &str, file: Option<String>) -> TokenSource {
+            TokenSource {
+                current: Mutex::new(token.to_string()),
+                file: Mutex::new(file),
+                provider: Mutex::new(None),
+                generation: watch::channel(0).0,
             }
-            Err(_) => std::ptr::null_mut(),
         }
-    }
 
-    #[no_mangle]
-    pub extern "C" fn surreal_connect_with_token(url: *const c_char, ns: *const c_char, db: *const c_char, token: *const c_char) -> *mut SurHandle {
-        let url_s = match cstr_to_str(url) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let ns_s = match cstr_to_str(ns) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let db_s = match cstr_to_str(db) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
-        let token_s = match cstr_to_str(token) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        fn current(&self) -> String {
+            self.current.lock().map(|t| t.clone()).unwrap_or_default()
+        }
 
-        let auth = Auth::Token(token_s);
-        match open_client(&url_s, &ns_s, &db_s, &auth) {
-            Ok(client) => {
-                let handle = Box::new(SurHandle {
-                    last_error_code: AtomicI32::new(0),
-                    callback: Mutex::new(None),
-                    client: Some(client),
-                    url: url_s,
-                    ns: ns_s,
-                    db: db_s,
-                    auth,
-                    subs: Mutex::new(HashMap::new()),
-                    last_error_msg: Mutex::new(String::new()),
-                });
-                LAST_ERR.store(0, Ordering::Relaxed);
-                Box::into_raw(handle)
+        /// Asks the provider callback (preferred) or re-reads the token file. Returns the new
+        /// token when it differs from the current one; renewals are logged.
+        fn renew(&self) -> Option<String> {
+            let provider = self.provider.lock().ok().and_then(|g| *g);
+            let (fresh, from) = if let Some((Some(cb), user)) = provider {
+                let mut buf = vec![0u8; 8192];
+                let n = cb(buf.as_mut_ptr() as *mut c_char, buf.len() as u32, user as *mut c_void);
+                if n <= 0 {
+                    log_warn(&format!("token provider returned {}; keeping the current token", n));
+                    return None;
+                }
+                buf.truncate((n as usize).min(buf.len() - 1));
+                (String::from_utf8_lossy(&buf).trim().to_string(), "provider")
+            } else if let Some(path) = self.file.lock().ok().and_then(|g| g.clone()) {
+                match std::fs::read_to_string(&path) {
+                    Ok(t) => (t.trim().to_string(), "token file"),
+                    Err(e) => { log_warn(&format!("token file {}: {}", path, e)); return None; }
+                }
+            } else {
+                log_warn("token rejected and no token provider or token file is configured");
+                return None;
+            };
+            let mut cur = self.current.lock().ok()?;
+            if fresh.is_empty() || *cur == fresh {
+                log_warn(&format!("token {} has no new token", from));
+                return None;
             }
-            Err(_) => std::ptr::null_mut(),
+            *cur = fresh.clone();
+            drop(cur);
+            self.generation.send_modify(|g| *g += 1);
+            log_info(&format!("token renewed from {}", from));
+            Some(fresh)
         }
     }
 
+    /// Best-effort check for an expired or rejected session in a SurrealDB error message.
+    fn is_auth_error(err: &str) -> bool {
+        let e = err.to_ascii_lowercase();
+        e.contains("expired") || e.contains("invalidauth") || e.contains("problem with authentication") || e.contains("not authenticated")
+    }
+
+    impl ConnParams {
+        fn new(url: String, ns: String, db: String, auth: Auth, tls: Option<rustls::ClientConfig>) -> ConnParams {
+            let token = match &auth {
+                Auth::Token { token, token_file } => {
+                    let file = token_file.as_deref().map(str::trim).filter(|f| !f.is_empty()).map(str::to_string);
+                    let source = TokenSource::new(token, file);
+                    if token.is_empty() { source.renew(); }
+                    source
+                }
+                _ => TokenSource::new("", None),
+            };
+            ConnParams { url, ns, db, auth, tls, token, timeouts: Timeouts::defaults() }
+        }
+
+        /// True when the URL selects the HTTP engine, which has no LIVE queries.
+        fn is_http(&self) -> bool {
+            let u = self.url.trim();
+            u.starts_with("http://") || u.starts_with("https://")
+        }
+    }
+
+    #[repr(C)]
+    pub struct SurHandle {
+        last_error_code: AtomicI32,
+        callback: Mutex<Option<(SurCommandCb, *mut c_void)>>,
+        pool: Arc<Pool>,
+        pool_task: JoinHandle<()>,
+        conn: Arc<ConnParams>,
+        subs: Mutex<HashMap<(c_int, String), Sub>>, // (kind, table) -> subscription
+        last_error: Mutex<ErrorDetail>,
+        async_pub: Mutex<Option<AsyncPublisher>>,
+        spool: Arc<OnceLock<Spool>>,
+        claimant: Arc<Claimant>,
+    }
+
+    const DEFAULT_CLAIM_LEASE_MS: u64 = 60_000;
+    const DEFAULT_REAP_INTERVAL_MS: u64 = 10_000;
+    const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+    /// Who claims command rows and for how long (`surreal_set_claim_options`), and how
+    /// expired claims are recovered (`surreal_set_claim_recovery`).
+    struct Claimant {
+        node: RwLock<String>,
+        lease_ms: AtomicU64,
+        reap_interval_ms: AtomicU64,
+        max_attempts: AtomicU32,
+    }
+
+    impl Claimant {
+        fn new() -> Self {
+            Claimant {
+                node: RwLock::new(default_node_id()),
+                lease_ms: AtomicU64::new(DEFAULT_CLAIM_LEASE_MS),
+                reap_interval_ms: AtomicU64::new(DEFAULT_REAP_INTERVAL_MS),
+                max_attempts: AtomicU32::new(DEFAULT_MAX_ATTEMPTS),
+            }
+        }
+
+        fn reap_interval(&self) -> Duration { Duration::from_millis(self.reap_interval_ms.load(Ordering::Relaxed)) }
+
+        fn node(&self) -> String { self.node.read().map(|n| n.clone()).unwrap_or_default() }
+    }
+
+    /// The host name, which is how a switch is usually known in a cluster.
+    fn default_node_id() -> String {
+        std::fs::read_to_string("/proc/sys/kernel/hostname").ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| format!("pid-{}", std::process::id()))
+    }
+
+    /// The most recent failure on a handle, for `surreal_last_error_text` and `surreal_last_error_json`.
+    #[derive(Default)]
+    struct ErrorDetail {
+        kind: Option<SurError>,
+        op: &'static str,
+        message: String,
+        statement: Option<String>,
+    }
+
+    /// Longest statement text kept in an error report; documents are not repeated in full.
+    const ERROR_STATEMENT_MAX: usize = 512;
+
+    impl SurHandle {
+        fn clear_error(&self) {
+            self.last_error_code.store(0, Ordering::Relaxed);
+            if let Ok(mut d) = self.last_error.lock() { *d = ErrorDetail::default(); }
+        }
+
+        /// Records a failure of `op` and returns `code`, the function's historical return value.
+        fn fail(&self, op: &'static str, code: c_int, kind: SurError, message: impl Into<String>) -> c_int {
+            self.record(op, code, kind, message.into(), None)
+        }
+
+        /// Records a failed round trip with the server's error text and the statement that was
+        /// sent. Rejected tokens also trigger a renewal (see `Pool::note_error`).
+        fn fail_query(&self, op: &'static str, code: c_int, message: String, statement: &str) -> c_int {
+            self.pool.note_error(&message);
+            let kind = classify(&message);
+            self.record(op, code, kind, message, Some(statement))
+        }
+
+        /// Records an expired deadline (-21) and drops the connection it ran on.
+        fn timed_out(&self, op: &'static str, client: &Lease<'_>, message: String, statement: &str) -> c_int {
+            client.reset();
+            self.record(op, -21, SurError::Timeout, message, Some(statement))
+        }
+
+        // Functions added since the error categories return the category code itself
+        // (SurError::code) instead of a per-function code; these record and return it.
+
+        fn fail_kind(&self, op: &'static str, kind: SurError, message: impl Into<String>) -> c_int {
+            self.record(op, kind.code(), kind, message.into(), None)
+        }
+
+        fn fail_query_kind(&self, op: &'static str, message: String, statement: &str) -> c_int {
+            self.pool.note_error(&message);
+            let kind = classify(&message);
+            self.record(op, kind.code(), kind, message, Some(statement))
+        }
+
+        fn timed_out_kind(&self, op: &'static str, client: &Lease<'_>, message: String, statement: &str) -> c_int {
+            client.reset();
+            self.record(op, SurError::Timeout.code(), SurError::Timeout, message, Some(statement))
+        }
+
+        fn record(&self, op: &'static str, code: c_int, kind: SurError, message: String, statement: Option<&str>) -> c_int {
+            self.last_error_code.store(code, Ordering::Relaxed);
+            self.pool.metrics.failure(kind);
+            let statement = statement.map(|s| match s.char_indices().nth(ERROR_STATEMENT_MAX) {
+                Some((cut, _)) => format!("{}...", &s[..cut]),
+                None => s.to_string(),
+            });
+            if let Ok(mut d) = self.last_error.lock() { *d = ErrorDetail { kind: Some(kind), op, message, statement }; }
+            code
+        }
+    }
+
+    /// Picks a category for an SDK error message.
+    fn classify(err: &str) -> SurError {
+        let e = err.to_ascii_lowercase();
+        if is_auth_error(err) || e.contains("not enough permissions") || e.contains("not allowed") {
+            SurError::Auth
+        } else if e.contains("timed out") || e.contains("timeout") {
+            SurError::Timeout
+        } else if e.contains("connection") || e.contains("not connected") || e.contains("websocket") || e.contains("send request") {
+            SurError::NotConnected
+        } else {
+            SurError::Query
+        }
+    }
+
+    /// Slots allocated per handle; `surreal_pool_config` caps the maximum size here.
+    const POOL_SLOTS: usize = 64;
+    /// Connections above the minimum are closed after this long without use.
+    const POOL_IDLE_MS: u64 = 60_000;
+    /// How often the supervisor retries the spool while it holds documents.
+    const SPOOL_REPLAY_EVERY: Duration = Duration::from_secs(1);
+    /// Poll interval for subscriptions over HTTP, where LIVE queries are unavailable.
+    const HTTP_POLL_MS: u64 = 1_000;
+    /// Rows fetched per catch-up query, and the most pages read before resuming the stream.
+    const CATCH_UP_PAGE: usize = 100;
+    const CATCH_UP_MAX_PAGES: usize = 50;
+
+    /// Connection states reported through `surreal_set_state_callback`.
+    const STATE_CONNECTED: c_int = 1;
+    const STATE_DISCONNECTED: c_int = 2;
+    const STATE_RECONNECTING: c_int = 3;
+    const STATE_AUTH_FAILED: c_int = 4;
+
+    /// Exponential reconnect delay: doubles from `initial_ms` up to `max_ms`, and each delay is
+    /// drawn from [d/2, d] so clients that lost the server together do not retry in lockstep.
+    struct Backoff {
+        initial_ms: u64,
+        max_ms: u64,
+        attempt: u32,
+        seed: u64,
+    }
+
+    impl Backoff {
+        fn new(initial_ms: u64, max_ms: u64) -> Backoff {
+            let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0x9E37_79B9);
+            Backoff { initial_ms: initial_ms.max(1), max_ms: max_ms.max(initial_ms.max(1)), attempt: 0, seed: seed | 1 }
+        }
+
+        fn reset(&mut self) { self.attempt = 0; }
+
+        fn next_delay(&mut self) -> Duration {
+            let d = self.initial_ms.saturating_mul(1u64 << self.attempt.min(30)).min(self.max_ms);
+            self.attempt = self.attempt.saturating_add(1);
+            // xorshift64 is plenty for jitter and avoids pulling in a rand dependency
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 7;
+            self.seed ^= self.seed << 17;
+            Duration::from_millis(d / 2 + self.seed % (d / 2 + 1))
+        }
+    }
+
+    /// One pooled connection. `busy` counts callers currently using it.
+    struct PoolSlot {
+        client: Mutex<Option<Surreal<Any>>>,
+        busy: AtomicUsize,
+        last_used_ms: AtomicU64,
+    }
+
+    /// Per-handle connection pool for publish, select, get, update and query.
+    /// Calls are spread round-robin over the open slots, preferring an idle one; while every
+    /// slot is busy the pool grows up to `max`, so one slow query does not stall other callers.
+    /// A supervisor task owns reconnection: while the server is unreachable callers fail fast
+    /// instead of blocking on a connect, and the supervisor retries with backoff.
+    struct Pool {
+        conn: Arc<ConnParams>,
+        slots: Vec<PoolSlot>,
+        min: AtomicUsize,
+        max: AtomicUsize,
+        size: AtomicUsize,
+        next: AtomicUsize,
+        health_interval_ms: AtomicU64,
+        backoff_initial_ms: AtomicU64,
+        backoff_max_ms: AtomicU64,
+        state: AtomicI32,
+        state_cb: Mutex<Option<(SurStateCb, usize)>>,
+        wake: Notify,
+        /// Set when a call failed on a rejected token; the supervisor renews and re-authenticates.
+        reauth: AtomicBool,
+        /// Counters for the handle, shared with its subscriptions and async queue.
+        metrics: Metrics,
+        epoch: Instant,
+        checkouts: AtomicU64,
+        connects: AtomicU64,
+        connect_failures: AtomicU64,
+        health_checks: AtomicU64,
+        health_failures: AtomicU64,
+    }
+
+    /// A pooled client in use; gives its slot back on drop.
+    struct Lease<'a> {
+        pool: &'a Pool,
+        slot: &'a PoolSlot,
+        client: Surreal<Any>,
+    }
+
+    impl Deref for Lease<'_> {
+        type Target = Surreal<Any>;
+        fn deref(&self) -> &Surreal<Any> { &self.client }
+    }
+
+    impl Lease<'_> {
+        /// Drops the slot's connection after a transport failure and wakes the supervisor,
+        /// which checks the remaining connections and reconnects if none is left.
+        fn reset(&self) {
+            if let Ok(mut c) = self.slot.client.lock() { *c = None; }
+            self.pool.wake.notify_one();
+        }
+    }
+
+    impl Drop for Lease<'_> {
+        fn drop(&mut self) { self.slot.busy.fetch_sub(1, Ordering::Relaxed); }
+    }
+
+    impl Pool {
+        fn new(conn: Arc<ConnParams>, first: Surreal<Any>) -> Pool {
+            let slots: Vec<PoolSlot> = (0..POOL_SLOTS)
+                .map(|_| PoolSlot { client: Mutex::new(None), busy: AtomicUsize::new(0), last_used_ms: AtomicU64::new(0) })
+                .collect();
+            if let Ok(mut c) = slots[0].client.lock() { *c = Some(first); }
+            Pool {
+                conn,
+                slots,
+                min: AtomicUsize::new(1),
+                max: AtomicUsize::new(1),
+                size: AtomicUsize::new(1),
+                next: AtomicUsize::new(0),
+                health_interval_ms: AtomicU64::new(30_000),
+                backoff_initial_ms: AtomicU64::new(250),
+                backoff_max_ms: AtomicU64::new(30_000),
+                state: AtomicI32::new(STATE_CONNECTED),
+                state_cb: Mutex::new(None),
+                wake: Notify::new(),
+                reauth: AtomicBool::new(false),
+                metrics: Metrics::default(),
+                epoch: Instant::now(),
+                checkouts: AtomicU64::new(0),
+                connects: AtomicU64::new(1),
+                connect_failures: AtomicU64::new(0),
+                health_checks: AtomicU64::new(0),
+                health_failures: AtomicU64::new(0),
+            }
+        }
+
+        fn now_ms(&self) -> u64 { self.epoch.elapsed().as_millis() as u64 }
+
+        fn backoff(&self) -> Backoff {
+            Backoff::new(self.backoff_initial_ms.load(Ordering::Relaxed), self.backoff_max_ms.load(Ordering::Relaxed))
+        }
+
+        /// Records a state transition and reports it to the C callback (only on change).
+        fn set_state(&self, state: c_int, detail: &str) {
+            let prev = self.state.swap(state, Ordering::AcqRel);
+            if prev == state { return; }
+            if state == STATE_CONNECTED { self.metrics.reconnects.fetch_add(1, Ordering::Relaxed); }
+            let level = if state == STATE_CONNECTED { logging::LOG_INFO } else { logging::LOG_WARN };
+            log_event(level, || format!("connection state {} ({})", state_name(state), detail),
+                || serde_json::json!({ "state": state_name(state), "detail": detail }));
+            let cb = match self.state_cb.lock() { Ok(g) => *g, Err(_) => None };
+            if let Some((Some(cb_fn), user)) = cb {
+                if let Ok(cdetail) = std::ffi::CString::new(detail) { cb_fn(state, cdetail.as_ptr(), user as *mut c_void); }
+            }
+        }
+
+        /// Picks a slot and returns its client. An empty slot is connected inline only while
+        /// the server is known to be up (pool growth); otherwise the call fails fast and the
+        /// supervisor reconnects. Must not be called from a runtime thread.
+        fn checkout(&self) -> Option<Lease<'_>> {
+            let size = self.size.load(Ordering::Acquire).clamp(1, POOL_SLOTS);
+            let start = self.next.fetch_add(1, Ordering::Relaxed);
+            let idle = (0..size).map(|o| (start + o) % size).find(|&i| self.slots[i].busy.load(Ordering::Relaxed) == 0);
+            let pick = match idle {
+                Some(i) => i,
+                // Every connection is busy: open another one instead of queueing behind it
+                None if size < self.max.load(Ordering::Relaxed)
+                    && self.size.compare_exchange(size, size + 1, Ordering::AcqRel, Ordering::Relaxed).is_ok() => size,
+                None => start % size,
+            };
+            let slot = &self.slots[pick];
+            slot.busy.fetch_add(1, Ordering::Relaxed);
+            slot.last_used_ms.store(self.now_ms(), Ordering::Relaxed);
+            let mut client = slot.client.lock().ok().and_then(|c| c.clone());
+            if client.is_none() && self.state.load(Ordering::Acquire) == STATE_CONNECTED {
+                // Connect without holding the slot lock, so health checks and other callers
+                // are not stuck behind the handshake; a racing caller may fill the slot first
+                match open_client(&self.conn) {
+                    Ok(c) => {
+                        self.connects.fetch_add(1, Ordering::Relaxed);
+                        client = match slot.client.lock() {
+                            Ok(mut guard) => Some(guard.get_or_insert(c).clone()),
+                            Err(_) => None,
+                        };
+                    }
+                    Err(_) => { self.connect_failures.fetch_add(1, Ordering::Relaxed); self.wake.notify_one(); }
+                }
+            }
+            match client {
+                Some(client) => {
+                    self.checkouts.fetch_add(1, Ordering::Relaxed);
+                    Some(Lease { pool: self, slot, client })
+                }
+                None => {
+                    slot.busy.fetch_sub(1, Ordering::Relaxed);
+                    None
+                }
+            }
+        }
+
+        /// Inspects a failed call's error text; a rejected token asks the supervisor to renew it.
+        fn note_error(&self, err: &str) {
+            if matches!(self.conn.auth, Auth::Token { .. }) && is_auth_error(err) {
+                self.reauth.store(true, Ordering::Release);
+                self.wake.notify_one();
+            }
+        }
+
+        /// Renews the token and re-authenticates every open connection in place;
+        /// connections that still fail are dropped and reconnected later.
+        async fn reauthenticate(&self) {
+            let token = match self.conn.token.renew() { Some(t) => t, None => return };
+            let size = self.size.load(Ordering::Acquire);
+            for (i, slot) in self.slots.iter().enumerate().take(size) {
+                let client = match slot.client.lock().ok().and_then(|c| c.clone()) { Some(c) => c, None => continue };
+                if let Err(e) = client.authenticate(token.clone()).await {
+                    log_warn(&format!("pool connection {} rejected the renewed token: {}", i, e));
+                    if let Ok(mut g) = slot.client.lock() { *g = None; }
+                }
+            }
+        }
+
+        /// Applies new bounds; existing connections above a lowered maximum close when idle.
+        fn configure(&self, min: usize, max: usize, health_interval_ms: u64) {
+            let min = min.clamp(1, POOL_SLOTS);
+            let max = max.clamp(min, POOL_SLOTS);
+            self.min.store(min, Ordering::Relaxed);
+            self.max.store(max, Ordering::Relaxed);
+            self.health_interval_ms.store(health_interval_ms, Ordering::Relaxed);
+            let _ = self.size.fetch_update(Ordering::AcqRel, Ordering::Relaxed, |s| Some(s.clamp(min, max)));
+            self.wake.notify_one();
+        }
+
+        /// Any open connection, without marking it busy; for the supervisor's own requests.
+        fn any_client(&self) -> Option<Surreal<Any>> {
+            let size = self.size.load(Ordering::Acquire);
+            self.slots.iter().take(size).find_map(|s| s.client.lock().ok().and_then(|c| c.clone()))
+        }
+
+        fn open_count(&self) -> usize {
+            self.slots.iter().filter(|s| s.client.lock().map(|c| c.is_some()).unwrap_or(false)).count()
+        }
+
+        /// Pings open connections (dropping dead ones) and reconnects slots below the minimum.
+        async fn check_slots(&self) {
+            let size = self.size.load(Ordering::Acquire);
+            for (i, slot) in self.slots.iter().enumerate().take(size) {
+                let client = slot.client.lock().ok().and_then(|c| c.clone());
+                match client {
+                    Some(c) => {
+                        self.health_checks.fetch_add(1, Ordering::Relaxed);
+                        if !matches!(tokio::time::timeout(Duration::from_secs(5), c.health()).await, Ok(Ok(()))) {
+                            self.health_failures.fetch_add(1, Ordering::Relaxed);
+                            log_warn(&format!("pool connection {} failed health check; dropping it", i));
+                            if let Ok(mut g) = slot.client.lock() { *g = None; }
+                        }
+                    }
+                    None if i < self.min.load(Ordering::Relaxed) => {
+                        match connect_client(&self.conn).await {
+                            Ok(c) => {
+                                self.connects.fetch_add(1, Ordering::Relaxed);
+                                if let Ok(mut g) = slot.client.lock() { if g.is_none() { *g = Some(c); } }
+                            }
+                            Err(_) => { self.connect_failures.fetch_add(1, Ordering::Relaxed); }
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        /// Closes idle connections above the minimum, shrinking from the top slot down.
+        fn shrink(&self) {
+            let now = self.now_ms();
+            loop {
+                let size = self.size.load(Ordering::Acquire);
+                if size <= self.min.load(Ordering::Relaxed) { break; }
+                let top = &self.slots[size - 1];
+                let idle_for = now.saturating_sub(top.last_used_ms.load(Ordering::Relaxed));
+                if top.busy.load(Ordering::Relaxed) > 0 || idle_for < POOL_IDLE_MS { break; }
+                if self.size.compare_exchange(size, size - 1, Ordering::AcqRel, Ordering::Relaxed).is_err() { continue; }
+                if let Ok(mut c) = top.client.lock() { *c = None; }
+            }
+        }
+
+        fn busy_count(&self) -> usize {
+            self.slots.iter().map(|s| s.busy.load(Ordering::Relaxed)).sum()
+        }
+
+        fn stats_json(&self) -> JsonValue {
+            let busy = self.busy_count();
+            serde_json::json!({
+                "state": state_name(self.state.load(Ordering::Relaxed)),
+                "min": self.min.load(Ordering::Relaxed),
+                "max": self.max.load(Ordering::Relaxed),
+                "size": self.size.load(Ordering::Relaxed),
+                "open": self.open_count(),
+                "busy": busy,
+                "checkouts": self.checkouts.load(Ordering::Relaxed),
+                "connects": self.connects.load(Ordering::Relaxed),
+                "connect_failures": self.connect_failures.load(Ordering::Relaxed),
+                "health_checks": self.health_checks.load(Ordering::Relaxed),
+                "health_failures": self.health_failures.load(Ordering::Relaxed),
+            })
+        }
+    }
+
+    fn state_name(state: c_int) -> &'static str {
+        match state {
+            STATE_CONNECTED => "connected",
+            STATE_DISCONNECTED => "disconnected",
+            STATE_RECONNECTING => "reconnecting",
+            STATE_AUTH_FAILED => "auth-failed",
+            _ => "unknown",
+        }
+    }
+
+    /// Background supervisor for a handle's pool. While connected it runs health checks every
+    /// `health_interval_ms` (or when a caller reports a failed connection) and replays the
+    /// spool while it holds documents; once no connection is left it reconnects with
+    /// exponential backoff and jitter, reporting each transition.
+    async fn supervise(pool: Arc<Pool>, spool: Arc<OnceLock<Spool>>) {
+        let mut backoff = pool.backoff();
+        loop {
+            if pool.state.load(Ordering::Acquire) == STATE_CONNECTED {
+                let spooled = spool.get().is_some_and(|sp| sp.depth() > 0);
+                let every = pool.health_interval_ms.load(Ordering::Relaxed);
+                let wait = Duration::from_millis(if every == 0 { 3_600_000 } else { every });
+                let timed_out = tokio::time::timeout(if spooled { wait.min(SPOOL_REPLAY_EVERY) } else { wait }, pool.wake.notified()).await.is_err();
+                // A replay tick is not a health tick
+                let health_due = !(spooled && timed_out && wait > SPOOL_REPLAY_EVERY);
+                if pool.reauth.swap(false, Ordering::AcqRel) { pool.reauthenticate().await; }
+                if health_due && (every != 0 || pool.open_count() < pool.min.load(Ordering::Relaxed)) { pool.check_slots().await; }
+                if pool.open_count() == 0 {
+                    pool.set_state(STATE_DISCONNECTED, "no usable connection left");
+                    backoff = pool.backoff();
+                    continue;
+                }
+                pool.shrink();
+                if let (Some(sp), Some(client)) = (spool.get().filter(|sp| sp.depth() > 0), pool.any_client()) {
+                    match replay_spool(&client, sp, pool.conn.timeouts.publish()).await {
+                        Ok(n) if n > 0 => log_info(&format!("spool replayed {} documents", n)),
+                        Ok(_) => {}
+                        Err(e) => {
+                            log_warn(&format!("spool replay stopped: {}", e));
+                            pool.note_error(&e);
+                            // Health-check the connections on the next pass
+                            pool.wake.notify_one();
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if pool.state.load(Ordering::Acquire) == STATE_DISCONNECTED {
+                pool.set_state(STATE_RECONNECTING, "retrying with backoff");
+            }
+            match connect_client(&pool.conn).await {
+                Ok(c) => {
+                    pool.connects.fetch_add(1, Ordering::Relaxed);
+                    if let Ok(mut g) = pool.slots[0].client.lock() { *g = Some(c); }
+                    backoff.reset();
+                    pool.set_state(STATE_CONNECTED, "reconnected");
+                }
+                Err(code) => {
+                    pool.connect_failures.fetch_add(1, Ordering::Relaxed);
+                    if code == -103 || code == -104 {
+                        // Keep retrying: credentials or tokens may be fixed server-side
+                        pool.set_state(STATE_AUTH_FAILED, if code == -103 { "signin rejected" } else { "token rejected" });
+                    } else {
+                        pool.set_state(STATE_RECONNECTING, "retrying with backoff");
+                    }
+                    let delay = backoff.next_delay();
+                    log_warn(&format!("reconnect failed (code {}); next attempt in {} ms", code, delay.as_millis()));
+                    sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    /// One document waiting in the async publish queue.
+    struct AsyncMsg {
+        table: String,
+        doc: JsonValue,
+        raw: String,
+    }
+
+    /// Bounded queue drained by a runtime task that inserts documents in batches.
+    struct AsyncPublisher {
+        tx: mpsc::Sender<AsyncMsg>,
+        depth: Arc<AtomicUsize>,
+        task: JoinHandle<()>,
+    }
+
+    struct Sub {
+        stop: Arc<AtomicBool>,
+        handle: JoinHandle<()>,
+        cb: SurCommandCb,
+        user: *mut c_void,
+    }
+
+    fn cstr_to_str<'a>(ptr: *const c_char) -> Option<&'a str> { if ptr.is_null() { return None; } unsafe { CStr::from_ptr(ptr) }.to_str().ok() }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_init_runtime() -> c_int {
+        surreal_init_runtime_ex(0, std::ptr::null())
+    }
+
+    struct RuntimeSlot(RwLock<Option<Arc<Runtime>>>);
+
+    impl RuntimeSlot {
+        fn get(&self) -> Option<Arc<Runtime>> {
+            self.0.read().ok().and_then(|g| g.clone())
+        }
+    }
+
+    /// Starts the runtime with `worker_threads` workers (0 = one per CPU) named
+    /// `<prefix>-<n>` (NULL or empty = "surrealdb-ffi"). A no-op if one is already running.
+    #[no_mangle]
+    pub extern "C" fn surreal_init_runtime_ex(worker_threads: u32, thread_name_prefix: *const c_char) -> c_int {
+        let mut slot = match RUNTIME.0.write() { Ok(g) => g, Err(_) => { set_err(-100); return -1; } };
+        if slot.is_some() { return 0; }
+        let prefix = cstr_to_str(thread_name_prefix).map(str::trim).filter(|p| !p.is_empty()).unwrap_or("surrealdb-ffi").to_string();
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all().thread_name_fn(move || {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            format!("{}-{}", prefix, NEXT.fetch_add(1, Ordering::Relaxed))
+        });
+        if worker_threads > 0 { builder.worker_threads(worker_threads as usize); }
+        match builder.build() {
+            Ok(rt) => { *slot = Some(Arc::new(rt)); LAST_ERR.store(0, Ordering::Relaxed); 0 }
+            Err(e) => { set_err_msg(-100, e.to_string()); -1 }
+        }
+    }
+
+    /// Stops every handle's subscriptions, supervisor and async queue (flushing queued
+    /// documents), then shuts the runtime down, all within `timeout_ms` (0 = 5000).
+    /// Returns 0, or 1 if calls were still running at the deadline; the runtime then
+    /// goes away when the last of them returns. Handles stay allocated for `surreal_close`
+    /// but cannot be used again, even after a new `surreal_init_runtime`.
+    #[no_mangle]
+    pub extern "C" fn surreal_shutdown_runtime(timeout_ms: u32) -> c_int {
+        let until = Instant::now() + Duration::from_millis(if timeout_ms == 0 { 5_000 } else { timeout_ms as u64 });
+        {
+            // Held throughout: surreal_close unregisters a handle under this lock before
+            // freeing it, so every address stays valid while it is being stopped
+            let handles = HANDLES.lock().unwrap_or_else(|e| e.into_inner());
+            for &addr in handles.iter() {
+                let h = unsafe { &*(addr as *const SurHandle) };
+                stop_subscriptions(h);
+                stop_async_publisher(h, until.saturating_duration_since(Instant::now()));
+                h.pool_task.abort();
+            }
+        }
+        #[cfg(feature = "embedded")]
+        loop {
+            // A connect may briefly hold the lock; wait for it rather than leak the datastores
+            if let Ok(mut all) = MEM_DB.try_lock() { all.clear(); break; }
+            if Instant::now() >= until {
+                log_warn("runtime shutdown: embedded datastores still in use at the deadline");
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let mut rt = match RUNTIME.0.write().ok().and_then(|mut g| g.take()) { Some(rt) => rt, None => return 0 };
+        // In-flight calls hold a reference; give them until the deadline to finish
+        loop {
+            match Arc::try_unwrap(rt) {
+                Ok(owned) => {
+                    owned.shutdown_timeout(until.saturating_duration_since(Instant::now()));
+                    log_info("runtime shut down");
+                    return 0;
+                }
+                Err(shared) if Instant::now() < until => { rt = shared; std::thread::sleep(Duration::from_millis(10)); }
+                Err(_) => {
+                    log_warn("runtime shutdown: calls still running at the deadline");
+                    return 1;
+                }
+            }
+        }
+    }
+
+    const LOG_TARGET: &str = "surrealdb_ffi";
+
+    fn log_error(msg: &str) { logging::emit(logging::LOG_ERROR, LOG_TARGET, msg, None); }
+    fn log_warn(msg: &str) { logging::emit(logging::LOG_WARN, LOG_TARGET, msg, None); }
+    fn log_info(msg: &str) { logging::emit(logging::LOG_INFO, LOG_TARGET, msg, None); }
+    fn log_debug(msg: &str) { logging::emit(logging::LOG_DEBUG, LOG_TARGET, msg, None); }
+
+    /// Logs with structured fields; the message is only formatted if the level is enabled.
+    fn log_event(level: c_int, msg: impl FnOnce() -> String, fields: impl FnOnce() -> JsonValue) {
+        if logging::enabled(level) { logging::emit(level, LOG_TARGET, &msg(), Some(&fields())); }
+    }
+
+    /// Maps a configured URL to an engine endpoint. The scheme picks the transport:
+    /// ws:// and wss:// use WebSocket /rpc, http:// and https:// the HTTP engine,
+    /// mem:// the embedded datastore; a bare host:port defaults to ws://.
+    /// Returns (endpoint, uses TLS).
+    fn endpoint_url(url: &str) -> (String, bool) {
+        let u = url.trim();
+        for (p, tls) in [("wss://", true), ("https://", true), ("ws://", false), ("http://", false), ("mem://", false)] {
+            if u.starts_with(p) { return (u.to_string(), tls); }
+        }
+        (format!("ws://{}", u), false)
+    }
+
+    /// Verifies the server certificate against a configured name instead of the URL host,
+    /// for servers reached by IP or through a tunnel whose certificate names another host.
+    #[derive(Debug)]
+    struct ServerNameOverride {
+        inner: Arc<rustls::client::WebPkiServerVerifier>,
+        name: rustls::pki_types::ServerName<'static>,
+    }
+
+    impl rustls::client::danger::ServerCertVerifier for ServerNameOverride {
+        fn verify_server_cert(
+            &self,
+            end_entity: &rustls::pki_types::CertificateDer<'_>,
+            intermediates: &[rustls::pki_types::CertificateDer<'_>],
+            _server_name: &rustls::pki_types::ServerName<'_>,
+            ocsp_response: &[u8],
+            now: rustls::pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            self.inner.verify_server_cert(end_entity, intermediates, &self.name, ocsp_response, now)
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &rustls::pki_types::CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            self.inner.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &rustls::pki_types::CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            self.inner.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> { self.inner.supported_verify_schemes() }
+    }
+
+    fn read_pem_certs(path: &str) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>, String> {
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("{}: {}", path, e))?;
+        if certs.is_empty() { return Err(format!("{}: no PEM certificates found", path)); }
+        Ok(certs)
+    }
+
+    /// Builds the rustls client config from PEM files: `ca_file` replaces the built-in
+    /// roots, `cert_file`/`key_file` enable client certificate auth, `server_name`
+    /// overrides the name the server certificate is checked against.
+    fn build_tls(ca_file: Option<&str>, cert_file: Option<&str>, key_file: Option<&str>, server_name: Option<&str>) -> Result<rustls::ClientConfig, String> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = rustls::RootCertStore::empty();
+        match ca_file {
+            Some(path) => {
+                for cert in read_pem_certs(path)? { roots.add(cert).map_err(|e| format!("{}: {}", path, e))?; }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("tls: {}", e))?;
+        let builder = match server_name {
+            Some(name) => {
+                let name = rustls::pki_types::ServerName::try_from(name.to_string()).map_err(|e| format!("server name {:?}: {}", name, e))?;
+                let inner = rustls::client::WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .map_err(|e| format!("tls verifier: {}", e))?;
+                builder.dangerous().with_custom_certificate_verifier(Arc::new(ServerNameOverride { inner, name }))
+            }
+            None => builder.with_root_certificates(roots),
+        };
+        match (cert_file, key_file) {
+            (Some(cert), Some(key)) => {
+                let chain = read_pem_certs(cert)?;
+                let file = std::fs::File::open(key).map_err(|e| format!("{}: {}", key, e))?;
+                let key_der = rustls_pemfile::private_key(&mut std::io::BufReader::new(file))
+                    .map_err(|e| format!("{}: {}", key, e))?
+                    .ok_or_else(|| format!("{}: no PEM private key found", key))?;
+                builder.with_client_auth_cert(chain, key_der).map_err(|e| format!("client certificate: {}", e))
+            }
+            (None, None) => Ok(builder.with_no_client_auth()),
+            _ => Err("client certificate and key must be given together".to_string()),
+        }
+    }
+
+    /// In-memory datastores for `mem://`, one per ns/db pair and shared by every connection
+    /// to that pair: each `any::connect("mem://")` opens a new, empty datastore, and clones of
+    /// one client share its session, so a handle must never switch another's ns/db.
+    /// Credentials are ignored; the embedded engine runs without authentication.
+    /// Dropped by `surreal_shutdown_runtime`.
+    #[cfg(feature = "embedded")]
+    static MEM_DB: tokio::sync::Mutex<std::collections::BTreeMap<(String, String), Surreal<Any>>> =
+        tokio::sync::Mutex::const_new(std::collections::BTreeMap::new());
+
+    #[cfg(feature = "embedded")]
+    async fn connect_embedded(conn: &ConnParams) -> Result<Surreal<Any>, c_int> {
+        let key = (conn.ns.clone(), conn.db.clone());
+        let mut all = MEM_DB.lock().await;
+        if let Some(db) = all.get(&key) { return Ok(db.clone()); }
+        let dbh = match any::connect("mem://").await {
+            Ok(db) => db,
+            Err(e) => {
+                log_error(&format!("embedded datastore start failed: {}", e));
+                set_err_msg(-102, e.to_string());
+                return Err(-102);
+            }
+        };
+        // Selected once, before the client is shared
+        if dbh.use_ns(conn.ns.as_str()).use_db(conn.db.as_str()).await.is_err() { set_err(-105); return Err(-105); }
+        all.insert(key, dbh.clone());
+        Ok(dbh)
+    }
+
+    #[cfg(not(feature = "embedded"))]
+    async fn connect_embedded(conn: &ConnParams) -> Result<Surreal<Any>, c_int> {
+        let msg = format!("cannot open {}: library built without the embedded feature", conn.url);
+        log_error(&msg);
+        set_err_msg(-102, msg);
+        Err(-102)
+    }
+
+    /// Connects, authenticates and selects ns/db within the connect timeout.
+    /// Safe to await from runtime tasks.
+    async fn connect_client(conn: &ConnParams) -> Result<Surreal<Any>, c_int> {
+        match deadline(conn.timeouts.connect(), open_session(conn)).await {
+            Ok(res) => res,
+            Err(e) => {
+                log_event(logging::LOG_ERROR, || format!("connect to {} {}", conn.url, e),
+                    || serde_json::json!({ "url": conn.url, "error": e, "code": -108 }));
+                set_err_msg(-108, e);
+                Err(-108)
+            }
+        }
+    }
+
+    async fn open_session(conn: &ConnParams) -> Result<Surreal<Any>, c_int> {
+        let (endpoint, tls) = endpoint_url(&conn.url);
+        if endpoint.starts_with("mem://") {
+            return connect_embedded(conn).await;
+        }
+        let opened = match (tls, &conn.tls) {
+            (true, Some(cfg)) => any::connect((endpoint, surrealdb::opt::Config::new().rustls(cfg.clone()))).await,
+            _ => any::connect(endpoint).await,
+        };
+        let dbh = match opened {
+            Ok(v) => v,
+            Err(e) => {
+                log_event(logging::LOG_ERROR, || format!("connect to {} failed: {}", conn.url, e),
+                    || serde_json::json!({ "url": conn.url, "error": e.to_string(), "code": -102 }));
+                set_err_msg(-102, e.to_string());
+                return Err(-102);
+            }
+        };
+        let (ns, db) = (conn.ns.as_str(), conn.db.as_str());
+        let signed_in = match &conn.auth {
+            Auth::Root { user, pass } => dbh.signin(Root { username: user, password: pass }).await.map(|_| ()),
+            Auth::Namespace { user, pass } => dbh.signin(Namespace { namespace: ns, username: user, password: pass }).await.map(|_| ()),
+            Auth::Database { user, pass } => dbh.signin(Database { namespace: ns, database: db, username: user, password: pass }).await.map(|_| ()),
+            Auth::Record { access, params, signup } => {
+                let params = if params.is_null() { JsonValue::Object(Default::default()) } else { params.clone() };
+                let creds = || Record { namespace: ns, database: db, access: access.as_str(), params: params.clone() };
+                match dbh.signin(creds()).await {
+                    Ok(_) => Ok(()),
+                    Err(e) if *signup => {
+                        log_debug(&format!("record signin via {} failed ({}); trying signup", access, e));
+                        dbh.signup(creds()).await.map(|_| ())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Auth::Token { .. } => {
+                if dbh.authenticate(conn.token.current()).await.is_err() {
+                    // Most likely expired: fetch a fresh token and retry once
+                    let renewed = match conn.token.renew() {
+                        Some(fresh) => dbh.authenticate(fresh).await.is_ok(),
+                        None => false,
+                    };
+                    if !renewed { set_err(-104); return Err(-104); }
+                }
+                Ok(())
+            }
+        };
+        if let Err(e) = signed_in {
+            log_event(logging::LOG_ERROR, || format!("signin to {} failed: {}", conn.url, e),
+                || serde_json::json!({ "url": conn.url, "error": e.to_string(), "code": -103 }));
+            set_err_msg(-103, e.to_string());
+            return Err(-103);
+        }
+        if let Err(e) = dbh.use_ns(ns).use_db(db).await { set_err_msg(-105, e.to_string()); return Err(-105); }
+        Ok(dbh)
+    }
+
+    /// Blocking wrapper around `connect_client` for the C entry points (must not run on a runtime thread).
+    fn open_client(conn: &ConnParams) -> Result<Surreal<Any>, c_int> {
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => { set_err(-101); return Err(-101); } };
+        rt.block_on(connect_client(conn))
+    }
+
+    /// Opens the first connection and builds the handle around it, or returns NULL.
+    fn connect_handle(conn: ConnParams) -> *mut SurHandle {
+        let conn = Arc::new(conn);
+        match open_client(&conn) {
+            Ok(client) => {
+                let rt = match RUNTIME.get() { Some(rt) => rt, None => { set_err(-101); return std::ptr::null_mut(); } };
+                let pool = Arc::new(Pool::new(conn.clone(), client));
+                let spool = Arc::new(OnceLock::new());
+                let pool_task = rt.spawn(supervise(pool.clone(), spool.clone()));
+                let handle = Box::new(SurHandle {
+                    last_error_code: AtomicI32::new(0),
+                    callback: Mutex::new(None),
+                    pool,
+                    pool_task,
+                    conn,
+                    subs: Mutex::new(HashMap::new()),
+                    last_error: Mutex::new(ErrorDetail::default()),
+                    async_pub: Mutex::new(None),
+                    spool,
+                    claimant: Arc::new(Claimant::new()),
+                });
+                LAST_ERR.store(0, Ordering::Relaxed);
+                let ptr = Box::into_raw(handle);
+                if let Ok(mut all) = HANDLES.lock() { all.push(ptr as usize); }
+                ptr
+            }
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connect(url: *const c_char, ns: *const c_char, db: *const c_char, user: *const c_char, pass: *const c_char) -> *mut SurHandle {
+        let url_s = match cstr_to_str(url) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let ns_s = match cstr_to_str(ns) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let db_s = match cstr_to_str(db) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let user_s = match cstr_to_str(user) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let pass_s = match cstr_to_str(pass) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+
+        let auth = Auth::Root { user: user_s, pass: pass_s };
+        connect_handle(ConnParams::new(url_s, ns_s, db_s, auth, None))
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connect_with_token(url: *const c_char, ns: *const c_char, db: *const c_char, token: *const c_char) -> *mut SurHandle {
+        let url_s = match cstr_to_str(url) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let ns_s = match cstr_to_str(ns) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let db_s = match cstr_to_str(db) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let token_s = match cstr_to_str(token) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+
+        let auth = Auth::Token { token: token_s, token_file: None };
+        connect_handle(ConnParams::new(url_s, ns_s, db_s, auth, None))
+    }
+
+    /// TLS settings passed from C; every field may be NULL.
+    #[repr(C)]
+    pub struct SurTlsOptions {
+        pub ca_file: *const c_char,
+        pub cert_file: *const c_char,
+        pub key_file: *const c_char,
+        pub server_name: *const c_char,
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connect_tls(url: *const c_char, ns: *const c_char, db: *const c_char, user: *const c_char, pass: *const c_char, token: *const c_char, tls: *const SurTlsOptions) -> *mut SurHandle {
+        let url_s = match cstr_to_str(url) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let ns_s = match cstr_to_str(ns) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        let db_s = match cstr_to_str(db) { Some(s) => s.to_string(), None => return std::ptr::null_mut() };
+        // A token wins over user/pass, matching the module configuration
+        let auth = match (cstr_to_str(token).filter(|t| !t.is_empty()), cstr_to_str(user), cstr_to_str(pass)) {
+            (Some(t), _, _) => Auth::Token { token: t.to_string(), token_file: None },
+            (None, Some(u), Some(p)) => Auth::Root { user: u.to_string(), pass: p.to_string() },
+            _ => return std::ptr::null_mut(),
+        };
+        let tls_cfg = if tls.is_null() {
+            None
+        } else {
+            let o = unsafe { &*tls };
+            let field = |p: *const c_char| cstr_to_str(p).map(str::trim).filter(|s| !s.is_empty());
+            match build_tls(field(o.ca_file), field(o.cert_file), field(o.key_file), field(o.server_name)) {
+                Ok(cfg) => Some(cfg),
+                Err(e) => {
+                    log_error(&format!("tls setup failed: {}", e));
+                    set_err_msg(-106, e);
+                    return std::ptr::null_mut();
+                }
+            }
+        };
+        connect_handle(ConnParams::new(url_s, ns_s, db_s, auth, tls_cfg))
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connect_opts(json_options: *const c_char) -> *mut SurHandle {
+        let text = match cstr_to_str(json_options) { Some(s) => s, None => { set_err(-107); return std::ptr::null_mut(); } };
+        let opts: ConnectOpts = match serde_json::from_str(text) {
+            Ok(o) => o,
+            Err(e) => {
+                log_error(&format!("invalid connect options: {}", e));
+                set_err_msg(-107, e.to_string());
+                return std::ptr::null_mut();
+            }
+        };
+        let url = match opts.transport.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(t) if !opts.url.contains("://") => format!("{}://{}", t.to_ascii_lowercase(), opts.url.trim()),
+            _ => opts.url,
+        };
+        let tls = match opts.tls {
+            Some(t) => {
+                let field = |v: &Option<String>| v.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+                let (ca, cert, key, name) = (field(&t.ca_file), field(&t.cert_file), field(&t.key_file), field(&t.server_name));
+                match build_tls(ca.as_deref(), cert.as_deref(), key.as_deref(), name.as_deref()) {
+                    Ok(cfg) => Some(cfg),
+                    Err(e) => {
+                        log_error(&format!("tls setup failed: {}", e));
+                        set_err_msg(-106, e);
+                        return std::ptr::null_mut();
+                    }
+                }
+            }
+            None => None,
+        };
+        connect_handle(ConnParams::new(url, opts.ns, opts.db, opts.auth, tls))
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_publish(handle: *mut SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let rc = publish_one(h, table_or_topic, json_payload);
+        h.pool.metrics.published(rc, 1);
+        rc
+    }
+
+    fn publish_one(h: &SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
+        h.clear_error();
+        let table = match cstr_to_str(table_or_topic) { Some(s) => s, None => return h.fail("publish", -3, SurError::InvalidArgument, "table is NULL or not UTF-8") };
+        let json = match cstr_to_str(json_payload) { Some(s) => s, None => return h.fail("publish", -4, SurError::InvalidArgument, "payload is NULL or not UTF-8") };
+        // Validate table name to be conservative; it is spliced into the statement
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return h.fail("publish", -3, SurError::InvalidArgument, format!("invalid table identifier: {}", table)); }
+
+        let value: JsonValue = match serde_json::from_str(json) { Ok(v) => v, Err(e) => return h.fail("publish", -5, SurError::Json, e.to_string()) };
+
+        let spool_cell = h.spool.clone();
+        let spool = spool_cell.get();
+        let client = match h.pool.checkout() {
+            Some(c) => c,
+            None => return match spool { Some(sp) => spool_doc(h, sp, table, &value), None => h.fail("publish", -2, SurError::NotConnected, "no connection available") },
+        };
+
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return h.fail("publish", -101, SurError::Runtime, "runtime not initialized") };
+
+        // Keep ordering: while anything is spooled, new documents queue behind it and the
+        // supervisor replays them in the background
+        if let Some(sp) = spool.filter(|sp| sp.depth() > 0) { return spool_doc(h, sp, table, &value); }
+
+        // The table is validated above; the document is bound, never spliced
+        let sql = format!("CREATE {} CONTENT $doc", table);
+        let sent = rt.block_on(h.pool.metrics.publish_latency.time(deadline(h.conn.timeouts.publish(), async {
+            client.query(sql.as_str()).bind(("doc", value.clone())).await
+        })));
+        let resp = match sent {
+            Ok(r) => r,
+            Err(e) => {
+                let rc = h.timed_out("publish", &client, e, &sql);
+                return match spool { Some(sp) => spool_doc(h, sp, table, &value), None => rc };
+            }
+        };
+
+        match resp {
+            Ok(r) => match r.check() {
+                Ok(_) => 0,
+                // The server rejected the document: retrying or spooling cannot fix it
+                Err(e) => h.fail_query("publish", -6, e.to_string(), &sql),
+            },
+            Err(e) => {
+                let rc = h.fail_query("publish", -6, e.to_string(), &sql);
+                client.reset();
+                match spool { Some(sp) => spool_doc(h, sp, table, &value), None => rc }
+            }
+        }
+    }
+
+    /// Writes a document that could not be sent to the spool.
+    /// Returns 1 (spooled) or -10 if the spool rejected it.
+    fn spool_doc(h: &SurHandle, sp: &Spool, table: &str, doc: &JsonValue) -> c_int {
+        match sp.append(table, doc) {
+            Ok(()) => 1,
+            Err(e) => h.fail("publish", -10, SurError::Spool, format!("spool write failed: {}", e)),
+        }
+    }
+
+    /// Spools a group of documents with one disk sync; returns each document's outcome.
+    fn spool_rows(sp: &Spool, table: &str, docs: &[JsonValue]) -> Vec<(c_int, Option<String>)> {
+        sp.append_all(table, docs).into_iter().map(|r| match r {
+            Ok(()) => (1, None),
+            Err(e) => (-10, Some(format!("spool write failed: {}", e))),
+        }).collect()
+    }
+
+    /// Publishes spooled documents in order until the spool is empty or the connection fails.
+    /// Rows the server rejects are dropped, since retrying cannot fix them.
+    /// Each request gets `publish_ms`; an expiry stops the replay like a connection failure.
+    async fn replay_spool(client: &Surreal<Any>, sp: &Spool, publish_ms: u64) -> Result<usize, String> {
+        // Another thread is already draining the spool, including what we just appended
+        let _replay = match sp.begin_replay() { Some(g) => g, None => return Ok(0) };
+        let mut replayed = 0usize;
+        loop {
+            let batch = sp.peek(256).map_err(|e| format!("spool read failed: {}", e))?;
+            if batch.records.is_empty() { return Ok(replayed); }
+            // Insert consecutive runs of one table so acknowledgement stays a prefix
+            let mut done = 0usize;
+            while done < batch.records.len() {
+                let table = batch.records[done].table.as_str();
+                let run = batch.records[done..].iter().take_while(|r| r.table == table).count();
+                if table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) {
+                    let docs: Vec<JsonValue> = batch.records[done..done + run].iter().map(|r| r.doc.clone()).collect();
+                    match deadline(publish_ms, insert_rows(client, table, &docs)).await.and_then(|r| r) {
+                        Ok(errors) => {
+                            for e in errors.iter().flatten() { log_warn(&format!("spool replay: row rejected by {}: {}", table, e)); }
+                        }
+                        Err(e) => {
+                            let _ = sp.ack(&batch, done);
+                            return Err(e);
+                        }
+                    }
+                } else {
+                    log_warn(&format!("spool replay: skipping {} rows with invalid table {:?}", run, table));
+                }
+                done += run;
+                replayed += run;
+            }
+            sp.ack(&batch, done).map_err(|e| format!("spool ack failed: {}", e))?;
+        }
+    }
+
+    /// The multi-statement request behind `insert_rows`, one CREATE per object row.
+    struct InsertPlan {
+        sql: String,
+        vars: serde_json::Map<String, JsonValue>,
+        /// Statement index -> row index.
+        stmt_rows: Vec<usize>,
+        /// One slot per input row; rows that are not objects fail up front.
+        errors: Vec<Option<String>>,
+    }
+
+    impl InsertPlan {
+        fn new(table: &str, rows: &[JsonValue]) -> InsertPlan {
+            let mut plan = InsertPlan { sql: String::new(), vars: serde_json::Map::new(), stmt_rows: Vec::new(), errors: vec![None; rows.len()] };
+            for (i, row) in rows.iter().enumerate() {
+                if !row.is_object() { plan.errors[i] = Some("row is not a JSON object".to_string()); continue; }
+                plan.sql.push_str(&format!("CREATE {} CONTENT $r{};\n", table, i));
+                plan.vars.insert(format!("r{}", i), row.clone());
+                plan.stmt_rows.push(i);
+            }
+            plan
+        }
+
+        /// Moves per-statement errors onto the rows they belong to.
+        fn finish(mut self, stmt_errors: impl IntoIterator<Item = (usize, String)>) -> Vec<Option<String>> {
+            for (stmt, err) in stmt_errors {
+                if let Some(&row) = self.stmt_rows.get(stmt) { self.errors[row] = Some(err); }
+            }
+            self.errors
+        }
+    }
+
+    /// Inserts every row with a single multi-statement request (one CREATE per row).
+    /// Statements run independently, so a bad row does not abort the others.
+    /// Returns one slot per input row: `None` if inserted, `Some(error)` otherwise.
+    async fn insert_rows(client: &Surreal<Any>, table: &str, rows: &[JsonValue]) -> Result<Vec<Option<String>>, String> {
+        let mut plan = InsertPlan::new(table, rows);
+        if plan.stmt_rows.is_empty() { return Ok(plan.errors); }
+        let sql = std::mem::take(&mut plan.sql);
+        let vars = std::mem::take(&mut plan.vars);
+        let mut resp = client.query(sql).bind(JsonValue::Object(vars)).await.map_err(|e| format!("{}", e))?;
+        Ok(plan.finish(resp.take_errors().into_iter().map(|(stmt, err)| (stmt, format!("{}", err)))))
+    }
+
     #[no_mangle]
-    pub extern "C" fn surreal_publish(handle: *mut SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
+    pub extern "C" fn surreal_publish_batch(handle: *mut SurHandle, table: *const c_char, json_array: *const c_char) -> c_int {
         if handle.is_null() { return -1; }
-        let table = match cstr_to_str(table_or_topic) { Some(s) => s, None => return -3 };
-        let json = match cstr_to_str(json_payload) { Some(s) => s, None => return -4 };
-
-        let value: JsonValue = match serde_json::from_str(json) { Ok(v) => v, Err(_) => return -5 };
+        let h = unsafe { &*handle };
+        h.clear_error();
+        let table = match cstr_to_str(table) { Some(s) => s, None => return h.fail("publish_batch", -3, SurError::InvalidArgument, "table is NULL or not UTF-8") };
+        let json = match cstr_to_str(json_array) { Some(s) => s, None => return h.fail("publish_batch", -4, SurError::InvalidArgument, "payload is NULL or not UTF-8") };
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return h.fail("publish_batch", -3, SurError::InvalidArgument, format!("invalid table identifier: {}", table)); }
+        let rows: Vec<JsonValue> = match serde_json::from_str(json) { Ok(v) => v, Err(e) => return h.fail("publish_batch", -5, SurError::Json, e.to_string()) };
+
+        if rows.is_empty() { return 0; }
+        let (rc, rejected) = publish_rows(h, table, &rows);
+        let total = rows.len() as u64;
+        if rejected > 0 {
+            h.pool.metrics.published(0, total.saturating_sub(rejected));
+            h.pool.metrics.published(rc, rejected);
+        } else {
+            h.pool.metrics.published(rc, total);
+        }
+        rc
+    }
 
-        let h = unsafe { &mut *handle };
-        if let Ok(mut m) = h.last_error_msg.lock() { m.clear(); }
-        let client = match h.client.as_ref() {
+    /// Sends a batch under the same rules as `publish_one`: while anything is spooled, or
+    /// when the batch cannot be sent, it goes to the spool whole (rc 1). Returns the code
+    /// and how many rows the server rejected (rc -7, never spooled).
+    fn publish_rows(h: &SurHandle, table: &str, rows: &[JsonValue]) -> (c_int, u64) {
+        let spool_cell = h.spool.clone();
+        let spool = spool_cell.get();
+        let client = match h.pool.checkout() {
             Some(c) => c,
-            None => {
-                // Try to reconnect lazily
-                match open_client(&h.url, &h.ns, &h.db, &h.auth) {
-                    Ok(c) => { h.client = Some(c); h.client.as_ref().unwrap() }
-                    Err(_) => return -2,
-                }
-            }
+            None => return match spool { Some(sp) => spool_batch(h, sp, table, rows), None => (h.fail("publish_batch", -2, SurError::NotConnected, "no connection available"), 0) },
         };
 
-        let rt = match RUNTIME.get() { Some(rt) => rt, None => return -1 };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return (h.fail("publish_batch", -101, SurError::Runtime, "runtime not initialized"), 0) };
+        if let Some(sp) = spool.filter(|sp| sp.depth() > 0) { return spool_batch(h, sp, table, rows); }
 
-        let res: Result<(), String> = rt.block_on(async {
-            // Safer path: use SQL with validated table identifier and canonical JSON string
-            // Validate table name to be conservative
-            if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return Err("invalid table identifier".to_string()); }
-            let json_str = match serde_json::to_string(&value) { Ok(s) => s, Err(e) => return Err(format!("json stringify failed: {}", e)) };
-            let sql = format!("CREATE {} CONTENT {}", table, json_str);
-            match client.query(sql).await { Ok(_) => Ok(()), Err(e) => Err(format!("{}", e)) }
-        });
+        let statement = format!("CREATE {} CONTENT $r0; ... ({} rows)", table, rows.len());
+        let res = match rt.block_on(h.pool.metrics.publish_latency.time(deadline(h.conn.timeouts.publish(), insert_rows(&client, table, rows)))) {
+            Ok(r) => r,
+            Err(e) => {
+                let rc = h.timed_out("publish_batch", &client, e, &statement);
+                return match spool { Some(sp) => spool_batch(h, sp, table, rows), None => (rc, 0) };
+            }
+        };
 
         match res {
+            Ok(errors) => {
+                // Per-row failures are reported as a JSON array in the last error text
+                let failed: Vec<JsonValue> = errors.iter().enumerate()
+                    .filter_map(|(i, e)| e.as_ref().map(|e| serde_json::json!({ "index": i, "error": e })))
+                    .collect();
+                let n = failed.len() as u64;
+                if n == 0 { return (0, 0); }
+                (h.fail("publish_batch", -7, SurError::Query, JsonValue::Array(failed).to_string()), n)
+            }
+            Err(e) => {
+                let rc = h.fail_query("publish_batch", -6, e, &statement);
+                client.reset();
+                match spool { Some(sp) => spool_batch(h, sp, table, rows), None => (rc, 0) }
+            }
+        }
+    }
+
+    /// Writes a batch that could not be sent to the spool with one disk sync.
+    /// Returns 1 (spooled), or -10 with the rows the spool rejected in the last error text.
+    fn spool_batch(h: &SurHandle, sp: &Spool, table: &str, rows: &[JsonValue]) -> (c_int, u64) {
+        let failed: Vec<JsonValue> = spool_rows(sp, table, rows).into_iter().enumerate()
+            .filter_map(|(i, (_, e))| e.map(|e| serde_json::json!({ "index": i, "error": e })))
+            .collect();
+        if failed.is_empty() { return (1, 0); }
+        (h.fail("publish_batch", -10, SurError::Spool, JsonValue::Array(failed).to_string()), 0)
+    }
+
+    fn notify_publish(cb: SurPublishCb, user: usize, table: &str, raw: &str, rc: c_int, err: Option<&str>) {
+        let cb_fn = match cb { Some(f) => f, None => return };
+        let ctable = match std::ffi::CString::new(table) { Ok(s) => s, Err(_) => return };
+        let cjson = match std::ffi::CString::new(raw) { Ok(s) => s, Err(_) => return };
+        let cerr = err.and_then(|e| std::ffi::CString::new(e).ok());
+        cb_fn(rc, ctable.as_ptr(), cjson.as_ptr(), cerr.as_ref().map_or(std::ptr::null(), |e| e.as_ptr()), user as *mut c_void);
+    }
+
+    /// Drains the async queue: waits for one document, greedily takes up to `max_batch`,
+    /// inserts them per table with `insert_rows`, then reports each outcome to the callback.
+    /// Uses its own connection so a slow insert never holds the handle's client.
+    /// When a spool is enabled it is replayed first (also on idle ticks), and documents
+    /// that cannot be sent, or would overtake spooled ones, are spooled (reported as rc 1).
+    async fn drain_async(mut rx: mpsc::Receiver<AsyncMsg>, depth: Arc<AtomicUsize>, pool: Arc<Pool>, max_batch: usize, cb: SurPublishCb, user: usize, spool: Arc<OnceLock<Spool>>) {
+        let mut client: Option<Surreal<Any>> = None;
+        let mut backoff = pool.backoff();
+        let mut retry_at = Instant::now();
+        let mut token_gen = pool.conn.token.generation.subscribe();
+        let mut batch: Vec<AsyncMsg> = Vec::with_capacity(max_batch);
+        loop {
+            let first = match tokio::time::timeout(Duration::from_secs(1), rx.recv()).await {
+                Ok(Some(m)) => Some(m),
+                Ok(None) => break,
+                Err(_) => None, // idle tick
+            };
+            let spooled = spool.get().is_some_and(|sp| sp.depth() > 0);
+            if first.is_none() && !spooled { continue; }
+            if let Some(first) = first {
+                batch.push(first);
+                while batch.len() < max_batch {
+                    match rx.try_recv() { Ok(m) => batch.push(m), Err(_) => break }
+                }
+                depth.fetch_sub(batch.len(), Ordering::Relaxed);
+            }
+
+            if token_gen.has_changed().unwrap_or(false) {
+                token_gen.borrow_and_update();
+                if let Some(c) = client.as_ref() {
+                    if c.authenticate(pool.conn.token.current()).await.is_err() { client = None; }
+                }
+            }
+            if client.is_none() && Instant::now() >= retry_at {
+                client = connect_client(&pool.conn).await.ok();
+                if client.is_some() {
+                    backoff.reset();
+                } else {
+                    let delay = backoff.next_delay();
+                    retry_at = Instant::now() + delay;
+                    log_warn(&format!("async publish: reconnect failed; next attempt in {} ms", delay.as_millis()));
+                }
+            }
+            if let (Some(c), Some(sp)) = (client.as_ref(), spool.get()) {
+                if sp.depth() > 0 {
+                    match replay_spool(c, sp, pool.conn.timeouts.publish()).await {
+                        Ok(n) if n > 0 => log_info(&format!("spool replayed {} documents", n)),
+                        Ok(_) => {}
+                        Err(e) => { log_warn(&format!("spool replay stopped: {}", e)); client = None; }
+                    }
+                }
+            }
+            if batch.is_empty() { continue; }
+
+            // Split into runs of consecutive documents for the same table, so
+            // inserts reach the database in arrival order even across tables
+            let mut groups: Vec<(String, Vec<JsonValue>, Vec<String>)> = Vec::new();
+            for m in batch.drain(..) {
+                match groups.last_mut() {
+                    Some((t, docs, raws)) if *t == m.table => { docs.push(m.doc); raws.push(m.raw); }
+                    _ => groups.push((m.table, vec![m.doc], vec![m.raw])),
+                }
+            }
+
+            for (table, docs, raws) in groups {
+                let sp = spool.get();
+                let outcome: Vec<(c_int, Option<String>)> = match (client.as_ref(), sp) {
+                    (_, Some(sp)) if sp.depth() > 0 => spool_rows(sp, &table, &docs),
+                    (None, Some(sp)) => spool_rows(sp, &table, &docs),
+                    (None, None) => vec![(-2, Some("not connected".to_string())); raws.len()],
+                    (Some(c), _) => match pool.metrics.publish_latency.time(deadline(pool.conn.timeouts.publish(), insert_rows(c, &table, &docs))).await.and_then(|r| r) {
+                        Ok(errors) => errors.into_iter().map(|e| match e { None => (0, None), Some(e) => (-7, Some(e)) }).collect(),
+                        Err(e) => {
+                            log_event(logging::LOG_WARN, || format!("async publish to {} failed: {}", table, e),
+                                || serde_json::json!({ "table": table, "docs": docs.len(), "error": e }));
+                            pool.note_error(&e);
+                            client = None;
+                            match sp { Some(sp) => spool_rows(sp, &table, &docs), None => vec![(-6, Some(e)); raws.len()] }
+                        }
+                    },
+                };
+                for (raw, (rc, err)) in raws.iter().zip(outcome) {
+                    pool.metrics.published(rc, 1);
+                    match rc {
+                        0 | 1 => {}
+                        -2 => pool.metrics.failure(SurError::NotConnected),
+                        -7 => pool.metrics.failure(SurError::Query),
+                        -10 => pool.metrics.failure(SurError::Spool),
+                        _ => pool.metrics.failure(err.as_deref().map_or(SurError::Query, classify)),
+                    }
+                    notify_publish(cb, user, &table, raw, rc, err.as_deref());
+                }
+            }
+            if client.is_none() { sleep(Duration::from_millis(200)).await; }
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_publish_async_start(handle: *mut SurHandle, queue_capacity: u32, max_batch: u32, cb: SurPublishCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return h.fail("publish_async_start", -101, SurError::Runtime, "runtime not initialized") };
+        let mut guard = match h.async_pub.lock() { Ok(g) => g, Err(_) => return h.fail("publish_async_start", -8, SurError::Internal, "async publisher lock poisoned") };
+        if guard.is_some() { return 0; }
+        let capacity = if queue_capacity == 0 { 10000 } else { queue_capacity as usize };
+        let max_batch = if max_batch == 0 { 256 } else { max_batch.min(5000) as usize };
+        let (tx, rx) = mpsc::channel(capacity);
+        let depth = Arc::new(AtomicUsize::new(0));
+        let task = rt.spawn(drain_async(rx, depth.clone(), h.pool.clone(), max_batch, cb, user_data as usize, h.spool.clone()));
+        *guard = Some(AsyncPublisher { tx, depth, task });
+        log_info(&format!("async publish started (queue {}, batch {})", capacity, max_batch));
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_publish_async(handle: *mut SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let rc = enqueue_async(h, table_or_topic, json_payload);
+        // Queued documents are counted when the queue reports their outcome
+        if rc != 0 { h.pool.metrics.published(rc, 1); }
+        rc
+    }
+
+    fn enqueue_async(h: &SurHandle, table_or_topic: *const c_char, json_payload: *const c_char) -> c_int {
+        let table = match cstr_to_str(table_or_topic) { Some(s) => s, None => return h.fail("publish_async", -3, SurError::InvalidArgument, "table is NULL or not UTF-8") };
+        let json = match cstr_to_str(json_payload) { Some(s) => s, None => return h.fail("publish_async", -4, SurError::InvalidArgument, "payload is NULL or not UTF-8") };
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return h.fail("publish_async", -3, SurError::InvalidArgument, format!("invalid table identifier: {}", table)); }
+        let doc: JsonValue = match serde_json::from_str(json) { Ok(v) => v, Err(e) => return h.fail("publish_async", -5, SurError::Json, e.to_string()) };
+
+        let guard = match h.async_pub.lock() { Ok(g) => g, Err(_) => return h.fail("publish_async", -8, SurError::Internal, "async publisher lock poisoned") };
+        let publisher = match guard.as_ref() { Some(p) => p, None => return h.fail("publish_async", -8, SurError::InvalidArgument, "async publish not started") };
+        publisher.depth.fetch_add(1, Ordering::Relaxed);
+        match publisher.tx.try_send(AsyncMsg { table: table.to_string(), doc, raw: json.to_string() }) {
             Ok(()) => 0,
             Err(e) => {
-                if let Ok(mut msg) = h.last_error_msg.lock() { *msg = e; }
-                h.last_error_code.store(-6, Ordering::Relaxed);
-                h.client = None;
-                -6
+                publisher.depth.fetch_sub(1, Ordering::Relaxed);
+                match e {
+                    // Overflow goes to the spool when one is enabled
+                    mpsc::error::TrySendError::Full(m) => match h.spool.get() {
+                        Some(sp) => spool_doc(h, sp, &m.table, &m.doc),
+                        None => h.fail("publish_async", -9, SurError::QueueFull, "async publish queue is full"),
+                    },
+                    mpsc::error::TrySendError::Closed(_) => h.fail("publish_async", -8, SurError::Internal, "async publisher stopped"),
+                }
             }
         }
     }
 
     #[no_mangle]
-    pub extern "C" fn surreal_close(handle: *mut SurHandle) { if handle.is_null() { return; } let _ = unsafe { Box::from_raw(handle) }; }
+    pub extern "C" fn surreal_publish_queue_depth(handle: *mut SurHandle) -> i64 {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        match h.async_pub.lock() {
+            Ok(g) => g.as_ref().map_or(0, |p| p.depth.load(Ordering::Relaxed) as i64),
+            Err(_) => -1,
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_enable(handle: *mut SurHandle, dir: *const c_char, max_bytes: u64, max_age_secs: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let dir = match cstr_to_str(dir) { Some(s) if !s.is_empty() => s, _ => return h.fail("spool_enable", -3, SurError::InvalidArgument, "spool directory is empty") };
+        if h.spool.get().is_some() { return 0; }
+        match Spool::open(dir, max_bytes, max_age_secs as u64) {
+            Ok(sp) => {
+                let pending = sp.depth();
+                let _ = h.spool.set(sp);
+                log_info(&format!("spool enabled at {} ({} documents pending)", dir, pending));
+                0
+            }
+            Err(e) => h.fail("spool_enable", -2, SurError::Spool, format!("spool open failed: {}", e)),
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_depth(handle: *mut SurHandle) -> i64 {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        h.spool.get().map_or(0, |sp| sp.depth() as i64)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_bytes(handle: *mut SurHandle) -> i64 {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        h.spool.get().map_or(0, |sp| sp.bytes() as i64)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_spool_dropped(handle: *mut SurHandle) -> i64 {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        h.spool.get().map_or(0, |sp| sp.dropped() as i64)
+    }
+
+    /// Closes the async queue and waits up to `timeout` for queued documents to be flushed.
+    fn stop_async_publisher(h: &SurHandle, timeout: Duration) {
+        let publisher = match h.async_pub.lock() { Ok(mut g) => g.take(), Err(_) => None };
+        let Some(AsyncPublisher { tx, depth, mut task }) = publisher else { return };
+        drop(tx);
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return };
+        let flushed = rt.block_on(async { tokio::time::timeout(timeout, &mut task).await.is_ok() });
+        if !flushed {
+            task.abort();
+            log_warn(&format!("async publish flush timed out; {} documents dropped", depth.load(Ordering::Relaxed)));
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_close(handle: *mut SurHandle) {
+        if handle.is_null() { return; }
+        if let Ok(mut all) = HANDLES.lock() { all.retain(|&a| a != handle as usize); }
+        let h = unsafe { Box::from_raw(handle) };
+        stop_subscriptions(&h);
+        stop_async_publisher(&h, Duration::from_secs(5));
+        h.pool_task.abort();
+    }
+
+    /// Stops every subscription task on the handle; callbacks are not invoked afterwards.
+    fn stop_subscriptions(h: &SurHandle) {
+        let subs: Vec<Sub> = match h.subs.lock() { Ok(mut m) => m.drain().map(|(_, s)| s).collect(), Err(_) => return };
+        for sub in subs {
+            sub.stop.store(true, Ordering::Relaxed);
+            sub.handle.abort();
+        }
+    }
 
     #[no_mangle]
     pub extern "C" fn surreal_last_error_code(handle: *mut SurHandle) -> c_int { if handle.is_null() { return -1; } let h = unsafe { &*handle }; h.last_error_code.load(Ordering::Relaxed) }
@@ -422,11 +2173,29 @@ mod api {
     #[no_mangle]
     pub extern "C" fn surreal_last_error_global() -> c_int { LAST_ERR.load(Ordering::Relaxed) }
 
+    /// Returns the last failure as JSON (see the header); NULL handle reports the last connect
+    /// failure. Free the result with `surreal_free_string`.
+    #[no_mangle]
+    pub extern "C" fn surreal_last_error_json(handle: *mut SurHandle) -> *mut c_char {
+        let json = if handle.is_null() {
+            let msg = LAST_ERR_MSG.lock().map(|m| m.clone()).unwrap_or_default();
+            detail_json(LAST_ERR.load(Ordering::Relaxed), None, "connect", &msg, None)
+        } else {
+            let h = unsafe { &*handle };
+            let code = h.last_error_code.load(Ordering::Relaxed);
+            match h.last_error.lock() {
+                Ok(d) => detail_json(code, d.kind, d.op, &d.message, d.statement.as_deref()),
+                Err(_) => detail_json(code, Some(SurError::Internal), "", "error state lock poisoned", None),
+            }
+        };
+        CString::new(json).unwrap_or_default().into_raw()
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_last_error_text(handle: *mut SurHandle, buf: *mut c_char, len: u32) -> c_int {
         if handle.is_null() || buf.is_null() || len == 0 { return -1; }
         let h = unsafe { &*handle };
-        let s = match h.last_error_msg.lock() { Ok(g) => g.clone(), Err(_) => String::new() };
+        let s = match h.last_error.lock() { Ok(g) => g.message.clone(), Err(_) => String::new() };
         let bytes = s.as_bytes();
         let n = (bytes.len().min((len - 1) as usize)) as usize;
         unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, n) };
@@ -437,29 +2206,596 @@ mod api {
     #[no_mangle]
     pub extern "C" fn surreal_is_stub() -> c_int { 0 }
 
+    /// Plain callback: INFO and above, fields appended to the text. Replaces any log handler.
     #[no_mangle]
     pub extern "C" fn surreal_set_logger(cb: SurLogCb, user_data: *mut c_void) -> c_int {
-        let m = LOGGER.get_or_init(|| Mutex::new(None));
-        match m.lock() {
-            Ok(mut g) => { *g = Some((cb, user_data as usize)); 0 }
-            Err(_) => -1,
+        logging::set_plain(cb, user_data as usize);
+        0
+    }
+
+    /// Leveled callback carrying target and fields as JSON, for FFI messages and the SDK's
+    /// `tracing` events; `max_level` (1 error .. 5 trace) drops anything more verbose before
+    /// it is formatted. Replaces any plain logger; NULL turns logging off.
+    #[no_mangle]
+    pub extern "C" fn surreal_set_log_handler(cb: SurLogHandlerCb, max_level: c_int, user_data: *mut c_void) -> c_int {
+        logging::set_leveled(cb, max_level, user_data as usize);
+        0
+    }
+
+    /// Claims rows that became `new` while no LIVE query was running (they are never
+    /// notified), oldest `created_at` first. Rows also seen on the stream are delivered once,
+    /// since only one claim can succeed. Pages continue after the last row of the previous
+    /// one (by `created_at`, then id), so rows whose claim failed are not fetched again.
+    async fn catch_up(dbh: &Surreal<Any>, metrics: &Metrics, claimant: &Claimant, table: &str, filter: Option<&Filter>, cb: SurCommandCb, user: usize) {
+        use surrealdb::value::from_value as from_sur_value;
+
+        #[derive(serde::Deserialize)]
+        struct Key { id: RecordId }
+
+        // Only the condition is spliced, and it was validated and probed by
+        // surreal_subscribe_where; table, cursor and page size are bound
+        let sql = format!(
+            "LET $_ts = IF $_after != NONE THEN (SELECT VALUE created_at FROM ONLY $_after) END;\n\
+             SELECT * FROM type::table($_tb) WHERE status = 'new'{} \
+             AND ($_after = NONE OR created_at > $_ts OR (created_at = $_ts AND id > $_after)) \
+             ORDER BY created_at, id LIMIT $_n",
+            filter.map_or(String::new(), |f| format!(" AND ({})", f.condition)));
+        let mut after: Option<RecordId> = None;
+        let mut total = 0;
+        for _ in 0..CATCH_UP_MAX_PAGES {
+            let mut query = dbh.query(sql.as_str());
+            if let Some(f) = filter { query = query.bind(JsonValue::Object(f.vars.clone())); }
+            let page = query
+                .bind(("_tb", table.to_string()))
+                .bind(("_n", CATCH_UP_PAGE as i64))
+                .bind(("_after", after.clone()))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|mut resp| take_rows(&mut resp, 1));
+            let rows = match page {
+                Ok(rows) => rows,
+                Err(e) => { log_warn(&format!("catch-up query failed on {}: {}", table, e)); break; }
+            };
+            let n = rows.len();
+            let last = rows.last().cloned().and_then(|row| from_sur_value::<Key>(row).ok());
+            for row in rows {
+                claim_and_deliver(dbh, metrics, claimant, table, row, cb, user).await;
+            }
+            total += n;
+            if n < CATCH_UP_PAGE { break; }
+            match last {
+                Some(key) => after = Some(key.id),
+                None => break,
+            }
+        }
+        if total > 0 { log_info(&format!("caught up {} rows on {} created while disconnected", total, table)); }
+    }
+
+    /// Recovers claims whose lease ran out (the claimant crashed or hung): back to `new`, or
+    /// `failed` once `attempts` reaches the limit. Rows without a lease are left alone.
+    async fn reap_expired(dbh: &Surreal<Any>, metrics: &Metrics, claimant: &Claimant, table: &str) {
+        let reaped = dbh
+            .query("UPDATE type::table($tb) SET status = IF (attempts ?? 0) + 1 >= $max THEN 'failed' ELSE 'new' END, \
+                    attempts = (attempts ?? 0) + 1, claimed_by = NONE, lease_until = NONE \
+                    WHERE status = 'processing' AND lease_until != NONE AND lease_until < time::now() RETURN id")
+            .bind(("tb", table.to_string()))
+            .bind(("max", claimant.max_attempts.load(Ordering::Relaxed) as i64))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|mut resp| take_rows(&mut resp, 0));
+        match reaped {
+            Ok(rows) if rows.is_empty() => {}
+            Ok(rows) => {
+                metrics.claims_expired.fetch_add(rows.len() as u64, Ordering::Relaxed);
+                log_event(logging::LOG_WARN, || format!("recovered {} expired claims on {}", rows.len(), table),
+                    || serde_json::json!({ "table": table, "count": rows.len() }));
+            }
+            Err(e) => log_warn(&format!("claim recovery failed on {}: {}", table, e)),
+        }
+    }
+
+    /// Claims a row with `status = 'new'` and passes it to the subscriber callback,
+    /// with the id rendered as a string. Rows in any other state are skipped.
+    /// The claim is a compare-and-set on the server: when several nodes see the same row,
+    /// only the one whose update matched delivers it.
+    async fn claim_and_deliver(dbh: &Surreal<Any>, metrics: &Metrics, claimant: &Claimant, table: &str, row: surrealdb::Value, cb: SurCommandCb, user: usize) {
+        use surrealdb::value::from_value as from_sur_value;
+
+        // Serialize to JSON for callback shaping
+        let mut json = sur_to_json(&row);
+
+        // Extract id + status using typed conversion for reliability
+        #[derive(serde::Deserialize)]
+        struct IdOnly { id: RecordId, status: Option<String> }
+        let idonly: IdOnly = match from_sur_value::<IdOnly>(row) {
+            Ok(v) => v,
+            Err(e) => { log_warn(&format!("subscribe could not parse id/status on {}: {}", table, e)); return; }
+        };
+        // gate on status == 'new'
+        if !matches!(idonly.status.as_deref(), Some(st) if st.eq_ignore_ascii_case("new")) { return; }
+
+        // Render the id as SurrealQL (table:key, escaped as needed) so it parses back in surreal_update
+        let id_str = idonly.id.to_string();
+        // Claim the record only if it is still new, recording who holds it and until when
+        #[derive(serde::Deserialize)]
+        struct Claimed { status: Option<String>, claimed_by: Option<String> }
+        let node = claimant.node();
+        let lease_ms = claimant.lease_ms.load(Ordering::Relaxed);
+        let claimed = dbh
+            .query("UPDATE $rid SET status = 'processing', claimed_at = time::now(), claimed_by = $node, \
+                    lease_until = time::now() + duration::from::millis($lease_ms) WHERE status = 'new' RETURN AFTER")
+            .bind(("rid", idonly.id.clone()))
+            .bind(("node", node.clone()))
+            .bind(("lease_ms", lease_ms as i64))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|mut resp| resp.take::<Vec<Claimed>>(0).map_err(|e| e.to_string()));
+        match claimed {
+            Ok(rows) if !rows.iter().any(|r| r.status.as_deref() == Some("processing") && r.claimed_by.as_deref() == Some(node.as_str())) => {
+                // Another node's update matched first
+                metrics.claim_conflicts.fetch_add(1, Ordering::Relaxed);
+                log_debug(&format!("subscribe claim of {} lost to another node", id_str));
+            }
+            Ok(_) => {
+                metrics.claims.fetch_add(1, Ordering::Relaxed);
+                // Ensure id is a JSON string for the callback
+                if let Some(obj) = json.as_object_mut() { obj.insert("id".to_string(), serde_json::Value::String(id_str)); }
+                if let Ok(txt) = serde_json::to_string(&json) {
+                    if let Ok(ctopic) = CString::new(table) {
+                        if let Ok(cjson) = CString::new(txt) {
+                            if let Some(cb_fn) = cb {
+                                // The callback runs the command; keep the claim alive meanwhile.
+                                // block_in_place hands this worker's queue to another thread, so
+                                // the renewal task is not stuck behind a long callback.
+                                let renewal = tokio::spawn(renew_lease(dbh.clone(), idonly.id, node, lease_ms));
+                                tokio::task::block_in_place(|| cb_fn(ctopic.as_ptr(), cjson.as_ptr(), user as *mut c_void));
+                                renewal.abort();
+                            }
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                metrics.claim_failures.fetch_add(1, Ordering::Relaxed);
+                log_event(logging::LOG_WARN, || format!("subscribe claim failed for {}: {}", id_str, e),
+                    || serde_json::json!({ "table": table, "id": id_str, "error": e.to_string() }));
+            }
+        }
+    }
+
+    /// Extends the lease on a claimed row every third of `lease_ms` until aborted, as long as
+    /// this node still holds the claim, so a command that outlives its lease is not reaped
+    /// and delivered again elsewhere.
+    async fn renew_lease(dbh: Surreal<Any>, rid: RecordId, node: String, lease_ms: u64) {
+        let every = Duration::from_millis((lease_ms / 3).max(100));
+        loop {
+            sleep(every).await;
+            let renewed = dbh
+                .query("UPDATE $rid SET lease_until = time::now() + duration::from::millis($lease_ms) \
+                        WHERE status = 'processing' AND claimed_by = $node")
+                .bind(("rid", rid.clone()))
+                .bind(("node", node.clone()))
+                .bind(("lease_ms", lease_ms as i64))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|resp| resp.check().map_err(|e| e.to_string()));
+            if let Err(e) = renewed { log_warn(&format!("lease renewal failed for {}: {}", rid, e)); }
+        }
+    }
+
+    /// Server-side condition for a LIVE subscription (`surreal_subscribe_where`).
+    #[derive(Clone)]
+    struct Filter {
+        condition: String,
+        vars: serde_json::Map<String, JsonValue>,
+    }
+
+    const ACTION_CREATE: c_int = 1;
+    const ACTION_UPDATE: c_int = 2;
+    const ACTION_DELETE: c_int = 3;
+
+    // Subscription kinds; a topic can have one subscription of each
+    const SUB_COMMAND: c_int = 1;
+    const SUB_RAW: c_int = 2;
+    const SUB_CHANGEFEED: c_int = 3;
+
+    /// What a subscription does with each notification.
+    #[derive(Clone, Copy)]
+    enum Delivery {
+        /// Claim rows with `status = 'new'`, then pass them to the command callback.
+        Claim(SurCommandCb),
+        /// Pass every create, update and delete through unchanged, without claiming.
+        Raw(SurChangeCb),
+    }
+
+    impl Delivery {
+        fn kind(&self) -> c_int {
+            match self { Delivery::Claim(_) => SUB_COMMAND, Delivery::Raw(_) => SUB_RAW }
+        }
+    }
+
+    /// Renders a notification record as JSON with its id as a `table:key` string.
+    fn record_json(row: surrealdb::Value) -> JsonValue {
+        #[derive(serde::Deserialize)]
+        struct IdOnly { id: RecordId }
+        let mut json = sur_to_json(&row);
+        if let Ok(idonly) = surrealdb::value::from_value::<IdOnly>(row) {
+            if let Some(obj) = json.as_object_mut() { obj.insert("id".to_string(), JsonValue::String(idonly.id.to_string())); }
+        }
+        json
+    }
+
+    fn deliver_change(table: &str, action: c_int, row: surrealdb::Value, cb: SurChangeCb, user: usize) {
+        let Some(cb_fn) = cb else { return };
+        let (Ok(ctopic), Ok(cjson)) = (CString::new(table), CString::new(record_json(row).to_string())) else { return };
+        cb_fn(ctopic.as_ptr(), action, cjson.as_ptr(), user as *mut c_void);
+    }
+
+    /// Untyped notifications: the SDK hands these over as they arrive, while a typed
+    /// `Notification<surrealdb::Value>` stream fails to decode every record.
+    type LiveStream = futures::stream::BoxStream<'static, surrealdb::Notification<surrealdb::Value>>;
+
+    /// Replaces each `$name` in `condition` that names one of `vars` with the value as a
+    /// SurrealQL literal. Quoted strings and identifiers are left alone, as are other
+    /// parameters such as `$before` and `$after`.
+    fn inline_vars(condition: &str, vars: &serde_json::Map<String, JsonValue>) -> String {
+        let mut out = String::with_capacity(condition.len());
+        let mut chars = condition.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' | '"' | '`' | '⟨' => {
+                    let close = if c == '⟨' { '⟩' } else { c };
+                    out.push(c);
+                    while let Some(q) = chars.next() {
+                        out.push(q);
+                        if q == '\\' {
+                            if let Some(escaped) = chars.next() { out.push(escaped); }
+                        } else if q == close {
+                            break;
+                        }
+                    }
+                }
+                '$' => {
+                    let mut name = String::new();
+                    while let Some(&n) = chars.peek().filter(|n| n.is_ascii_alphanumeric() || **n == '_') {
+                        name.push(n);
+                        chars.next();
+                    }
+                    match vars.get(&name).and_then(|v| surrealdb::sql::to_value(v.clone()).ok()) {
+                        Some(value) => { out.push('('); out.push_str(&value.to_string()); out.push(')'); }
+                        None => { out.push('$'); out.push_str(&name); }
+                    }
+                }
+                c => out.push(c),
+            }
         }
+        out
+    }
+
+    /// Starts `LIVE SELECT * FROM table WHERE condition`. The server keeps the statement but
+    /// not the session's variables, so a bound `$name` would be NONE whenever a change is
+    /// matched; the filter's variables are written into the condition as literals instead.
+    async fn live_where(dbh: &Surreal<Any>, table: &str, filter: &Filter) -> surrealdb::Result<LiveStream> {
+        use futures::StreamExt;
+        let mut resp = dbh.query(format!("LIVE SELECT * FROM {} WHERE {}", table, inline_vars(&filter.condition, &filter.vars)))
+            .await?
+            .check()?;
+        Ok(resp.stream::<surrealdb::Value>(0)?.boxed())
     }
 
     #[no_mangle]
     pub extern "C" fn surreal_subscribe(handle: *mut SurHandle, _topic: *const c_char, cb: SurCommandCb, user_data: *mut c_void) -> c_int {
         if handle.is_null() { return -1; }
         let h = unsafe { &*handle };
-        let table = match cstr_to_str(_topic) { Some(s) => s.to_string(), None => return -3 };
-        log_info(&format!("subscribe started on {}", table));
+        let table = match cstr_to_str(_topic) { Some(s) => s.to_string(), None => return h.fail("subscribe", -3, SurError::InvalidArgument, "topic is NULL or not UTF-8") };
+        match subscribe(h, table, None, Delivery::Claim(cb), user_data) {
+            Ok(()) => 0,
+            Err((kind, msg)) => h.fail("subscribe", if kind == SurError::Runtime { -5 } else { -4 }, kind, msg),
+        }
+    }
+
+    /// Validates the table and condition of a filtered subscription and probes the condition
+    /// with `SELECT ... LIMIT 0` and its variables bound, so a bad one is rejected now rather
+    /// than retried forever in the background. Without `required`, an empty condition means no
+    /// filter. Errors are recorded for `op` and returned as category codes.
+    fn check_filter(h: &SurHandle, op: &'static str, topic: *const c_char, condition: *const c_char, vars_json: *const c_char, required: bool) -> Result<(String, Option<Filter>), c_int> {
+        let table = match cstr_to_str(topic) { Some(s) => s.to_string(), None => return Err(h.fail_kind(op, SurError::InvalidArgument, "topic is NULL or not UTF-8")) };
+        // The table is spliced into LIVE SELECT, so only plain identifiers are accepted
+        if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(h.fail_kind(op, SurError::InvalidArgument, format!("invalid table identifier: {}", table)));
+        }
+        // One expression, not a statement list: values belong in vars_json
+        let condition = match cstr_to_str(condition).map(str::trim) {
+            None | Some("") if !required => return Ok((table, None)),
+            Some(c) if !c.is_empty() && !c.contains(';') => c.to_string(),
+            _ => return Err(h.fail_kind(op, SurError::InvalidArgument, "condition is empty or contains ';'")),
+        };
+        let vars = match parse_vars(vars_json) { Some(v) => v, None => return Err(h.fail_kind(op, SurError::Json, "vars must be a JSON object")) };
+        let filter = Filter { condition, vars };
+        if let (Some(client), Some(rt)) = (h.pool.checkout(), RUNTIME.get()) {
+            let statement = format!("SELECT * FROM {} WHERE {} LIMIT 0", table, filter.condition);
+            let checked = rt.block_on(deadline(h.conn.timeouts.query(), async {
+                match client.query(statement.as_str()).bind(JsonValue::Object(filter.vars.clone())).await {
+                    Ok(mut resp) => match resp.take_errors().into_values().next() {
+                        Some(e) => Err(e.to_string()),
+                        None => Ok(()),
+                    },
+                    Err(e) => Err(e.to_string()),
+                }
+            }));
+            match checked {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Err(h.fail_query_kind(op, e, &statement)),
+                Err(e) => return Err(h.timed_out_kind(op, &client, e, &statement)),
+            }
+        }
+        Ok((table, Some(filter)))
+    }
+
+    /// Like `surreal_subscribe`, but the server only sends rows matching `condition`
+    /// (a SurrealQL expression, values passed as `$name` through `vars_json`).
+    /// Returns 0 or a category code.
+    #[no_mangle]
+    pub extern "C" fn surreal_subscribe_where(handle: *mut SurHandle, topic: *const c_char, condition: *const c_char, vars_json: *const c_char, cb: SurCommandCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        h.clear_error();
+        let (table, filter) = match check_filter(h, "subscribe_where", topic, condition, vars_json, true) { Ok(v) => v, Err(rc) => return rc };
+        match subscribe(h, table, filter, Delivery::Claim(cb), user_data) {
+            Ok(()) => 0,
+            Err((kind, msg)) => h.fail_kind("subscribe_where", kind, msg),
+        }
+    }
+
+    /// Delivers every create, update and delete on `topic` (optionally filtered like
+    /// `surreal_subscribe_where`) to `cb` with its action and full record. Nothing is claimed.
+    /// Returns 0 or a category code.
+    #[no_mangle]
+    pub extern "C" fn surreal_subscribe_raw(handle: *mut SurHandle, topic: *const c_char, condition: *const c_char, vars_json: *const c_char, cb: SurChangeCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        h.clear_error();
+        if cb.is_none() { return h.fail_kind("subscribe_raw", SurError::InvalidArgument, "callback is NULL"); }
+        // Polling only sees current rows, so it cannot report updates and deletes faithfully
+        if h.conn.is_http() { return h.fail_kind("subscribe_raw", SurError::Unsupported, "raw subscriptions need a WebSocket connection"); }
+        let (table, filter) = match check_filter(h, "subscribe_raw", topic, condition, vars_json, false) { Ok(v) => v, Err(rc) => return rc };
+        match subscribe(h, table, filter, Delivery::Raw(cb), user_data) {
+            Ok(()) => 0,
+            Err((kind, msg)) => h.fail_kind("subscribe_raw", kind, msg),
+        }
+    }
+
+    /// Change-feed entries read per `SHOW CHANGES` query.
+    const CHANGEFEED_BATCH: usize = 100;
+    const DEFAULT_CHANGEFEED_POLL_MS: u64 = 1_000;
+
+    /// Where a change-feed subscription keeps the last versionstamp it delivered.
+    enum Checkpoint {
+        /// Not persisted: a restart reads the feed from the start of its retention.
+        Memory,
+        /// A local file holding the versionstamp as text, replaced atomically.
+        File(std::path::PathBuf),
+        /// A record `table:[node, topic]` with a `versionstamp` field.
+        Table(String),
+    }
+
+    impl Checkpoint {
+        /// NULL or "" keeps it in memory, `table:<name>` uses a table, anything else is a file path.
+        fn parse(spec: Option<&str>) -> Result<Self, String> {
+            match spec.map(str::trim) {
+                None | Some("") => Ok(Checkpoint::Memory),
+                Some(s) => match s.strip_prefix("table:") {
+                    Some(t) if !t.is_empty() && t.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => Ok(Checkpoint::Table(t.to_string())),
+                    Some(t) => Err(format!("invalid checkpoint table: {}", t)),
+                    None => Ok(Checkpoint::File(s.into())),
+                },
+            }
+        }
+
+        async fn load(&self, dbh: &Surreal<Any>, node: &str, topic: &str) -> Result<Option<u64>, String> {
+            match self {
+                Checkpoint::Memory => Ok(None),
+                Checkpoint::File(path) => {
+                    // File I/O runs on the blocking pool, never on a runtime worker
+                    let path = path.clone();
+                    tokio::task::spawn_blocking(move || match std::fs::read_to_string(&path) {
+                        Ok(text) => text.trim().parse::<u64>().map(Some).map_err(|e| format!("checkpoint {}: {}", path.display(), e)),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                        Err(e) => Err(format!("checkpoint {}: {}", path.display(), e)),
+                    }).await.map_err(|e| format!("checkpoint: {}", e))?
+                }
+                Checkpoint::Table(cp) => dbh
+                    .query("SELECT VALUE versionstamp FROM ONLY type::thing($cp, [$node, $tb])")
+                    .bind(("cp", cp.clone()))
+                    .bind(("node", node.to_string()))
+                    .bind(("tb", topic.to_string()))
+                    .await
+                    .map_err(|e| format!("checkpoint {}: {}", cp, e))
+                    .and_then(|mut resp| resp.take::<Option<u64>>(0).map_err(|e| format!("checkpoint {}: {}", cp, e))),
+            }
+        }
+
+        async fn save(&self, dbh: &Surreal<Any>, node: &str, topic: &str, versionstamp: u64) -> Result<(), String> {
+            match self {
+                Checkpoint::Memory => Ok(()),
+                Checkpoint::File(path) => {
+                    let path = path.clone();
+                    tokio::task::spawn_blocking(move || {
+                        let tmp = path.with_extension("tmp");
+                        std::fs::write(&tmp, versionstamp.to_string())
+                            .and_then(|_| std::fs::rename(&tmp, &path))
+                            .map_err(|e| format!("checkpoint {}: {}", path.display(), e))
+                    }).await.map_err(|e| format!("checkpoint: {}", e))?
+                }
+                Checkpoint::Table(cp) => dbh
+                    .query("UPSERT type::thing($cp, [$node, $tb]) SET versionstamp = $vs, updated_at = time::now()")
+                    .bind(("cp", cp.clone()))
+                    .bind(("node", node.to_string()))
+                    .bind(("tb", topic.to_string()))
+                    .bind(("vs", versionstamp as i64))
+                    .await
+                    .map_err(|e| format!("checkpoint {}: {}", cp, e))
+                    .and_then(|resp| resp.check().map(|_| ()).map_err(|e| format!("checkpoint {}: {}", cp, e))),
+            }
+        }
+    }
+
+    /// Splits one versionstamp of `SHOW CHANGES` output into its versionstamp and record
+    /// changes; table definitions and other entries have no `create`, `update` or `delete`
+    /// and are skipped. Read from the untyped value: the SDK cannot decode a struct holding
+    /// `surrealdb::Value` fields.
+    /// The output reports the full 10-byte stamp, the sequence shifted left by 16 bits, while
+    /// `SINCE` takes the sequence; the sequence is returned.
+    fn parse_change_set(set: surrealdb::Value) -> Option<(u64, Vec<(c_int, surrealdb::Value)>)> {
+        use surrealdb::sql::Value as CoreValue;
+        let CoreValue::Object(mut set) = set.into_inner() else { return None };
+        let versionstamp = match set.get("versionstamp") {
+            Some(CoreValue::Number(n)) if n.is_int() => n.as_int() as u64 >> 16,
+            _ => return None,
+        };
+        let changes = match set.remove("changes") { Some(CoreValue::Array(changes)) => changes.0, _ => Vec::new() };
+        let changes = changes.into_iter().filter_map(|change| {
+            let CoreValue::Object(mut change) = change else { return None };
+            [("create", ACTION_CREATE), ("update", ACTION_UPDATE), ("delete", ACTION_DELETE)].into_iter()
+                .find_map(|(key, action)| change.remove(key).map(|row| (action, surrealdb::Value::from_inner(row))))
+        }).collect();
+        Some((versionstamp, changes))
+    }
+
+    /// Reads the change feed of `topic` (a table defined with `CHANGEFEED`) from its checkpoint
+    /// and delivers each change like `surreal_subscribe_raw`. The checkpoint is saved after
+    /// each batch of versionstamps, so a restart resumes after the last batch delivered.
+    /// `checkpoint`: NULL keeps it in memory, `table:<name>` stores it in that table (keyed
+    /// by node id and topic), anything else is a local file path. `poll_ms` 0 = 1000.
+    /// Returns 0 or a category code.
+    #[no_mangle]
+    pub extern "C" fn surreal_subscribe_changefeed(handle: *mut SurHandle, topic: *const c_char, checkpoint: *const c_char, poll_ms: u32, cb: SurChangeCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        h.clear_error();
+        if cb.is_none() { return h.fail_kind("subscribe_changefeed", SurError::InvalidArgument, "callback is NULL"); }
+        let table = match cstr_to_str(topic) { Some(s) => s.to_string(), None => return h.fail_kind("subscribe_changefeed", SurError::InvalidArgument, "topic is NULL or not UTF-8") };
+        // The table is spliced into SHOW CHANGES, so only plain identifiers are accepted
+        if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return h.fail_kind("subscribe_changefeed", SurError::InvalidArgument, format!("invalid table identifier: {}", table));
+        }
+        let checkpoint = match Checkpoint::parse(cstr_to_str(checkpoint)) { Ok(c) => c, Err(e) => return h.fail_kind("subscribe_changefeed", SurError::InvalidArgument, e) };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return h.fail_kind("subscribe_changefeed", SurError::Runtime, "runtime not initialized") };
+        // A table without CHANGEFEED would otherwise fail quietly in the background forever
+        if let Some(client) = h.pool.checkout() {
+            let statement = format!("SHOW CHANGES FOR TABLE {} SINCE 0 LIMIT 1", table);
+            match rt.block_on(deadline(h.conn.timeouts.query(), async { client.query(statement.as_str()).await.map_err(|e| e.to_string()).and_then(|r| r.check().map_err(|e| e.to_string())) })) {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return h.fail_query_kind("subscribe_changefeed", e, &statement),
+                Err(e) => return h.timed_out_kind("subscribe_changefeed", &client, e, &statement),
+            }
+        }
+        log_info(&format!("changefeed subscription started on {}", table));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_task = stop.clone();
+        let conn = h.conn.clone();
+        let pool = h.pool.clone();
+        let node = h.claimant.node();
+        let mut backoff = h.pool.backoff();
+        let mut token_gen = conn.token.generation.subscribe();
+        let poll = Duration::from_millis(if poll_ms == 0 { DEFAULT_CHANGEFEED_POLL_MS } else { poll_ms as u64 });
+        let user = user_data as usize;
+        let topic = table.clone();
+        let join = rt.spawn(async move {
+            let mut client: Option<Surreal<Any>> = None;
+            // Next versionstamp to read; loaded from the checkpoint on first connect
+            let mut since: Option<u64> = None;
+            loop {
+                if stop_task.load(Ordering::Relaxed) { break; }
+                if client.is_none() {
+                    client = connect_client(&conn).await.ok();
+                    if client.is_none() {
+                        let delay = backoff.next_delay();
+                        log_warn(&format!("changefeed connect failed on {}; retrying in {} ms", topic, delay.as_millis()));
+                        sleep(delay).await;
+                        continue;
+                    }
+                }
+                let dbh = client.as_ref().unwrap();
+                if token_gen.has_changed().unwrap_or(false) {
+                    token_gen.borrow_and_update();
+                    if dbh.authenticate(conn.token.current()).await.is_err() { client = None; continue; }
+                }
+                let from = match since {
+                    Some(v) => v,
+                    None => match checkpoint.load(dbh, &node, &topic).await {
+                        Ok(v) => {
+                            let from = v.map_or(0, |v| v + 1);
+                            log_debug(&format!("changefeed on {} resuming at versionstamp {}", topic, from));
+                            since = Some(from);
+                            from
+                        }
+                        Err(e) => { log_warn(&e); sleep(backoff.next_delay()).await; continue; }
+                    },
+                };
+                let read = dbh
+                    .query(format!("SHOW CHANGES FOR TABLE {} SINCE {} LIMIT {}", topic, from, CHANGEFEED_BATCH))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut resp| take_rows(&mut resp, 0));
+                let sets = match read {
+                    Ok(sets) => { backoff.reset(); sets }
+                    Err(e) => {
+                        log_warn(&format!("changefeed read failed on {}: {}", topic, e));
+                        if is_auth_error(&e.to_string()) { conn.token.renew(); }
+                        client = None;
+                        sleep(backoff.next_delay()).await;
+                        continue;
+                    }
+                };
+                let full = sets.len() >= CHANGEFEED_BATCH;
+                let mut delivered: Option<u64> = None;
+                for set in sets {
+                    if stop_task.load(Ordering::Relaxed) { break; }
+                    let (versionstamp, changes) = match parse_change_set(set) {
+                        Some(s) => s,
+                        None => { log_warn(&format!("changefeed entry on {} not understood", topic)); continue; }
+                    };
+                    for (action, row) in changes {
+                        pool.metrics.notifications.fetch_add(1, Ordering::Relaxed);
+                        deliver_change(&topic, action, row, cb, user);
+                    }
+                    since = Some(versionstamp + 1);
+                    delivered = Some(versionstamp);
+                }
+                // One checkpoint write per batch; a crash before it redelivers the batch
+                if let Some(vs) = delivered {
+                    if let Err(e) = checkpoint.save(dbh, &node, &topic, vs).await { log_warn(&e); }
+                }
+                if !full { sleep(poll).await; }
+            }
+        });
+
+        let mut subs = match h.subs.lock() { Ok(m) => m, Err(_) => return h.fail_kind("subscribe_changefeed", SurError::Internal, "subscription lock poisoned") };
+        if let Some(old) = subs.insert((SUB_CHANGEFEED, table), Sub { stop, handle: join, cb: None, user: user_data }) {
+            old.stop.store(true, Ordering::Relaxed);
+            old.handle.abort();
+        }
+        0
+    }
+
+    /// Spawns the subscription task for `table`, replacing any earlier one of the same kind.
+    /// Callers record a failure with their own return code.
+    fn subscribe(h: &SurHandle, table: String, filter: Option<Filter>, delivery: Delivery, user_data: *mut c_void) -> Result<(), (SurError, &'static str)> {
+        match &filter {
+            Some(f) => log_info(&format!("subscribe started on {} where {}", table, f.condition)),
+            None => log_info(&format!("subscribe started on {}", table)),
+        }
 
         // Resolve callback and user_data
-        let mut cb_to_use: SurCommandCb = cb;
+        let cb = match delivery { Delivery::Claim(cb) => cb, Delivery::Raw(_) => None };
+        let mut delivery = delivery;
         let mut user_to_use: *mut c_void = user_data;
-        if cb_to_use.is_none() {
+        if let Delivery::Claim(None) = delivery {
             if let Ok(guard) = h.callback.lock() {
                 if let Some((stored_cb, stored_user)) = *guard {
-                    cb_to_use = stored_cb;
+                    delivery = Delivery::Claim(stored_cb);
                     user_to_use = stored_user;
                 }
             }
@@ -467,179 +2803,305 @@ mod api {
 
         let stop = Arc::new(AtomicBool::new(false));
         let stop_clone = stop.clone();
-        let url = h.url.clone();
-        let ns = h.ns.clone();
-        let db = h.db.clone();
-        let auth = match &h.auth { Auth::UserPass { user, pass } => Auth::UserPass { user: user.clone(), pass: pass.clone() }, Auth::Token(t) => Auth::Token(t.clone()) };
+        let conn = h.conn.clone();
+        let pool = h.pool.clone();
+        let claimant = h.claimant.clone();
+        let mut backoff = h.pool.backoff();
+        let mut token_gen = conn.token.generation.subscribe();
 
         // Use integer for user_data to satisfy Send in tokio::spawn
         let user_ptr_usize = user_to_use as usize;
         let table_stream = table.clone();
-        let join = RUNTIME.get().unwrap().spawn(async move {
+        let rt = RUNTIME.get().ok_or((SurError::Runtime, "runtime not initialized"))?;
+        let join = rt.spawn(async move {
             use futures::StreamExt;
             use surrealdb::Action;
-            use surrealdb::RecordId;
-            use surrealdb::value::{from_value as from_sur_value, Value as SurValue};
-            use std::convert::TryInto;
 
-            log_info(&format!("subscribe loop starting on {} (LIVE)", table_stream));
-            let mut client = open_client(&url, &ns, &db, &auth).ok();
+            if conn.is_http() {
+                // HTTP has no LIVE queries: poll for new rows instead
+                log_debug(&format!("subscribe loop starting on {} (polling every {} ms over http)", table_stream, HTTP_POLL_MS));
+                let mut client: Option<Surreal<Any>> = None;
+                let mut last_reap: Option<Instant> = None;
+                loop {
+                    if stop_clone.load(std::sync::atomic::Ordering::Relaxed) { break; }
+                    if client.is_none() {
+                        client = connect_client(&conn).await.ok();
+                        if client.is_none() {
+                            let delay = backoff.next_delay();
+                            log_warn(&format!("poll connect failed on {}; retrying in {} ms", table_stream, delay.as_millis()));
+                            sleep(delay).await;
+                            continue;
+                        }
+                    }
+                    let dbh = client.as_ref().unwrap();
+                    if token_gen.has_changed().unwrap_or(false) {
+                        token_gen.borrow_and_update();
+                        if dbh.authenticate(conn.token.current()).await.is_err() { client = None; continue; }
+                    }
+                    if last_reap.is_none_or(|t| t.elapsed() >= claimant.reap_interval()) {
+                        reap_expired(dbh, &pool.metrics, &claimant, &table_stream).await;
+                        last_reap = Some(Instant::now());
+                    }
+                    let polled = match &filter {
+                        None => dbh.query("SELECT * FROM type::table($tb) WHERE status = 'new' LIMIT 50")
+                            .bind(("tb", table_stream.clone()))
+                            .await,
+                        Some(f) => dbh.query(format!("SELECT * FROM type::table($_tb) WHERE status = 'new' AND ({}) LIMIT 50", f.condition))
+                            .bind(JsonValue::Object(f.vars.clone()))
+                            .bind(("_tb", table_stream.clone()))
+                            .await,
+                    }.map_err(|e| e.to_string()).and_then(|mut resp| take_rows(&mut resp, 0));
+                    match polled {
+                        Ok(rows) => {
+                            backoff.reset();
+                            for row in rows {
+                                if stop_clone.load(std::sync::atomic::Ordering::Relaxed) { break; }
+                                pool.metrics.notifications.fetch_add(1, Ordering::Relaxed);
+                                // Raw subscriptions are refused over HTTP, so this is always a claim
+                                if let Delivery::Claim(cb) = delivery {
+                                    claim_and_deliver(dbh, &pool.metrics, &claimant, &table_stream, row, cb, user_ptr_usize).await;
+                                }
+                            }
+                            sleep(Duration::from_millis(HTTP_POLL_MS)).await;
+                        }
+                        Err(e) => {
+                            log_warn(&format!("poll failed on {}: {}", table_stream, e));
+                            if is_auth_error(&e.to_string()) { conn.token.renew(); }
+                            client = None;
+                            sleep(backoff.next_delay()).await;
+                        }
+                    }
+                }
+                return;
+            }
+
+            log_debug(&format!("subscribe loop starting on {} (LIVE)", table_stream));
+            let mut client = connect_client(&conn).await.ok();
             'outer: loop {
                 if stop_clone.load(std::sync::atomic::Ordering::Relaxed) { break; }
                 if client.is_none() {
-                    client = open_client(&url, &ns, &db, &auth).ok();
-                    if client.is_none() { log_info(&format!("live connect failed on {}; retrying", table_stream)); sleep(Duration::from_millis(1000)).await; continue; }
+                    client = connect_client(&conn).await.ok();
+                    if client.is_none() {
+                        let delay = backoff.next_delay();
+                        log_warn(&format!("live connect failed on {}; retrying in {} ms", table_stream, delay.as_millis()));
+                        sleep(delay).await;
+                        continue;
+                    }
                 }
                 let dbh = client.as_ref().unwrap();
-                log_info(&format!("live connected on {}", table_stream));
+                log_debug(&format!("live connected on {}", table_stream));
                 // Start LIVE SELECT stream
-                let mut stream = match dbh.select(&table_stream).live().await {
+                let started: surrealdb::Result<LiveStream> = match &filter {
+                    None => dbh.select(surrealdb::opt::Resource::from(&table_stream)).live().await.map(|s| s.boxed()),
+                    Some(f) => live_where(dbh, &table_stream, f).await,
+                };
+                let mut stream = match started {
                     Ok(s) => s,
-                    Err(e) => { log_info(&format!("live start failed on {}: {}", table_stream, e)); client = None; sleep(Duration::from_millis(1000)).await; continue; }
+                    Err(e) => {
+                        log_warn(&format!("live start failed on {}: {}", table_stream, e));
+                        if is_auth_error(&e.to_string()) { conn.token.renew(); }
+                        client = None;
+                        sleep(backoff.next_delay()).await;
+                        continue;
+                    }
                 };
+                backoff.reset();
+                // The stream is already open, so nothing created from here on can be missed
+                if let Delivery::Claim(cb) = delivery {
+                    catch_up(dbh, &pool.metrics, &claimant, &table_stream, filter.as_ref(), cb, user_ptr_usize).await;
+                }
 
-                while let Some(item) = stream.next().await {
-                    let notif: surrealdb::Notification<SurValue> = match item {
-                        Ok(n) => n,
-                        Err(e) => { log_info(&format!("live notification error on {}: {}", table_stream, e)); continue; }
+                token_gen.borrow_and_update();
+                // Only command subscriptions claim, so only they have leases to recover
+                let reaping = matches!(delivery, Delivery::Claim(_));
+                let mut reap_tick = tokio::time::interval(claimant.reap_interval());
+                reap_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    let notif = tokio::select! {
+                        item = stream.next() => match item { Some(item) => item, None => break },
+                        _ = reap_tick.tick(), if reaping => {
+                            reap_expired(dbh, &pool.metrics, &claimant, &table_stream).await;
+                            continue;
+                        }
+                        changed = token_gen.changed() => {
+                            // Token renewed elsewhere: re-authenticate this connection in place
+                            if changed.is_ok() && dbh.authenticate(conn.token.current()).await.is_err() {
+                                log_warn(&format!("live connection on {} rejected the renewed token; reconnecting", table_stream));
+                                break;
+                            }
+                            continue;
+                        }
                     };
                     if stop_clone.load(std::sync::atomic::Ordering::Relaxed) { break 'outer; }
-                    // Only react to create/update; delete is irrelevant for commands
-                    if notif.action != Action::Create && notif.action != Action::Update { continue; }
-
-                    // Serialize to JSON for callback shaping
-                    let sur_val: SurValue = notif.data.clone();
-                    let mut json = match serde_json::to_value(&sur_val) { Ok(v) => v, Err(_) => serde_json::json!({}) };
-
-                    // Extract id + status using typed conversion for reliability
-                    #[derive(serde::Deserialize)]
-                    struct IdOnly { id: RecordId, status: Option<String> }
-                    let idonly: IdOnly = match from_sur_value::<IdOnly>(sur_val.clone()) {
-                        Ok(v) => v,
-                        Err(e) => { log_info(&format!("live could not parse id/status on {}: {}", table_stream, e)); continue; }
-                    };
-                    // gate on status == 'new'
-                    if let Some(st) = idonly.status.as_deref() { if !st.eq_ignore_ascii_case("new") { continue; } } else { continue; }
-
-                    // Build id string as table:key (bare key for claim/update API)
-                    let tb = idonly.id.table().to_string();
-                    let key: Result<String, _> = idonly.id.key().clone().try_into();
-                    let key = match key { Ok(s) => s, Err(_) => {
-                        log_info(&format!("live could not stringify id key for {}; skipping", table_stream));
-                        continue;
-                    }};
-                    let id_str = key;
-                    // Claim the record
-                    let claim = format!("UPDATE {}:{} SET status = 'processing', claimed_at = time::now()", table_stream, id_str);
-                    match dbh.query(claim).await {
-                        Ok(_) => {
-                            // Ensure id is a JSON string for the callback
-                            if let Some(obj) = json.as_object_mut() { obj.insert("id".to_string(), serde_json::Value::String(format!("{}:{}", tb, id_str))); }
-                            if let Ok(txt) = serde_json::to_string(&json) {
-                                if let Ok(ctopic) = std::ffi::CString::new(table_stream.clone()) {
-                                    if let Ok(cjson) = std::ffi::CString::new(txt) {
-                                        if let Some(cb_fn) = cb_to_use {
-                                            let user_ptr = user_ptr_usize as *mut c_void;
-                                            cb_fn(ctopic.as_ptr(), cjson.as_ptr(), user_ptr);
-                                        }
-                                    }
-                                }
-                            }
+                    pool.metrics.notifications.fetch_add(1, Ordering::Relaxed);
+                    match delivery {
+                        Delivery::Claim(cb) => {
+                            // Only react to create/update; delete is irrelevant for commands
+                            if notif.action != Action::Create && notif.action != Action::Update { continue; }
+                            claim_and_deliver(dbh, &pool.metrics, &claimant, &table_stream, notif.data, cb, user_ptr_usize).await;
+                        }
+                        Delivery::Raw(cb) => {
+                            let action = match notif.action {
+                                Action::Create => ACTION_CREATE,
+                                Action::Update => ACTION_UPDATE,
+                                Action::Delete => ACTION_DELETE,
+                                _ => continue,
+                            };
+                            deliver_change(&table_stream, action, notif.data, cb, user_ptr_usize);
                         }
-                        Err(e) => { log_info(&format!("live claim failed for {}:{}: {}", table_stream, id_str, e)); }
                     }
                 }
                 // stream ended; reconnect
                 client = None;
-                log_info(&format!("live stream ended on {}; reconnecting", table_stream));
-                sleep(Duration::from_millis(500)).await;
+                log_warn(&format!("live stream ended on {}; reconnecting", table_stream));
+                sleep(backoff.next_delay()).await;
             }
         });
 
-        let mut subs = match h.subs.lock() { Ok(m) => m, Err(_) => return -4 };
-        subs.insert(table, Sub { stop, handle: join, cb, user: user_data });
-        0
+        let kind = delivery.kind();
+        let mut subs = h.subs.lock().map_err(|_| (SurError::Internal, "subscription lock poisoned"))?;
+        if let Some(old) = subs.insert((kind, table), Sub { stop, handle: join, cb, user: user_data }) {
+            old.stop.store(true, Ordering::Relaxed);
+            old.handle.abort();
+        }
+        Ok(())
     }
 
+    /// Stops every subscription on `topic`, of any kind.
     #[no_mangle]
     pub extern "C" fn surreal_unsubscribe(handle: *mut SurHandle, _topic: *const c_char) -> c_int {
         if handle.is_null() { return -1; }
         let h = unsafe { &*handle };
-        let table = match cstr_to_str(_topic) { Some(s) => s, None => return -3 };
-        let mut subs = match h.subs.lock() { Ok(m) => m, Err(_) => return -2 };
-        if let Some(sub) = subs.remove(&*table) {
-            sub.stop.store(true, std::sync::atomic::Ordering::Relaxed);
-            sub.handle.abort();
-            return 0;
+        let table = match cstr_to_str(_topic) { Some(s) => s, None => return h.fail("unsubscribe", -3, SurError::InvalidArgument, "topic is NULL or not UTF-8") };
+        let mut subs = match h.subs.lock() { Ok(m) => m, Err(_) => return h.fail("unsubscribe", -2, SurError::Internal, "subscription lock poisoned") };
+        let keys: Vec<(c_int, String)> = subs.keys().filter(|(_, t)| t == table).cloned().collect();
+        if keys.is_empty() { return h.fail("unsubscribe", -4, SurError::InvalidArgument, format!("no subscription on {}", table)); }
+        for key in keys {
+            if let Some(sub) = subs.remove(&key) {
+                sub.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                sub.handle.abort();
+            }
+        }
+        0
+    }
+
+    /// Stops only the subscription of `kind` (`SUB_COMMAND`, `SUB_RAW`, ...) on `topic`,
+    /// leaving other kinds running.
+    /// Returns 0 or a category code.
+    #[no_mangle]
+    pub extern "C" fn surreal_unsubscribe_kind(handle: *mut SurHandle, topic: *const c_char, kind: c_int) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        h.clear_error();
+        let table = match cstr_to_str(topic) { Some(s) => s.to_string(), None => return h.fail_kind("unsubscribe_kind", SurError::InvalidArgument, "topic is NULL or not UTF-8") };
+        let mut subs = match h.subs.lock() { Ok(m) => m, Err(_) => return h.fail_kind("unsubscribe_kind", SurError::Internal, "subscription lock poisoned") };
+        let key = (kind, table);
+        match subs.remove(&key) {
+            Some(sub) => {
+                sub.stop.store(true, Ordering::Relaxed);
+                sub.handle.abort();
+                0
+            }
+            None => h.fail_kind("unsubscribe_kind", SurError::InvalidArgument, format!("no subscription of kind {} on {}", kind, key.1)),
+        }
+    }
+
+    /// Builds a record id for `table` from either a bare key or a `table:key` string.
+    /// Keys are parsed with SurrealQL record-id syntax, so numeric, `⟨escaped⟩`, array and
+    /// object keys keep their type; anything that does not parse (e.g. a FreeSWITCH UUID)
+    /// becomes a plain string key. Returns None for an empty id.
+    fn parse_record_id(table: &str, id: &str) -> Option<RecordId> {
+        let id = id.trim();
+        if id.is_empty() { return None; }
+        if let Ok(rid) = id.parse::<RecordId>() {
+            if rid.table() == table { return Some(rid); }
+        }
+        if let Ok(rid) = format!("{}:{}", table, id).parse::<RecordId>() {
+            return Some(rid);
         }
-        -4
+        Some(RecordId::from_table_key(table, id))
     }
 
     #[no_mangle]
     pub extern "C" fn surreal_update(handle: *mut SurHandle, table: *const c_char, id: *const c_char, json_patch: *const c_char) -> c_int {
         if handle.is_null() { return -1; }
-        let h = unsafe { &mut *handle };
-        let table = match cstr_to_str(table) { Some(s) => s, None => return -2 };
-        let mut id = match cstr_to_str(id) { Some(s) => s.to_string(), None => return -3 };
-        // Normalize id: allow fully qualified "table:id" or bare id
-        if let Some(stripped) = id.strip_prefix(&format!("{}:", table)) { id = stripped.to_string(); }
-        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return -2; }
-        if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' ) { return -3; }
-        let patch = match cstr_to_str(json_patch) { Some(s) => s, None => return -4 };
-        let value: JsonValue = match serde_json::from_str(patch) { Ok(v) => v, Err(_) => return -5 };
-        let client = match h.client.as_ref() {
-            Some(c) => c,
-            None => {
-                match open_client(&h.url, &h.ns, &h.db, &h.auth) {
-                    Ok(c) => { h.client = Some(c); h.client.as_ref().unwrap() }
-                    Err(_) => return -6,
-                }
-            }
-        };
-        let rt = match RUNTIME.get() { Some(rt) => rt, None => return -7 };
+        let h = unsafe { &*handle };
+        h.clear_error();
+        let table = match cstr_to_str(table) { Some(s) => s, None => return h.fail("update", -2, SurError::InvalidArgument, "table is NULL or not UTF-8") };
+        let id = match cstr_to_str(id) { Some(s) => s, None => return h.fail("update", -3, SurError::InvalidArgument, "id is NULL or not UTF-8") };
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return h.fail("update", -2, SurError::InvalidArgument, format!("invalid table identifier: {}", table)); }
+        let rid = match parse_record_id(table, id) { Some(r) => r, None => return h.fail("update", -3, SurError::InvalidArgument, "id is empty") };
+        let patch = match cstr_to_str(json_patch) { Some(s) => s, None => return h.fail("update", -4, SurError::InvalidArgument, "patch is NULL or not UTF-8") };
+        let value: JsonValue = match serde_json::from_str(patch) { Ok(v) => v, Err(e) => return h.fail("update", -5, SurError::Json, e.to_string()) };
+        let client = match h.pool.checkout() { Some(c) => c, None => return h.fail("update", -6, SurError::NotConnected, "no connection available") };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return h.fail("update", -7, SurError::Runtime, "runtime not initialized") };
+        let statement = format!("UPDATE {} MERGE $patch", rid);
         // Use SQL to avoid method-shape mismatches and capture error text
-        let res: Result<(), String> = rt.block_on(async {
-            let json_str = match serde_json::to_string(&value) { Ok(s) => s, Err(e) => return Err(format!("json stringify failed: {}", e)) };
-            let sql = format!("UPDATE {}:{} MERGE {}", table, id, json_str);
-            match client.query(sql).await {
-                Ok(_) => Ok(()),
+        let res: Result<(), String> = match rt.block_on(h.pool.metrics.query_latency.time(deadline(h.conn.timeouts.query(), async {
+            match client.query("UPDATE $rid MERGE $patch").bind(("rid", rid)).bind(("patch", value)).await {
+                Ok(mut resp) => match resp.take_errors().into_values().next() {
+                    Some(e) => Err(format!("{}", e)),
+                    None => Ok(()),
+                },
                 Err(e) => Err(format!("{}", e)),
             }
-        });
+        }))) {
+            Ok(r) => r,
+            Err(e) => return h.timed_out("update", &client, e, &statement),
+        };
         match res {
             Ok(()) => 0,
-            Err(e) => {
-                if let Ok(mut msg) = h.last_error_msg.lock() { *msg = e; }
-                h.last_error_code.store(-8, Ordering::Relaxed);
-                -8
-            }
+            Err(e) => h.fail_query("update", -8, e, &statement),
         }
     }
 
     #[no_mangle]
     pub extern "C" fn surreal_debug_emit(_handle: *mut SurHandle, _topic: *const c_char, _json: *const c_char) -> c_int { -1 }
 
+    /// Copies a result into the caller's buffer; returns -20 (and writes an empty string) if it does not fit.
+    fn write_out(h: &SurHandle, op: &'static str, s: &str, out_json: *mut c_char, out_len: u32) -> c_int {
+        let bytes = s.as_bytes();
+        if bytes.len() >= out_len as usize {
+            unsafe { *out_json = 0 };
+            return h.fail(op, -20, SurError::Truncated, format!("output needs {} bytes, buffer has {}", bytes.len() + 1, out_len));
+        }
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_json as *mut u8, bytes.len()) };
+        unsafe { *(out_json.wrapping_add(bytes.len())) = 0 };
+        0
+    }
+
+    /// Hands a result to the caller as a heap string released with `surreal_free_string`.
+    fn alloc_out(s: String, out_json: *mut *mut c_char) -> c_int {
+        unsafe { *out_json = CString::new(s).unwrap_or_default().into_raw() };
+        0
+    }
+
     #[no_mangle]
     pub extern "C" fn surreal_select(handle: *mut SurHandle, table: *const c_char, limit: u32, out_json: *mut c_char, out_len: u32) -> c_int {
         if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
-        let h = unsafe { &mut *handle };
-        let table = match cstr_to_str(table) { Some(s) => s, None => return -2 };
-        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return -3; }
+        let h = unsafe { &*handle };
+        match select_json(h, table, limit) { Ok(s) => write_out(h, "select", &s, out_json, out_len), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_select_alloc(handle: *mut SurHandle, table: *const c_char, limit: u32, out_json: *mut *mut c_char) -> c_int {
+        if handle.is_null() || out_json.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        unsafe { *out_json = std::ptr::null_mut() };
+        match select_json(h, table, limit) { Ok(s) => alloc_out(s, out_json), Err(rc) => rc }
+    }
+
+    fn select_json(h: &SurHandle, table: *const c_char, limit: u32) -> Result<String, c_int> {
+        h.clear_error();
+        let table = match cstr_to_str(table) { Some(s) => s, None => return Err(h.fail("select", -2, SurError::InvalidArgument, "table is NULL or not UTF-8")) };
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return Err(h.fail("select", -3, SurError::InvalidArgument, format!("invalid table identifier: {}", table))); }
         let limit = if limit == 0 { 100 } else { limit.min(10000) };
-        let client = match h.client.as_ref() {
-            Some(c) => c,
-            None => {
-                match open_client(&h.url, &h.ns, &h.db, &h.auth) {
-                    Ok(c) => { h.client = Some(c); h.client.as_ref().unwrap() }
-                    Err(_) => return -4,
-                }
-            }
-        };
-        let rt = match RUNTIME.get() { Some(rt) => rt, None => return -5 };
+        let client = match h.pool.checkout() { Some(c) => c, None => return Err(h.fail("select", -4, SurError::NotConnected, "no connection available")) };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return Err(h.fail("select", -5, SurError::Runtime, "runtime not initialized")) };
         // Project string-cast id last to override typed id in output JSON
         let sql = format!("SELECT *, type::string(id) AS id FROM {} LIMIT {}", table, limit);
-        let res: Result<String, String> = rt.block_on(async {
-            match client.query(sql).await {
+        let res: Result<String, String> = match rt.block_on(h.pool.metrics.query_latency.time(deadline(h.conn.timeouts.query(), async {
+            match client.query(sql.as_str()).await {
                 Ok(mut resp) => {
                     match resp.take::<Vec<serde_json::Value>>(0) {
                         Ok(list) => serde_json::to_string(&list).map_err(|e| format!("json encode failed: {}", e)),
@@ -648,44 +3110,39 @@ mod api {
                 }
                 Err(e) => Err(format!("{}", e))
             }
-        });
-        match res {
-            Ok(s) => {
-                let bytes = s.as_bytes();
-                let n = (bytes.len().min((out_len - 1) as usize)) as usize;
-                unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_json as *mut u8, n) };
-                unsafe { *(out_json.wrapping_add(n as usize)) = 0 };
-                0
-            }
-            Err(e) => {
-                if let Ok(mut msg) = h.last_error_msg.lock() { *msg = e; }
-                h.last_error_code.store(-9, Ordering::Relaxed);
-                -6
-            }
-        }
+        }))) {
+            Ok(r) => r,
+            Err(e) => return Err(h.timed_out("select", &client, e, &sql)),
+        };
+        res.map_err(|e| h.fail_query("select", -6, e, &sql))
     }
 
     #[no_mangle]
     pub extern "C" fn surreal_get(handle: *mut SurHandle, table: *const c_char, id: *const c_char, out_json: *mut c_char, out_len: u32) -> c_int {
         if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
-        let h = unsafe { &mut *handle };
-        let table = match cstr_to_str(table) { Some(s) => s, None => return -2 };
-        let mut id = match cstr_to_str(id) { Some(s) => s.to_string(), None => return -3 };
-        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return -4; }
-        if let Some(stripped) = id.strip_prefix(&format!("{}:", table)) { id = stripped.to_string(); }
-        let client = match h.client.as_ref() {
-            Some(c) => c,
-            None => {
-                match open_client(&h.url, &h.ns, &h.db, &h.auth) {
-                    Ok(c) => { h.client = Some(c); h.client.as_ref().unwrap() }
-                    Err(_) => return -5,
-                }
-            }
-        };
-        let rt = match RUNTIME.get() { Some(rt) => rt, None => return -6 };
-        let res: Result<String, String> = rt.block_on(async {
-            let sql = format!("SELECT *, type::string(id) AS id FROM {} WHERE id = {}:{} LIMIT 1", table, table, id);
-            match client.query(sql).await {
+        let h = unsafe { &*handle };
+        match get_json(h, table, id) { Ok(s) => write_out(h, "get", &s, out_json, out_len), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_get_alloc(handle: *mut SurHandle, table: *const c_char, id: *const c_char, out_json: *mut *mut c_char) -> c_int {
+        if handle.is_null() || out_json.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        unsafe { *out_json = std::ptr::null_mut() };
+        match get_json(h, table, id) { Ok(s) => alloc_out(s, out_json), Err(rc) => rc }
+    }
+
+    fn get_json(h: &SurHandle, table: *const c_char, id: *const c_char) -> Result<String, c_int> {
+        h.clear_error();
+        let table = match cstr_to_str(table) { Some(s) => s, None => return Err(h.fail("get", -2, SurError::InvalidArgument, "table is NULL or not UTF-8")) };
+        let id = match cstr_to_str(id) { Some(s) => s, None => return Err(h.fail("get", -3, SurError::InvalidArgument, "id is NULL or not UTF-8")) };
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' ) { return Err(h.fail("get", -4, SurError::InvalidArgument, format!("invalid table identifier: {}", table))); }
+        let rid = match parse_record_id(table, id) { Some(r) => r, None => return Err(h.fail("get", -3, SurError::InvalidArgument, "id is empty")) };
+        let client = match h.pool.checkout() { Some(c) => c, None => return Err(h.fail("get", -5, SurError::NotConnected, "no connection available")) };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return Err(h.fail("get", -6, SurError::Runtime, "runtime not initialized")) };
+        let statement = format!("SELECT *, type::string(id) AS id FROM {}", rid);
+        let res: Result<String, String> = match rt.block_on(h.pool.metrics.query_latency.time(deadline(h.conn.timeouts.query(), async {
+            match client.query("SELECT *, type::string(id) AS id FROM $rid").bind(("rid", rid)).await {
                 Ok(mut resp) => {
                     match resp.take::<Vec<serde_json::Value>>(0) {
                         Ok(mut list) => {
@@ -696,20 +3153,690 @@ mod api {
                 }
                 Err(e) => Err(format!("{}", e))
             }
-        });
-        match res {
-            Ok(s) => {
-                let bytes = s.as_bytes();
-                let n = (bytes.len().min((out_len - 1) as usize)) as usize;
-                unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_json as *mut u8, n) };
-                unsafe { *(out_json.wrapping_add(n as usize)) = 0 };
-                0
+        }))) {
+            Ok(r) => r,
+            Err(e) => return Err(h.timed_out("get", &client, e, &statement)),
+        };
+        res.map_err(|e| h.fail_query("get", -6, e, &statement))
+    }
+
+    /// Converts an SDK value into plain JSON for C callers: record ids become `table:key`
+    /// strings and numbers plain numbers. Serializing the value directly would keep the
+    /// engine's tagged form (`{"Number":{"Int":1}}`).
+    fn sur_to_json(v: &surrealdb::Value) -> JsonValue {
+        v.clone().into_inner().into_json()
+    }
+
+    /// Takes statement `index` of a response as a list of rows. The SDK only decodes an
+    /// untyped result whole: `take::<Vec<surrealdb::Value>>` fails on any non-empty list.
+    fn take_rows(resp: &mut surrealdb::Response, index: usize) -> Result<Vec<surrealdb::Value>, String> {
+        use surrealdb::sql::Value as CoreValue;
+        Ok(match resp.take::<surrealdb::Value>(index).map_err(|e| e.to_string())?.into_inner() {
+            CoreValue::Array(rows) => rows.0.into_iter().map(surrealdb::Value::from_inner).collect(),
+            CoreValue::None | CoreValue::Null => Vec::new(),
+            row => vec![surrealdb::Value::from_inner(row)],
+        })
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_query(handle: *mut SurHandle, sql: *const c_char, vars_json: *const c_char, out_json: *mut c_char, out_len: u32) -> c_int {
+        if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
+        let h = unsafe { &*handle };
+        match query_json(h, sql, vars_json) { Ok(s) => write_out(h, "query", &s, out_json, out_len), Err(rc) => rc }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_query_alloc(handle: *mut SurHandle, sql: *const c_char, vars_json: *const c_char, out_json: *mut *mut c_char) -> c_int {
+        if handle.is_null() || out_json.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        unsafe { *out_json = std::ptr::null_mut() };
+        match query_json(h, sql, vars_json) { Ok(s) => alloc_out(s, out_json), Err(rc) => rc }
+    }
+
+    /// Parses optional bound variables: NULL, empty or `null` is no variables. None if not an object.
+    fn parse_vars(vars_json: *const c_char) -> Option<serde_json::Map<String, JsonValue>> {
+        match cstr_to_str(vars_json).map(str::trim) {
+            None | Some("") => Some(serde_json::Map::new()),
+            Some(s) => match serde_json::from_str::<JsonValue>(s) {
+                Ok(JsonValue::Object(m)) => Some(m),
+                Ok(JsonValue::Null) => Some(serde_json::Map::new()),
+                _ => None,
+            },
+        }
+    }
+
+    fn query_json(h: &SurHandle, sql: *const c_char, vars_json: *const c_char) -> Result<String, c_int> {
+        h.clear_error();
+        let sql = match cstr_to_str(sql) { Some(s) if !s.trim().is_empty() => s.to_string(), _ => return Err(h.fail("query", -2, SurError::InvalidArgument, "statement is empty")) };
+        // Variables are bound server-side, never spliced into the SurrealQL text
+        let vars = match parse_vars(vars_json) { Some(v) => v, None => return Err(h.fail("query", -3, SurError::Json, "vars must be a JSON object")) };
+        let client = match h.pool.checkout() { Some(c) => c, None => return Err(h.fail("query", -4, SurError::NotConnected, "no connection available")) };
+        let rt = match RUNTIME.get() { Some(rt) => rt, None => return Err(h.fail("query", -5, SurError::Runtime, "runtime not initialized")) };
+        // One entry per statement, shaped like the server's /sql response
+        let res: Result<String, String> = match rt.block_on(h.pool.metrics.query_latency.time(deadline(h.conn.timeouts.query(), async {
+            let mut resp = client.query(sql.as_str()).bind(JsonValue::Object(vars)).await.map_err(|e| format!("{}", e))?;
+            let count = resp.num_statements();
+            let mut errors = resp.take_errors();
+            let mut results = Vec::with_capacity(count);
+            for i in 0..count {
+                if let Some(e) = errors.remove(&i) {
+                    results.push(serde_json::json!({ "status": "ERR", "result": format!("{}", e) }));
+                    continue;
+                }
+                match resp.take::<surrealdb::Value>(i) {
+                    Ok(v) => results.push(serde_json::json!({ "status": "OK", "result": sur_to_json(&v) })),
+                    Err(e) => results.push(serde_json::json!({ "status": "ERR", "result": format!("{}", e) })),
+                }
             }
-            Err(e) => {
-                if let Ok(mut msg) = h.last_error_msg.lock() { *msg = e; }
-                h.last_error_code.store(-10, Ordering::Relaxed);
-                -6
+            serde_json::to_string(&results).map_err(|e| format!("json encode failed: {}", e))
+        }))) {
+            Ok(r) => r,
+            Err(e) => return Err(h.timed_out("query", &client, e, &sql)),
+        };
+        res.map_err(|e| h.fail_query("query", -6, e, &sql))
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_pool_config(handle: *mut SurHandle, min_size: u32, max_size: u32, health_interval_ms: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        h.pool.configure(min_size as usize, max_size as usize, health_interval_ms as u64);
+        // Open the minimum right away rather than on first use
+        let min = h.pool.min.load(Ordering::Relaxed);
+        let leases: Vec<Lease<'_>> = (0..min).filter_map(|_| h.pool.checkout()).collect();
+        if leases.len() < min {
+            return h.fail("pool_config", -2, SurError::NotConnected, format!("opened {} of {} connections", leases.len(), min));
+        }
+        0
+    }
+
+    fn gauges(h: &SurHandle) -> Gauges {
+        let spool = h.spool.get();
+        Gauges {
+            state: state_name(h.pool.state.load(Ordering::Relaxed)),
+            pool_open: h.pool.open_count() as u64,
+            pool_busy: h.pool.busy_count() as u64,
+            connects: h.pool.connects.load(Ordering::Relaxed),
+            connect_failures: h.pool.connect_failures.load(Ordering::Relaxed),
+            subscriptions: h.subs.lock().map(|m| m.len() as u64).unwrap_or(0),
+            async_depth: h.async_pub.lock().ok().and_then(|g| g.as_ref().map(|p| p.depth.load(Ordering::Relaxed) as u64)).unwrap_or(0),
+            spool_depth: spool.map_or(0, |sp| sp.depth()),
+            spool_bytes: spool.map_or(0, |sp| sp.bytes()),
+            spool_dropped: spool.map_or(0, |sp| sp.dropped()),
+        }
+    }
+
+    /// Returns the handle's counters, latency histograms and queue depths as JSON
+    /// (see the header). Free the result with `surreal_free_string`.
+    #[no_mangle]
+    pub extern "C" fn surreal_stats_json(handle: *mut SurHandle) -> *mut c_char {
+        if handle.is_null() { return std::ptr::null_mut(); }
+        let h = unsafe { &*handle };
+        CString::new(h.pool.metrics.json(&gauges(h)).to_string()).unwrap_or_default().into_raw()
+    }
+
+    /// Same data as `surreal_stats_json` in Prometheus text exposition format.
+    #[no_mangle]
+    pub extern "C" fn surreal_stats_prometheus(handle: *mut SurHandle) -> *mut c_char {
+        if handle.is_null() { return std::ptr::null_mut(); }
+        let h = unsafe { &*handle };
+        CString::new(h.pool.metrics.prometheus(&gauges(h))).unwrap_or_default().into_raw()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_state_callback(handle: *mut SurHandle, cb: SurStateCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        match h.pool.state_cb.lock() {
+            Ok(mut g) => { *g = Some((cb, user_data as usize)); 0 }
+            Err(_) => -2,
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_connection_state(handle: *mut SurHandle) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        h.pool.state.load(Ordering::Acquire)
+    }
+
+    /// Sets how often subscriptions look for expired claims and how many times a command may
+    /// be claimed before it is marked `failed`. 0 keeps the value (defaults: 10 s, 3 attempts).
+    #[no_mangle]
+    pub extern "C" fn surreal_set_claim_recovery(handle: *mut SurHandle, reap_interval_ms: u32, max_attempts: u32) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        if reap_interval_ms > 0 { h.claimant.reap_interval_ms.store(reap_interval_ms as u64, Ordering::Relaxed); }
+        if max_attempts > 0 { h.claimant.max_attempts.store(max_attempts, Ordering::Relaxed); }
+        0
+    }
+
+    /// Sets the node id written to `claimed_by` when this handle claims a command, and the
+    /// lease written to `lease_until`. NULL or "" keeps the node id (default: the host name);
+    /// 0 keeps the lease (default 60 s).
+    #[no_mangle]
+    pub extern "C" fn surreal_set_claim_options(handle: *mut SurHandle, node_id: *const c_char, lease_ms: u32) -> c_int {
+        if handle.is_null() { return SurError::InvalidArgument.code(); }
+        let h = unsafe { &*handle };
+        if let Some(node) = cstr_to_str(node_id).map(str::trim).filter(|n| !n.is_empty()) {
+            match h.claimant.node.write() {
+                Ok(mut g) => *g = node.to_string(),
+                Err(_) => return h.fail_kind("set_claim_options", SurError::Internal, "claim lock poisoned"),
+            }
+        }
+        if lease_ms > 0 { h.claimant.lease_ms.store(lease_ms as u64, Ordering::Relaxed); }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_reconnect_backoff(handle: *mut SurHandle, initial_ms: u32, max_ms: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let initial = if initial_ms == 0 { 250 } else { initial_ms as u64 };
+        let max = if max_ms == 0 { 30_000 } else { max_ms as u64 };
+        h.pool.backoff_initial_ms.store(initial, Ordering::Relaxed);
+        h.pool.backoff_max_ms.store(max.max(initial), Ordering::Relaxed);
+        0
+    }
+
+    /// Sets the timeouts copied into handles connected after the call, including the connect
+    /// itself. 0 keeps the built-in value (connect 10 s, query 30 s, publish 10 s).
+    #[no_mangle]
+    pub extern "C" fn surreal_set_default_timeouts(connect_ms: u32, query_ms: u32, publish_ms: u32) -> c_int {
+        DEFAULT_CONNECT_TIMEOUT_MS.store(if connect_ms == 0 { 10_000 } else { connect_ms as u64 }, Ordering::Relaxed);
+        DEFAULT_QUERY_TIMEOUT_MS.store(if query_ms == 0 { 30_000 } else { query_ms as u64 }, Ordering::Relaxed);
+        DEFAULT_PUBLISH_TIMEOUT_MS.store(if publish_ms == 0 { 10_000 } else { publish_ms as u64 }, Ordering::Relaxed);
+        0
+    }
+
+    /// Overrides the timeouts of one handle; 0 leaves that value unchanged.
+    #[no_mangle]
+    pub extern "C" fn surreal_set_timeouts(handle: *mut SurHandle, connect_ms: u32, query_ms: u32, publish_ms: u32) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let t = &h.conn.timeouts;
+        if connect_ms != 0 { t.connect_ms.store(connect_ms as u64, Ordering::Relaxed); }
+        if query_ms != 0 { t.query_ms.store(query_ms as u64, Ordering::Relaxed); }
+        if publish_ms != 0 { t.publish_ms.store(publish_ms as u64, Ordering::Relaxed); }
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_token_provider(handle: *mut SurHandle, cb: SurTokenProviderCb, user_data: *mut c_void) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        match h.conn.token.provider.lock() {
+            Ok(mut g) => { *g = cb.map(|f| (Some(f), user_data as usize)); 0 }
+            Err(_) => -2,
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_set_token_file(handle: *mut SurHandle, path: *const c_char) -> c_int {
+        if handle.is_null() { return -1; }
+        let h = unsafe { &*handle };
+        let path = cstr_to_str(path).map(str::trim).filter(|p| !p.is_empty()).map(str::to_string);
+        match h.conn.token.file.lock() {
+            Ok(mut g) => { *g = path; 0 }
+            Err(_) => -2,
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_pool_stats_json(handle: *mut SurHandle, out_json: *mut c_char, out_len: u32) -> c_int {
+        if handle.is_null() || out_json.is_null() || out_len == 0 { return -1; }
+        let h = unsafe { &*handle };
+        write_out(h, "pool_stats_json", &h.pool.stats_json().to_string(), out_json, out_len)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn surreal_free_string(s: *mut c_char) {
+        if s.is_null() { return; }
+        drop(unsafe { CString::from_raw(s) });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn backoff_doubles_up_to_max() {
+            let mut b = Backoff::new(100, 1_000);
+            for bound in [100, 200, 400, 800, 1_000, 1_000, 1_000] {
+                let d = b.next_delay().as_millis() as u64;
+                assert!(d >= bound / 2 && d <= bound, "{} not in [{}, {}]", d, bound / 2, bound);
+            }
+            for _ in 0..100 {
+                assert!(b.next_delay() <= Duration::from_millis(1_000));
+            }
+            b.reset();
+            assert!(b.next_delay() <= Duration::from_millis(100));
+        }
+
+        #[test]
+        fn backoff_clamps_degenerate_bounds() {
+            let mut b = Backoff::new(0, 0);
+            for _ in 0..10 {
+                assert!(b.next_delay() <= Duration::from_millis(1));
+            }
+            let mut b = Backoff::new(500, 10);
+            let d = b.next_delay().as_millis() as u64;
+            assert!((250..=500).contains(&d));
+        }
+
+        #[test]
+        fn backoff_jitters() {
+            let mut b = Backoff::new(1_000, 1_000);
+            let delays: std::collections::HashSet<Duration> = (0..20).map(|_| b.next_delay()).collect();
+            assert!(delays.len() > 1, "delays never varied: {:?}", delays);
+        }
+
+        #[test]
+        fn record_id_forms() {
+            assert_eq!(parse_record_id("event", "abc"), Some(RecordId::from_table_key("event", "abc")));
+            assert_eq!(parse_record_id("event", " abc "), Some(RecordId::from_table_key("event", "abc")));
+            assert_eq!(parse_record_id("event", "42"), Some(RecordId::from_table_key("event", 42i64)));
+            assert_eq!(parse_record_id("event", "event:42"), Some(RecordId::from_table_key("event", 42i64)));
+            assert_eq!(parse_record_id("event", "event:abc"), Some(RecordId::from_table_key("event", "abc")));
+        }
+
+        #[test]
+        fn record_id_falls_back_to_string_key() {
+            let uuid = "3f2a1c9e-1b2c-4d5e-8f90-123456789abc";
+            assert_eq!(parse_record_id("event", uuid), Some(RecordId::from_table_key("event", uuid)));
+            // A prefix naming another table is part of the key, not a table switch
+            assert_eq!(parse_record_id("event", "other:x").map(|r| r.table().to_string()), Some("event".to_string()));
+        }
+
+        #[test]
+        fn record_id_rejects_empty() {
+            assert_eq!(parse_record_id("event", ""), None);
+            assert_eq!(parse_record_id("event", "   "), None);
+        }
+
+        #[test]
+        fn checkpoint_specs() {
+            assert!(matches!(Checkpoint::parse(None), Ok(Checkpoint::Memory)));
+            assert!(matches!(Checkpoint::parse(Some("")), Ok(Checkpoint::Memory)));
+            assert!(matches!(Checkpoint::parse(Some("  ")), Ok(Checkpoint::Memory)));
+            assert!(matches!(Checkpoint::parse(Some("table:cf_checkpoint")), Ok(Checkpoint::Table(t)) if t == "cf_checkpoint"));
+            assert!(matches!(Checkpoint::parse(Some(" /var/lib/fs/cf.ckpt ")), Ok(Checkpoint::File(p)) if p == std::path::Path::new("/var/lib/fs/cf.ckpt")));
+            assert!(Checkpoint::parse(Some("table:")).is_err());
+            assert!(Checkpoint::parse(Some("table:bad-name")).is_err());
+        }
+
+        #[test]
+        fn change_sets_yield_record_changes() {
+            let set = surrealdb::value::to_value(serde_json::json!({
+                "versionstamp": 65536,
+                "changes": [
+                    { "define_table": { "name": "cdr" } },
+                    { "create": { "a": 1 } },
+                    { "update": { "a": 2 } },
+                    { "delete": { "a": 2 } },
+                ],
+            })).unwrap();
+            let (vs, changes) = parse_change_set(set).unwrap();
+            assert_eq!(vs, 1);
+            let changes: Vec<(c_int, JsonValue)> = changes.iter().map(|(a, row)| (*a, sur_to_json(row))).collect();
+            assert_eq!(changes, vec![
+                (ACTION_CREATE, serde_json::json!({ "a": 1 })),
+                (ACTION_UPDATE, serde_json::json!({ "a": 2 })),
+                (ACTION_DELETE, serde_json::json!({ "a": 2 })),
+            ]);
+            assert!(parse_change_set(surrealdb::value::to_value(serde_json::json!({ "changes": [] })).unwrap()).is_none());
+            assert!(parse_change_set(surrealdb::value::to_value(serde_json::json!([1])).unwrap()).is_none());
+        }
+
+        #[test]
+        fn insert_plan_skips_non_objects() {
+            let rows = vec![serde_json::json!({"a": 1}), serde_json::json!(5), serde_json::json!({"b": 2})];
+            let plan = InsertPlan::new("event", &rows);
+            assert_eq!(plan.sql, "CREATE event CONTENT $r0;\nCREATE event CONTENT $r2;\n");
+            assert_eq!(plan.stmt_rows, vec![0, 2]);
+            assert_eq!(plan.vars.get("r2"), Some(&rows[2]));
+            assert!(!plan.vars.contains_key("r1"));
+            assert_eq!(plan.errors, vec![None, Some("row is not a JSON object".to_string()), None]);
+        }
+
+        #[test]
+        fn insert_plan_maps_statement_errors_to_rows() {
+            let rows = vec![serde_json::json!(null), serde_json::json!({"a": 1}), serde_json::json!({"b": 2})];
+            let errors = InsertPlan::new("event", &rows).finish(vec![(1, "already exists".to_string()), (7, "ignored".to_string())]);
+            assert_eq!(errors, vec![Some("row is not a JSON object".to_string()), None, Some("already exists".to_string())]);
+        }
+
+        #[test]
+        fn values_render_as_plain_json() {
+            let doc = serde_json::json!({ "n": 1, "f": 1.5, "tags": ["a", null], "nested": { "ok": true } });
+            assert_eq!(sur_to_json(&surrealdb::value::to_value(doc.clone()).unwrap()), doc);
+            let rid = surrealdb::value::to_value(RecordId::from_table_key("event", "a1")).unwrap();
+            assert_eq!(sur_to_json(&rid), serde_json::json!("event:a1"));
+        }
+
+        #[test]
+        fn live_conditions_inline_their_vars() {
+            let vars = serde_json::json!({ "host": "sw1", "n": 3, "tags": ["a"] });
+            let vars = vars.as_object().unwrap();
+            assert_eq!(inline_vars("target = $host AND n > $n", vars), "target = ('sw1') AND n > (3)");
+            assert_eq!(inline_vars("tags CONTAINSANY $tags", vars), "tags CONTAINSANY (['a'])");
+            // Strings, quoted identifiers and unknown parameters are kept as written
+            assert_eq!(inline_vars("note = '$host' AND `$n` = $after.n", vars), "note = '$host' AND `$n` = $after.n");
+            assert_eq!(inline_vars("note = 'it\\'s $host'", vars), "note = 'it\\'s $host'");
+        }
+    }
+
+    /// Round trips through the C entry points against the in-memory engine.
+    #[cfg(all(test, feature = "embedded"))]
+    mod embedded_tests {
+        use super::*;
+
+        /// Each test opens its own database: `mem://` keeps one datastore per ns/db.
+        fn open(db: &str) -> *mut SurHandle {
+            assert_eq!(surreal_init_runtime(), 0);
+            let db = CString::new(db).unwrap();
+            let h = surreal_connect(c"mem://".as_ptr(), c"test".as_ptr(), db.as_ptr(), c"".as_ptr(), c"".as_ptr());
+            assert!(!h.is_null(), "connect failed: {}", surreal_last_error_global());
+            h
+        }
+
+        fn take_string(s: *mut c_char) -> String {
+            let text = unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();
+            surreal_free_string(s);
+            text
+        }
+
+        fn last_error(h: *mut SurHandle) -> JsonValue {
+            serde_json::from_str(&take_string(surreal_last_error_json(h))).unwrap()
+        }
+
+        /// Runs `sql` through `surreal_query_alloc` and returns the first statement's result,
+        /// retrying write conflicts with the subscription tasks.
+        fn query(h: *mut SurHandle, sql: &str) -> JsonValue {
+            let sql = CString::new(sql).unwrap();
+            for _ in 0..20 {
+                let mut out = std::ptr::null_mut();
+                assert_eq!(surreal_query_alloc(h, sql.as_ptr(), std::ptr::null(), &mut out), 0, "{}", last_error(h));
+                let results: JsonValue = serde_json::from_str(&take_string(out)).unwrap();
+                if results[0]["status"] == "OK" { return results[0]["result"].clone(); }
+                assert!(results[0]["result"].as_str().is_some_and(|e| e.contains("can be retried")), "{}", results);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            panic!("{:?} kept conflicting", sql);
+        }
+
+        fn wait_for(what: &str, done: impl Fn() -> bool) {
+            let until = Instant::now() + Duration::from_secs(10);
+            while !done() {
+                assert!(Instant::now() < until, "timed out waiting for {}", what);
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+
+        /// Subscriptions open their live query in the background, and the engine drops
+        /// notifications for writes that overlap its registration; wait until it is listed.
+        fn wait_live(h: *mut SurHandle, table: &str) {
+            let info = format!("INFO FOR TABLE {}", table);
+            wait_for("the live query", || query(h, &info)["lives"].as_object().is_some_and(|l| !l.is_empty()));
+        }
+
+        /// What a subscription callback received; leaked so it outlives the subscription task.
+        type Seen = Mutex<Vec<(c_int, JsonValue)>>;
+
+        fn seen() -> &'static Seen { Box::leak(Box::new(Mutex::new(Vec::new()))) }
+
+        fn seen_len(seen: &Seen) -> usize { seen.lock().unwrap().len() }
+
+        extern "C" fn on_command(_topic: *const c_char, json: *const c_char, user: *mut c_void) {
+            let seen = unsafe { &*(user as *const Seen) };
+            let json = serde_json::from_str(&unsafe { CStr::from_ptr(json) }.to_string_lossy()).unwrap();
+            seen.lock().unwrap().push((0, json));
+        }
+
+        extern "C" fn on_change(_topic: *const c_char, action: c_int, json: *const c_char, user: *mut c_void) {
+            let seen = unsafe { &*(user as *const Seen) };
+            let json = serde_json::from_str(&unsafe { CStr::from_ptr(json) }.to_string_lossy()).unwrap();
+            seen.lock().unwrap().push((action, json));
+        }
+
+        #[test]
+        fn publish_then_read_back() {
+            let h = open("publish_read");
+            assert_eq!(surreal_publish(h, c"event".as_ptr(), c"{\"n\":1}".as_ptr()), 0, "{}", last_error(h));
+            assert_eq!(surreal_publish(h, c"bad-table".as_ptr(), c"{}".as_ptr()), -3);
+            assert_eq!(surreal_publish(h, c"event".as_ptr(), c"{".as_ptr()), -5);
+            assert_eq!(last_error(h)["name"], "json");
+
+            let mut out = std::ptr::null_mut();
+            assert_eq!(surreal_select_alloc(h, c"event".as_ptr(), 0, &mut out), 0);
+            let rows: JsonValue = serde_json::from_str(&take_string(out)).unwrap();
+            assert_eq!(rows.as_array().map(Vec::len), Some(1));
+            assert_eq!(rows[0]["n"], 1);
+            let id = rows[0]["id"].as_str().unwrap().to_string();
+            assert!(id.starts_with("event:"), "{}", id);
+
+            let cid = CString::new(id.clone()).unwrap();
+            let mut buf = [0 as c_char; 256];
+            assert_eq!(surreal_get(h, c"event".as_ptr(), cid.as_ptr(), buf.as_mut_ptr(), buf.len() as u32), 0);
+            let row: JsonValue = serde_json::from_str(&unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy()).unwrap();
+            assert_eq!((row["id"].as_str(), &row["n"]), (Some(id.as_str()), &serde_json::json!(1)));
+            let mut tiny = [0 as c_char; 4];
+            assert_eq!(surreal_get(h, c"event".as_ptr(), cid.as_ptr(), tiny.as_mut_ptr(), tiny.len() as u32), -20);
+
+            assert_eq!(query(h, "SELECT VALUE n FROM event"), serde_json::json!([1]));
+            surreal_close(h);
+        }
+
+        #[test]
+        fn batch_reports_rejected_rows() {
+            let h = open("batch");
+            assert_eq!(surreal_publish_batch(h, c"cdr".as_ptr(), c"[{\"a\":1},{\"a\":2}]".as_ptr()), 0, "{}", last_error(h));
+            assert_eq!(surreal_publish_batch(h, c"cdr".as_ptr(), c"[]".as_ptr()), 0);
+            // The second row is not an object and the third reuses an id; only the first lands
+            let rc = surreal_publish_batch(h, c"cdr".as_ptr(), c"[{\"a\":3},5,{\"id\":\"x\"},{\"id\":\"x\"}]".as_ptr());
+            assert_eq!(rc, -7);
+            let err = last_error(h);
+            assert_eq!(err["name"], "query");
+            let failed: JsonValue = serde_json::from_str(err["message"].as_str().unwrap()).unwrap();
+            let indexes: Vec<u64> = failed.as_array().unwrap().iter().map(|f| f["index"].as_u64().unwrap()).collect();
+            assert_eq!(indexes, vec![1, 3]);
+            assert_eq!(query(h, "RETURN count(SELECT id FROM cdr)"), serde_json::json!(4));
+            surreal_close(h);
+        }
+
+        #[test]
+        fn subscribe_claims_published_rows() {
+            let h = open("subscribe");
+            query(h, "DEFINE TABLE cmd SCHEMALESS");
+            let got = seen();
+            let user = got as *const Seen as *mut c_void;
+            assert_eq!(surreal_subscribe_where(h, c"cmd".as_ptr(), c"kind = $k".as_ptr(), c"{\"k\":\"go\"}".as_ptr(), Some(on_command), user), 0, "{}", last_error(h));
+            wait_live(h, "cmd");
+            assert_eq!(surreal_publish(h, c"cmd".as_ptr(), c"{\"status\":\"new\",\"kind\":\"go\"}".as_ptr()), 0, "{}", last_error(h));
+            assert_eq!(surreal_publish(h, c"cmd".as_ptr(), c"{\"status\":\"new\",\"kind\":\"skip\"}".as_ptr()), 0, "{}", last_error(h));
+            wait_for("the command", || seen_len(got) == 1);
+
+            let (_, row) = got.lock().unwrap()[0].clone();
+            assert_eq!(row["kind"], "go");
+            let id = row["id"].as_str().unwrap();
+            let claimed = query(h, &format!("SELECT VALUE status FROM ONLY {}", id));
+            assert_eq!(claimed, "processing");
+            assert_eq!(query(h, "SELECT VALUE status FROM cmd WHERE kind = 'skip'"), serde_json::json!(["new"]));
+            // A bad condition is rejected up front, not retried in the background
+            assert_eq!(surreal_subscribe_where(h, c"cmd".as_ptr(), c"kind = = 1".as_ptr(), std::ptr::null(), Some(on_command), user), SurError::Query.code());
+            surreal_close(h);
+        }
+
+        #[test]
+        fn raw_subscription_sees_every_change() {
+            let h = open("subscribe_raw");
+            query(h, "DEFINE TABLE obs SCHEMALESS");
+            let got = seen();
+            assert_eq!(surreal_subscribe_raw(h, c"obs".as_ptr(), std::ptr::null(), std::ptr::null(), Some(on_change), got as *const Seen as *mut c_void), 0, "{}", last_error(h));
+            wait_live(h, "obs");
+            query(h, "CREATE obs:a SET v = 1");
+            query(h, "UPDATE obs:a SET v = 100");
+            query(h, "DELETE obs:a");
+            wait_for("the delete", || got.lock().unwrap().iter().any(|(a, _)| *a == ACTION_DELETE));
+            let seen = got.lock().unwrap().clone();
+            assert!(seen.iter().all(|(_, row)| row["id"] == "obs:a"), "{:?}", seen);
+            assert!(seen.iter().any(|(a, row)| *a == ACTION_UPDATE && row["v"] == 100), "{:?}", seen);
+            surreal_close(h);
+        }
+
+        /// Reads `id` as a subscription would have seen it, to claim it later.
+        fn read_row(h: *mut SurHandle, id: &str) -> surrealdb::Value {
+            let h = unsafe { &*h };
+            let client = h.pool.checkout().unwrap();
+            let rid: RecordId = id.parse().unwrap();
+            RUNTIME.get().unwrap().block_on(async {
+                client.query("SELECT * FROM ONLY $rid").bind(("rid", rid)).await.unwrap().take::<surrealdb::Value>(0).unwrap()
+            })
+        }
+
+        fn claim(h: *mut SurHandle, row: surrealdb::Value, user: &'static Seen) {
+            let h = unsafe { &*h };
+            let client = h.pool.checkout().unwrap();
+            let user = user as *const Seen as usize;
+            RUNTIME.get().unwrap().block_on(claim_and_deliver(&client, &h.pool.metrics, &h.claimant, "cmd", row, Some(on_command), user));
+        }
+
+        #[test]
+        fn claim_loses_to_another_claimant() {
+            let h = open("claim_cas");
+            assert_eq!(surreal_set_claim_options(h, c"node-a".as_ptr(), 0), 0);
+            let got = seen();
+            query(h, "CREATE cmd:1 SET status = 'new'; CREATE cmd:2 SET status = 'new'");
+
+            // Another node claims cmd:1 between this node seeing it new and claiming it
+            let stale = read_row(h, "cmd:1");
+            query(h, "UPDATE cmd:1 SET status = 'processing', claimed_by = 'node-b'");
+            claim(h, stale, got);
+            assert_eq!(seen_len(got), 0);
+            assert_eq!(query(h, "SELECT VALUE claimed_by FROM ONLY cmd:1"), "node-b");
+
+            claim(h, read_row(h, "cmd:2"), got);
+            assert_eq!(seen_len(got), 1);
+            assert_eq!(query(h, "SELECT status, claimed_by FROM ONLY cmd:2"), serde_json::json!({ "status": "processing", "claimed_by": "node-a" }));
+
+            let metrics = &unsafe { &*h }.pool.metrics;
+            assert_eq!((metrics.claims.load(Ordering::Relaxed), metrics.claim_conflicts.load(Ordering::Relaxed)), (1, 1));
+            surreal_close(h);
+        }
+
+        #[test]
+        fn competing_subscribers_deliver_each_command_once() {
+            let (a, b) = (open("claim_race"), open("claim_race"));
+            query(a, "DEFINE TABLE cmd SCHEMALESS");
+            let (got_a, got_b) = (seen(), seen());
+            for (h, node, got) in [(a, c"node-a", got_a), (b, c"node-b", got_b)] {
+                assert_eq!(surreal_set_claim_options(h, node.as_ptr(), 0), 0);
+                assert_eq!(surreal_subscribe(h, c"cmd".as_ptr(), Some(on_command), got as *const Seen as *mut c_void), 0, "{}", last_error(h));
+                wait_live(h, "cmd");
+            }
+            wait_for("both live queries", || query(a, "INFO FOR TABLE cmd")["lives"].as_object().is_some_and(|l| l.len() == 2));
+            for _ in 0..20 {
+                assert_eq!(surreal_publish(a, c"cmd".as_ptr(), c"{\"status\":\"new\"}".as_ptr()), 0, "{}", last_error(a));
+            }
+            wait_for("every command", || seen_len(got_a) + seen_len(got_b) >= 20);
+
+            let mut ids = Vec::new();
+            for (node, got) in [("node-a", got_a), ("node-b", got_b)] {
+                for (_, row) in got.lock().unwrap().iter() {
+                    let id = row["id"].as_str().unwrap().to_string();
+                    assert_eq!(query(a, &format!("SELECT VALUE claimed_by FROM ONLY {}", id)), node);
+                    ids.push(id);
+                }
+            }
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 20, "a command was delivered twice");
+            surreal_close(a);
+            surreal_close(b);
+        }
+
+        #[test]
+        fn expired_claims_return_to_new_then_fail() {
+            let h = open("reap");
+            assert_eq!(surreal_set_claim_recovery(h, 0, 2), 0);
+            let expire = "UPDATE cmd:1 SET status = 'processing', claimed_by = 'node-b', lease_until = time::now() - 1s";
+            query(h, "CREATE cmd:1 SET status = 'new'");
+            query(h, expire);
+            // Claims still within their lease, or without one, are not touched
+            query(h, "CREATE cmd:2 SET status = 'processing', lease_until = time::now() + 1h; CREATE cmd:3 SET status = 'processing'");
+            let reap = || {
+                let h = unsafe { &*h };
+                let client = h.pool.checkout().unwrap();
+                RUNTIME.get().unwrap().block_on(reap_expired(&client, &h.pool.metrics, &h.claimant, "cmd"));
+            };
+
+            reap();
+            assert_eq!(query(h, "SELECT status, attempts, claimed_by, lease_until FROM ONLY cmd:1"), serde_json::json!({ "status": "new", "attempts": 1, "claimed_by": null, "lease_until": null }));
+            query(h, expire);
+            reap();
+            assert_eq!(query(h, "SELECT VALUE [status, attempts] FROM ONLY cmd:1"), serde_json::json!(["failed", 2]));
+            assert_eq!(query(h, "SELECT VALUE status FROM cmd:2, cmd:3"), serde_json::json!(["processing", "processing"]));
+            assert_eq!(unsafe { &*h }.pool.metrics.claims_expired.load(Ordering::Relaxed), 2);
+            surreal_close(h);
+        }
+
+        #[test]
+        fn catch_up_pages_through_the_backlog() {
+            let h = open("catch_up");
+            // Five timestamps shared by many rows, so pages break inside runs of equal created_at
+            query(h, "FOR $i IN 1..=250 { CREATE type::thing('cmd', $i) SET status = 'new', \
+                      kind = IF $i % 10 = 0 THEN 'skip' ELSE 'go' END, \
+                      created_at = d'2024-01-01T00:00:00Z' + duration::from::secs($i % 5) }");
+            let got = seen();
+            let filter = Filter { condition: "kind = $k".to_string(), vars: serde_json::json!({ "k": "go" }).as_object().unwrap().clone() };
+            {
+                let h = unsafe { &*h };
+                let client = h.pool.checkout().unwrap();
+                let user = got as *const Seen as usize;
+                RUNTIME.get().unwrap().block_on(catch_up(&client, &h.pool.metrics, &h.claimant, "cmd", Some(&filter), Some(on_command), user));
+            }
+
+            let mut ids: Vec<String> = got.lock().unwrap().iter().map(|(_, row)| row["id"].as_str().unwrap().to_string()).collect();
+            assert_eq!(ids.len(), 225);
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 225, "a row was delivered twice");
+            assert_eq!(query(h, "RETURN count(SELECT id FROM cmd WHERE status = 'new')"), serde_json::json!(25));
+            surreal_close(h);
+        }
+
+        #[test]
+        fn changefeed_resumes_from_its_checkpoint() {
+            let file = std::env::temp_dir().join(format!("surrealdb_ffi_cf_{}", std::process::id()));
+            let _ = std::fs::remove_file(&file);
+            for (db, spec) in [("cf_table", "table:cf_checkpoint".to_string()), ("cf_file", file.display().to_string())] {
+                let h = open(db);
+                query(h, "DEFINE TABLE feed CHANGEFEED 1h");
+                let spec = CString::new(spec).unwrap();
+                let checkpointed = || match file.exists() {
+                    _ if db == "cf_table" => query(h, "SELECT VALUE versionstamp FROM cf_checkpoint").as_array().is_some_and(|v| !v.is_empty()),
+                    exists => exists,
+                };
+
+                let first = seen();
+                assert_eq!(surreal_subscribe_changefeed(h, c"feed".as_ptr(), spec.as_ptr(), 20, Some(on_change), first as *const Seen as *mut c_void), 0, "{}", last_error(h));
+                query(h, "CREATE feed:1; CREATE feed:2");
+                wait_for("the first changes", || seen_len(first) == 2);
+                wait_for("the checkpoint", checkpointed);
+                assert_eq!(surreal_unsubscribe_kind(h, c"feed".as_ptr(), SUB_CHANGEFEED), 0);
+
+                // Changes made while it was stopped are delivered, earlier ones are not repeated
+                query(h, "UPDATE feed:2 SET v = 1");
+                let second = seen();
+                assert_eq!(surreal_subscribe_changefeed(h, c"feed".as_ptr(), spec.as_ptr(), 20, Some(on_change), second as *const Seen as *mut c_void), 0, "{}", last_error(h));
+                wait_for("the change made while stopped", || seen_len(second) > 0);
+                std::thread::sleep(Duration::from_millis(100));
+                let seen = second.lock().unwrap().clone();
+                assert_eq!(seen.len(), 1, "{:?}", seen);
+                assert_eq!((seen[0].0, &seen[0].1["id"], &seen[0].1["v"]), (ACTION_UPDATE, &serde_json::json!("feed:2"), &serde_json::json!(1)));
+                surreal_close(h);
             }
+            let _ = std::fs::remove_file(&file);
         }
     }
 }
@@ -0,0 +1,261 @@
+//! Log routing for the real client. FFI messages and `tracing` events (the SurrealDB SDK and
+//! its dependencies) go to one place: the leveled handler from `surreal_set_log_handler`,
+//! else the plain `surreal_set_logger` callback (INFO and above), else stderr when
+//! `SURREALDB_FFI_TRACE` is set. Nothing is printed by default.
+
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, Once};
+
+use serde_json::Value as JsonValue;
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+pub(crate) const LOG_ERROR: c_int = 1;
+pub(crate) const LOG_WARN: c_int = 2;
+pub(crate) const LOG_INFO: c_int = 3;
+pub(crate) const LOG_DEBUG: c_int = 4;
+pub(crate) const LOG_TRACE: c_int = 5;
+
+pub type SurLogCb = Option<extern "C" fn(msg: *const c_char, user_data: *mut c_void)>;
+pub type SurLogHandlerCb = Option<extern "C" fn(level: c_int, target: *const c_char, msg: *const c_char, fields_json: *const c_char, user_data: *mut c_void)>;
+
+#[derive(Clone, Copy)]
+enum Sink {
+    None,
+    Plain(extern "C" fn(*const c_char, *mut c_void), usize),
+    Leveled(extern "C" fn(c_int, *const c_char, *const c_char, *const c_char, *mut c_void), usize),
+}
+
+static SINK: Mutex<Sink> = Mutex::new(Sink::None);
+/// Most verbose level delivered; checked before any formatting happens.
+static MAX_LEVEL: AtomicI32 = AtomicI32::new(LOG_INFO);
+static BRIDGE: Once = Once::new();
+
+fn trace_enabled() -> bool {
+    trace_value_enabled(std::env::var("SURREALDB_FFI_TRACE").ok().as_deref())
+}
+
+/// Unset, empty, `0`, `false` and `off` keep stderr quiet.
+fn trace_value_enabled(value: Option<&str>) -> bool {
+    match value.map(|v| v.trim().to_ascii_lowercase()) {
+        Some(v) => !(v.is_empty() || v == "0" || v == "false" || v == "off"),
+        None => false,
+    }
+}
+
+pub(crate) fn enabled(level: c_int) -> bool {
+    level <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_plain(cb: SurLogCb, user: usize) {
+    if let Ok(mut s) = SINK.lock() { *s = cb.map_or(Sink::None, |f| Sink::Plain(f, user)); }
+    MAX_LEVEL.store(LOG_INFO, Ordering::Relaxed);
+    install_bridge();
+}
+
+pub(crate) fn set_leveled(cb: SurLogHandlerCb, max_level: c_int, user: usize) {
+    if let Ok(mut s) = SINK.lock() { *s = cb.map_or(Sink::None, |f| Sink::Leveled(f, user)); }
+    MAX_LEVEL.store(max_level.clamp(LOG_ERROR, LOG_TRACE), Ordering::Relaxed);
+    install_bridge();
+}
+
+/// Delivers one record. `fields` is a JSON object of structured values (None = `{}`).
+pub(crate) fn emit(level: c_int, target: &str, msg: &str, fields: Option<&JsonValue>) {
+    if !enabled(level) { return; }
+    let sink = SINK.lock().map(|s| *s).unwrap_or(Sink::None);
+    let fields_txt = fields.map_or_else(|| "{}".to_string(), |f| f.to_string());
+    match sink {
+        Sink::Leveled(cb, user) => {
+            let (Ok(t), Ok(m), Ok(f)) = (CString::new(target), CString::new(msg), CString::new(fields_txt)) else { return };
+            cb(level, t.as_ptr(), m.as_ptr(), f.as_ptr(), user as *mut c_void);
+        }
+        Sink::Plain(cb, user) => {
+            if level > LOG_INFO { return; }
+            let line = if fields_txt == "{}" { msg.to_string() } else { format!("{} {}", msg, fields_txt) };
+            if let Ok(m) = CString::new(line) { cb(m.as_ptr(), user as *mut c_void); }
+        }
+        Sink::None if trace_enabled() => {
+            if fields_txt == "{}" { eprintln!("[{}] {}", target, msg) } else { eprintln!("[{}] {} {}", target, msg, fields_txt) }
+        }
+        Sink::None => {}
+    }
+}
+
+fn level_of(level: &tracing::Level) -> c_int {
+    match *level {
+        tracing::Level::ERROR => LOG_ERROR,
+        tracing::Level::WARN => LOG_WARN,
+        tracing::Level::INFO => LOG_INFO,
+        tracing::Level::DEBUG => LOG_DEBUG,
+        tracing::Level::TRACE => LOG_TRACE,
+    }
+}
+
+/// Installs the `tracing` bridge as the process-wide subscriber, once. If the host already
+/// installed one, SDK events stay with it and only FFI messages reach the handler.
+fn install_bridge() {
+    BRIDGE.call_once(|| {
+        let subscriber = tracing_subscriber::registry().with(Bridge);
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            emit(LOG_DEBUG, "surrealdb_ffi", "a tracing subscriber is already installed; SDK events are not bridged", None);
+        }
+    });
+}
+
+struct Bridge;
+
+impl<S: Subscriber> Layer<S> for Bridge {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // The level and handler can change at runtime, so decide per event
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        enabled(level_of(metadata.level()))
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let meta = event.metadata();
+        emit(level_of(meta.level()), meta.target(), &fields.message, Some(&JsonValue::Object(fields.values)));
+    }
+}
+
+/// Collects an event's fields: `message` becomes the text, the rest the JSON fields.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    values: serde_json::Map<String, JsonValue>,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.values.insert(field.name().to_string(), JsonValue::String(format!("{:?}", value)));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.values.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) { self.values.insert(field.name().to_string(), value.into()); }
+    fn record_u64(&mut self, field: &Field, value: u64) { self.values.insert(field.name().to_string(), value.into()); }
+    fn record_bool(&mut self, field: &Field, value: bool) { self.values.insert(field.name().to_string(), value.into()); }
+    fn record_f64(&mut self, field: &Field, value: f64) { self.values.insert(field.name().to_string(), value.into()); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    /// The sink and level are process-wide, so tests that set them run one at a time.
+    static SERIAL: Mutex<()> = Mutex::new(());
+    static SEEN: Mutex<Vec<(c_int, String, String, String)>> = Mutex::new(Vec::new());
+
+    extern "C" fn record(level: c_int, target: *const c_char, msg: *const c_char, fields: *const c_char, _user: *mut c_void) {
+        let text = |p: *const c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+        SEEN.lock().unwrap().push((level, text(target), text(msg), text(fields)));
+    }
+
+    extern "C" fn record_plain(msg: *const c_char, _user: *mut c_void) {
+        let msg = unsafe { CStr::from_ptr(msg) }.to_string_lossy().into_owned();
+        SEEN.lock().unwrap().push((0, String::new(), msg, String::new()));
+    }
+
+    /// Runs `f` with the leveled test handler at `max_level` and returns what it received
+    /// for `test::` targets (other tests may log concurrently).
+    fn capture(max_level: c_int, f: impl FnOnce()) -> Vec<(c_int, String, String, String)> {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        SEEN.lock().unwrap().clear();
+        set_leveled(Some(record), max_level, 0);
+        f();
+        set_leveled(None, LOG_INFO, 0);
+        std::mem::take(&mut *SEEN.lock().unwrap()).into_iter().filter(|r| r.1.starts_with("test::")).collect()
+    }
+
+    #[test]
+    fn max_level_filters() {
+        let seen = capture(LOG_WARN, || {
+            assert!(enabled(LOG_ERROR) && enabled(LOG_WARN));
+            assert!(!enabled(LOG_INFO));
+            emit(LOG_ERROR, "test::levels", "e", None);
+            emit(LOG_WARN, "test::levels", "w", None);
+            emit(LOG_INFO, "test::levels", "i", None);
+            emit(LOG_TRACE, "test::levels", "x", None);
+        });
+        let levels: Vec<c_int> = seen.iter().map(|r| r.0).collect();
+        assert_eq!(levels, vec![LOG_ERROR, LOG_WARN]);
+    }
+
+    #[test]
+    fn max_level_is_clamped() {
+        capture(99, || assert!(enabled(LOG_TRACE)));
+        capture(-5, || {
+            assert!(enabled(LOG_ERROR));
+            assert!(!enabled(LOG_WARN));
+        });
+    }
+
+    #[test]
+    fn fields_default_to_empty_object() {
+        let seen = capture(LOG_INFO, || {
+            emit(LOG_INFO, "test::fields", "plain", None);
+            emit(LOG_INFO, "test::fields", "with", Some(&serde_json::json!({ "table": "t", "count": 2 })));
+        });
+        assert_eq!(seen[0], (LOG_INFO, "test::fields".to_string(), "plain".to_string(), "{}".to_string()));
+        assert_eq!(serde_json::from_str::<JsonValue>(&seen[1].3).unwrap(), serde_json::json!({ "table": "t", "count": 2 }));
+    }
+
+    #[test]
+    fn bridge_renders_event_fields_as_json() {
+        let seen = capture(LOG_DEBUG, || {
+            let subscriber = tracing_subscriber::registry().with(Bridge);
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::warn!(target: "test::bridge", rows = 3u64, delta = -1i64, table = "cdr", ok = true, addr = ?("db", 8000), "retrying {}", 2);
+                tracing::trace!(target: "test::bridge", "too verbose");
+            });
+        });
+        assert_eq!(seen.len(), 1);
+        let (level, target, msg, fields) = &seen[0];
+        assert_eq!((*level, target.as_str(), msg.as_str()), (LOG_WARN, "test::bridge", "retrying 2"));
+        assert_eq!(serde_json::from_str::<JsonValue>(fields).unwrap(), serde_json::json!({
+            "rows": 3, "delta": -1, "table": "cdr", "ok": true, "addr": "(\"db\", 8000)",
+        }));
+    }
+
+    #[test]
+    fn plain_callback_gets_info_and_above() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        SEEN.lock().unwrap().clear();
+        set_plain(Some(record_plain), 0);
+        emit(LOG_INFO, "test::plain", "hello", Some(&serde_json::json!({ "n": 1 })));
+        emit(LOG_DEBUG, "test::plain", "dropped", None);
+        set_plain(None, 0);
+        let seen: Vec<String> = std::mem::take(&mut *SEEN.lock().unwrap()).into_iter().map(|r| r.2).filter(|m| m.starts_with("hello") || m == "dropped").collect();
+        assert_eq!(seen, vec![r#"hello {"n":1}"#.to_string()]);
+    }
+
+    #[test]
+    fn stderr_needs_trace_variable() {
+        for off in [None, Some(""), Some("0"), Some("false"), Some(" OFF ")] {
+            assert!(!trace_value_enabled(off), "{:?}", off);
+        }
+        for on in [Some("1"), Some("true"), Some("debug")] {
+            assert!(trace_value_enabled(on), "{:?}", on);
+        }
+    }
+}
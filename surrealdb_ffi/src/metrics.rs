@@ -0,0 +1,296 @@
+//! Per-handle counters and latency histograms, rendered as JSON for `surreal_stats_json`
+//! and as Prometheus text exposition for `surreal_stats_prometheus`.
+//! Everything is a relaxed atomic: recording never blocks a caller, and a scrape may see
+//! counters from slightly different instants.
+
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::Value as JsonValue;
+
+use crate::error::SurError;
+
+/// Histogram bucket upper bounds in milliseconds; a final +Inf bucket catches the rest.
+const BUCKETS_MS: [u64; 12] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 10_000];
+
+const KINDS: [SurError; 13] = SurError::ALL;
+
+#[derive(Default)]
+pub(crate) struct Histogram {
+    /// Non-cumulative counts per bucket, the last one being +Inf.
+    buckets: [AtomicU64; BUCKETS_MS.len() + 1],
+    sum_us: AtomicU64,
+}
+
+impl Histogram {
+    pub(crate) fn observe(&self, d: Duration) {
+        let us = d.as_micros() as u64;
+        let i = BUCKETS_MS.iter().position(|&b| us <= b * 1000).unwrap_or(BUCKETS_MS.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(d.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Awaits `fut` and records how long it took.
+    pub(crate) async fn time<F: Future>(&self, fut: F) -> F::Output {
+        let started = Instant::now();
+        let out = fut.await;
+        self.observe(started.elapsed());
+        out
+    }
+
+    fn cumulative(&self) -> Vec<u64> {
+        let mut total = 0;
+        self.buckets.iter().map(|b| { total += b.load(Ordering::Relaxed); total }).collect()
+    }
+
+    fn json(&self) -> JsonValue {
+        let cum = self.cumulative();
+        let mut buckets = serde_json::Map::new();
+        for (bound, n) in BUCKETS_MS.iter().zip(&cum) { buckets.insert(bound.to_string(), (*n).into()); }
+        buckets.insert("+Inf".to_string(), cum[BUCKETS_MS.len()].into());
+        serde_json::json!({
+            "count": cum[BUCKETS_MS.len()],
+            "sum_ms": self.sum_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            "buckets": buckets,
+        })
+    }
+
+    fn prometheus(&self, out: &mut String, name: &str, help: &str) {
+        let cum = self.cumulative();
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        for (bound, n) in BUCKETS_MS.iter().zip(&cum) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, *bound as f64 / 1000.0, n);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, cum[BUCKETS_MS.len()]);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{}_count {}", name, cum[BUCKETS_MS.len()]);
+    }
+}
+
+/// Values owned elsewhere (pool, spool, async queue), sampled at render time.
+pub(crate) struct Gauges {
+    pub state: &'static str,
+    pub pool_open: u64,
+    pub pool_busy: u64,
+    pub connects: u64,
+    pub connect_failures: u64,
+    pub subscriptions: u64,
+    pub async_depth: u64,
+    pub spool_depth: u64,
+    pub spool_bytes: u64,
+    pub spool_dropped: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    pub publish_ok: AtomicU64,
+    pub publish_spooled: AtomicU64,
+    pub publish_failed: AtomicU64,
+    /// One sample per insert request: a single publish, a batch, or an async flush.
+    pub publish_latency: Histogram,
+    /// select, get, update and query round trips.
+    pub query_latency: Histogram,
+    failures: [AtomicU64; KINDS.len()],
+    pub reconnects: AtomicU64,
+    pub notifications: AtomicU64,
+    pub claims: AtomicU64,
+    pub claim_failures: AtomicU64,
+    /// Claims that found the row already taken by another node.
+    pub claim_conflicts: AtomicU64,
+    /// Claims whose lease expired and were returned to `new` or marked `failed`.
+    pub claims_expired: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn failure(&self, kind: SurError) {
+        if let Some(i) = KINDS.iter().position(|&k| k == kind) { self.failures[i].fetch_add(1, Ordering::Relaxed); }
+    }
+
+    /// Counts a publish outcome by its return code: 0 sent, 1 spooled, negative failed.
+    pub(crate) fn published(&self, rc: i32, docs: u64) {
+        let counter = match rc {
+            0 => &self.publish_ok,
+            1 => &self.publish_spooled,
+            _ => &self.publish_failed,
+        };
+        counter.fetch_add(docs, Ordering::Relaxed);
+    }
+
+    pub(crate) fn json(&self, g: &Gauges) -> JsonValue {
+        let errors: serde_json::Map<String, JsonValue> = KINDS.iter().zip(&self.failures)
+            .map(|(k, n)| (k.name().to_string(), n.load(Ordering::Relaxed).into()))
+            .collect();
+        serde_json::json!({
+            "state": g.state,
+            "publish": {
+                "ok": self.publish_ok.load(Ordering::Relaxed),
+                "spooled": self.publish_spooled.load(Ordering::Relaxed),
+                "failed": self.publish_failed.load(Ordering::Relaxed),
+                "latency_ms": self.publish_latency.json(),
+            },
+            "query": { "latency_ms": self.query_latency.json() },
+            "errors": errors,
+            "connections": {
+                "open": g.pool_open,
+                "busy": g.pool_busy,
+                "connects": g.connects,
+                "connect_failures": g.connect_failures,
+                "reconnects": self.reconnects.load(Ordering::Relaxed),
+            },
+            "subscriptions": {
+                "active": g.subscriptions,
+                "notifications": self.notifications.load(Ordering::Relaxed),
+                "claims": self.claims.load(Ordering::Relaxed),
+                "claim_failures": self.claim_failures.load(Ordering::Relaxed),
+                "claim_conflicts": self.claim_conflicts.load(Ordering::Relaxed),
+                "claims_expired": self.claims_expired.load(Ordering::Relaxed),
+            },
+            "queue": {
+                "async_depth": g.async_depth,
+                "spool_depth": g.spool_depth,
+                "spool_bytes": g.spool_bytes,
+                "spool_dropped": g.spool_dropped,
+            },
+        })
+    }
+
+    pub(crate) fn prometheus(&self, g: &Gauges) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, u64)]| {
+            let _ = writeln!(out, "# HELP surrealdb_ffi_{} {}\n# TYPE surrealdb_ffi_{} {}", name, help, name, kind);
+            for (labels, v) in samples {
+                let _ = writeln!(out, "surrealdb_ffi_{}{} {}", name, labels, v);
+            }
+        };
+        metric("publish_documents_total", "counter", "Documents published, by outcome.", &[
+            ("{result=\"ok\"}", self.publish_ok.load(Ordering::Relaxed)),
+            ("{result=\"spooled\"}", self.publish_spooled.load(Ordering::Relaxed)),
+            ("{result=\"failed\"}", self.publish_failed.load(Ordering::Relaxed)),
+        ]);
+        let labels: Vec<String> = KINDS.iter().map(|k| format!("{{kind=\"{}\"}}", k.name())).collect();
+        let errors: Vec<(&str, u64)> = labels.iter().zip(&self.failures).map(|(l, n)| (l.as_str(), n.load(Ordering::Relaxed))).collect();
+        metric("errors_total", "counter", "Failed calls, by error category.", &errors);
+        metric("connects_total", "counter", "Connections opened.", &[("", g.connects)]);
+        metric("connect_failures_total", "counter", "Connection attempts that failed.", &[("", g.connect_failures)]);
+        metric("reconnects_total", "counter", "Times the supervisor restored a lost connection.", &[("", self.reconnects.load(Ordering::Relaxed))]);
+        metric("notifications_total", "counter", "Subscription notifications and polled rows received.", &[("", self.notifications.load(Ordering::Relaxed))]);
+        metric("claims_total", "counter", "Subscription rows claimed, by outcome.", &[
+            ("{result=\"ok\"}", self.claims.load(Ordering::Relaxed)),
+            ("{result=\"failed\"}", self.claim_failures.load(Ordering::Relaxed)),
+            ("{result=\"conflict\"}", self.claim_conflicts.load(Ordering::Relaxed)),
+        ]);
+        metric("claims_expired_total", "counter", "Claims recovered after their lease expired.", &[("", self.claims_expired.load(Ordering::Relaxed))]);
+        metric("spool_dropped_total", "counter", "Spooled documents evicted by the size or age bounds.", &[("", g.spool_dropped)]);
+        metric("connected", "gauge", "1 while the handle is connected.", &[("", (g.state == "connected") as u64)]);
+        metric("pool_open_connections", "gauge", "Open pooled connections.", &[("", g.pool_open)]);
+        metric("pool_busy_connections", "gauge", "Pooled connections in use.", &[("", g.pool_busy)]);
+        metric("subscriptions", "gauge", "Active subscriptions.", &[("", g.subscriptions)]);
+        metric("async_queue_depth", "gauge", "Documents waiting in the async publish queue.", &[("", g.async_depth)]);
+        metric("spool_depth", "gauge", "Documents waiting in the spool.", &[("", g.spool_depth)]);
+        metric("spool_bytes", "gauge", "Spool size on disk in bytes.", &[("", g.spool_bytes)]);
+        self.publish_latency.prometheus(&mut out, "surrealdb_ffi_publish_duration_seconds", "Insert request latency.");
+        self.query_latency.prometheus(&mut out, "surrealdb_ffi_query_duration_seconds", "Select, get, update and query latency.");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gauges() -> Gauges {
+        Gauges {
+            state: "connected", pool_open: 2, pool_busy: 1, connects: 3, connect_failures: 1,
+            subscriptions: 1, async_depth: 4, spool_depth: 5, spool_bytes: 600, spool_dropped: 7,
+        }
+    }
+
+    fn sample(m: &Metrics) {
+        m.publish_latency.observe(Duration::from_micros(800));
+        m.publish_latency.observe(Duration::from_millis(30));
+        m.publish_latency.observe(Duration::from_secs(20));
+        m.published(0, 3);
+        m.published(1, 2);
+        m.published(-21, 1);
+        m.failure(SurError::Timeout);
+        m.failure(SurError::Timeout);
+        m.claim_conflicts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let h = Histogram::default();
+        h.observe(Duration::from_micros(800));
+        h.observe(Duration::from_millis(1));
+        h.observe(Duration::from_millis(30));
+        h.observe(Duration::from_secs(20));
+        let cum = h.cumulative();
+        assert_eq!(cum[0], 2);
+        assert_eq!(cum[5], 3);
+        assert_eq!(cum[BUCKETS_MS.len() - 1], 3);
+        assert_eq!(cum[BUCKETS_MS.len()], 4);
+    }
+
+    #[test]
+    fn json_rendering() {
+        let m = Metrics::default();
+        sample(&m);
+        let v = m.json(&gauges());
+        assert_eq!(v["state"], "connected");
+        assert_eq!(v["publish"]["ok"], 3);
+        assert_eq!(v["publish"]["spooled"], 2);
+        assert_eq!(v["publish"]["failed"], 1);
+        assert_eq!(v["publish"]["latency_ms"]["count"], 3);
+        assert_eq!(v["publish"]["latency_ms"]["sum_ms"], 20_030.8);
+        assert_eq!(v["publish"]["latency_ms"]["buckets"]["1"], 1);
+        assert_eq!(v["publish"]["latency_ms"]["buckets"]["+Inf"], 3);
+        assert_eq!(v["query"]["latency_ms"]["count"], 0);
+        assert_eq!(v["errors"]["timeout"], 2);
+        assert_eq!(v["errors"]["auth"], 0);
+        assert_eq!(v["errors"].as_object().unwrap().len(), KINDS.len());
+        assert_eq!(v["connections"]["busy"], 1);
+        assert_eq!(v["subscriptions"]["claim_conflicts"], 1);
+        assert_eq!(v["queue"]["spool_dropped"], 7);
+    }
+
+    #[test]
+    fn prometheus_rendering() {
+        let m = Metrics::default();
+        sample(&m);
+        let text = m.prometheus(&gauges());
+        for line in [
+            "# TYPE surrealdb_ffi_publish_documents_total counter",
+            "surrealdb_ffi_publish_documents_total{result=\"ok\"} 3",
+            "surrealdb_ffi_publish_documents_total{result=\"failed\"} 1",
+            "surrealdb_ffi_errors_total{kind=\"timeout\"} 2",
+            "surrealdb_ffi_errors_total{kind=\"queue_full\"} 0",
+            "surrealdb_ffi_claims_total{result=\"conflict\"} 1",
+            "surrealdb_ffi_claims_expired_total 0",
+            "surrealdb_ffi_connected 1",
+            "# TYPE surrealdb_ffi_spool_bytes gauge",
+            "surrealdb_ffi_spool_bytes 600",
+            "# TYPE surrealdb_ffi_publish_duration_seconds histogram",
+            "surrealdb_ffi_publish_duration_seconds_bucket{le=\"0.001\"} 1",
+            "surrealdb_ffi_publish_duration_seconds_bucket{le=\"10\"} 2",
+            "surrealdb_ffi_publish_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "surrealdb_ffi_publish_duration_seconds_count 3",
+            "surrealdb_ffi_query_duration_seconds_count 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+        // Every sample belongs to a declared metric
+        for l in text.lines().filter(|l| !l.starts_with('#')) {
+            let name = l.split(['{', ' ']).next().unwrap();
+            let family = name.trim_end_matches("_bucket").trim_end_matches("_sum").trim_end_matches("_count");
+            assert!(text.contains(&format!("# TYPE {} ", family)), "undeclared {}", name);
+        }
+    }
+
+    #[test]
+    fn disconnected_state() {
+        let g = Gauges { state: "reconnecting", ..gauges() };
+        assert!(Metrics::default().prometheus(&g).lines().any(|l| l == "surrealdb_ffi_connected 0"));
+    }
+}
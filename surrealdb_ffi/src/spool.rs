@@ -0,0 +1,429 @@
+//! Durable on-disk spool for documents that could not be published.
+//! Records are appended as JSON lines to numbered segment files under one directory.
+//! Replay reads the oldest segment from its acknowledged offset (kept in a `.ack`
+//! sidecar) and deletes the segment once every record in it has been acknowledged.
+//! Appends are synced to disk before they are reported as spooled, and a `.lock` file
+//! keeps a second process (or handle) from sharing the directory.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value as JsonValue;
+
+const SEGMENT_PREFIX: &str = "seg-";
+const SEGMENT_SUFFIX: &str = ".jsonl";
+const LOCK_FILE: &str = ".lock";
+
+pub(crate) struct Record {
+    pub table: String,
+    pub doc: JsonValue,
+}
+
+/// Records read by `peek`, with the byte offset just past each one.
+pub(crate) struct Batch {
+    seq: u64,
+    pub records: Vec<Record>,
+    ends: Vec<u64>,
+}
+
+struct Writer {
+    seq: u64,
+    file: File,
+    size: u64,
+}
+
+struct State {
+    writer: Option<Writer>,
+    records: u64,
+    bytes: u64,
+    dropped: u64,
+}
+
+pub(crate) struct Spool {
+    dir: PathBuf,
+    // Holds the exclusive lock on the directory for as long as the spool is open
+    _lock: File,
+    max_bytes: u64,
+    max_age_secs: u64,
+    segment_bytes: u64,
+    state: Mutex<State>,
+    replaying: AtomicBool,
+}
+
+/// Held by the one caller currently replaying the spool; released on drop.
+pub(crate) struct ReplayGuard<'a>(&'a AtomicBool);
+
+impl Drop for ReplayGuard<'_> {
+    fn drop(&mut self) { self.0.store(false, Ordering::Release); }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf { dir.join(format!("{}{:020}{}", SEGMENT_PREFIX, seq, SEGMENT_SUFFIX)) }
+
+fn ack_path(dir: &Path, seq: u64) -> PathBuf { dir.join(format!("{}{:020}{}.ack", SEGMENT_PREFIX, seq, SEGMENT_SUFFIX)) }
+
+fn read_ack(dir: &Path, seq: u64) -> u64 {
+    fs::read_to_string(ack_path(dir, seq)).ok().and_then(|s| s.trim().parse().ok()).unwrap_or(0)
+}
+
+fn write_ack(dir: &Path, seq: u64, offset: u64) -> io::Result<()> {
+    let tmp = dir.join(format!("{}{:020}.ack.tmp", SEGMENT_PREFIX, seq));
+    fs::write(&tmp, offset.to_string())?;
+    fs::rename(tmp, ack_path(dir, seq))
+}
+
+/// Segment sequence numbers present in `dir`, oldest first.
+fn list_segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut seqs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(seq) = name.strip_prefix(SEGMENT_PREFIX).and_then(|r| r.strip_suffix(SEGMENT_SUFFIX)) {
+            if let Ok(n) = seq.parse::<u64>() { seqs.push(n); }
+        }
+    }
+    seqs.sort_unstable();
+    Ok(seqs)
+}
+
+/// Counts complete records and unread bytes after the acknowledged offset.
+fn scan_segment(dir: &Path, seq: u64) -> io::Result<(u64, u64)> {
+    let mut file = File::open(segment_path(dir, seq))?;
+    let len = file.metadata()?.len();
+    let offset = read_ack(dir, seq).min(len);
+    file.seek(SeekFrom::Start(offset))?;
+    let mut records = 0u64;
+    let mut line = Vec::new();
+    let mut reader = BufReader::new(file);
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if line.ends_with(b"\n") { records += 1; }
+        line.clear();
+    }
+    Ok((records, len - offset))
+}
+
+impl Spool {
+    /// Opens (or creates) the spool directory and accounts for records left by a previous run.
+    pub(crate) fn open(dir: &str, max_bytes: u64, max_age_secs: u64) -> io::Result<Spool> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new().create(true).truncate(false).write(true).open(dir.join(LOCK_FILE))?;
+        if lock.try_lock().is_err() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, format!("spool directory {} is in use", dir.display())));
+        }
+        let mut records = 0u64;
+        let mut bytes = 0u64;
+        for seq in list_segments(&dir)? {
+            let (r, b) = scan_segment(&dir, seq)?;
+            records += r;
+            bytes += b;
+        }
+        let max_bytes = if max_bytes == 0 { 1024 * 1024 * 1024 } else { max_bytes };
+        let segment_bytes = (max_bytes / 8).clamp(64 * 1024, 64 * 1024 * 1024);
+        Ok(Spool {
+            dir,
+            _lock: lock,
+            max_bytes,
+            max_age_secs,
+            segment_bytes,
+            state: Mutex::new(State { writer: None, records, bytes, dropped: 0 }),
+            replaying: AtomicBool::new(false),
+        })
+    }
+
+    pub(crate) fn depth(&self) -> u64 { self.state.lock().map(|s| s.records).unwrap_or(0) }
+
+    pub(crate) fn bytes(&self) -> u64 { self.state.lock().map(|s| s.bytes).unwrap_or(0) }
+
+    /// Records discarded because they exceeded the age bound, were unreadable, or were
+    /// evicted to respect the size bound.
+    pub(crate) fn dropped(&self) -> u64 { self.state.lock().map(|s| s.dropped).unwrap_or(0) }
+
+    /// Appends one document and syncs it to disk.
+    pub(crate) fn append(&self, table: &str, doc: &JsonValue) -> io::Result<()> {
+        self.append_all(table, std::slice::from_ref(doc)).pop().unwrap_or(Ok(()))
+    }
+
+    /// Appends documents for one table with a single sync at the end; returns each
+    /// document's outcome. A failed sync fails every document written before it.
+    pub(crate) fn append_all(&self, table: &str, docs: &[JsonValue]) -> Vec<io::Result<()>> {
+        let mut st = match self.state.lock() {
+            Ok(st) => st,
+            Err(_) => return docs.iter().map(|_| Err(io::Error::other("spool lock poisoned"))).collect(),
+        };
+        let now = now_secs();
+        let mut out: Vec<io::Result<()>> = docs.iter().map(|doc| self.write_record(&mut st, now, table, doc)).collect();
+        if out.iter().any(|r| r.is_ok()) {
+            if let Err(e) = st.writer.as_ref().map_or(Ok(()), |w| w.file.sync_data()) {
+                let kind = e.kind();
+                let msg = e.to_string();
+                for r in out.iter_mut().filter(|r| r.is_ok()) { *r = Err(io::Error::new(kind, msg.clone())); }
+            }
+        }
+        out
+    }
+
+    /// Writes one record to the active segment. When the size bound would be exceeded,
+    /// the oldest closed segments are evicted first; fails if the record still does not fit.
+    fn write_record(&self, st: &mut State, now: u64, table: &str, doc: &JsonValue) -> io::Result<()> {
+        let mut line = serde_json::json!({ "ts": now, "table": table, "doc": doc }).to_string();
+        line.push('\n');
+        let len = line.len() as u64;
+
+        if st.bytes + len > self.max_bytes {
+            let active = st.writer.as_ref().map(|w| w.seq);
+            for seq in list_segments(&self.dir)? {
+                if st.bytes + len <= self.max_bytes || Some(seq) == active { break; }
+                let (r, b) = scan_segment(&self.dir, seq)?;
+                self.remove_segment(seq);
+                st.records = st.records.saturating_sub(r);
+                st.bytes = st.bytes.saturating_sub(b);
+                st.dropped += r;
+            }
+            if st.bytes + len > self.max_bytes { return Err(io::Error::other("spool full")); }
+        }
+
+        if st.writer.as_ref().is_some_and(|w| w.size >= self.segment_bytes) {
+            // Rolling over: make the finished segment durable before the next sync covers only the new one
+            if let Some(w) = st.writer.take() { w.file.sync_data()?; }
+        }
+        if st.writer.is_none() {
+            let seq = list_segments(&self.dir)?.last().map_or(1, |s| s + 1);
+            let file = OpenOptions::new().create(true).append(true).open(segment_path(&self.dir, seq))?;
+            // Persist the new directory entry too, or a crash could lose the whole segment
+            File::open(&self.dir)?.sync_all()?;
+            st.writer = Some(Writer { seq, file, size: 0 });
+        }
+        let w = st.writer.as_mut().expect("writer opened above");
+        w.file.write_all(line.as_bytes())?;
+        w.size += len;
+        st.records += 1;
+        st.bytes += len;
+        Ok(())
+    }
+
+    /// Claims the right to replay. Returns None while another thread is replaying, so two
+    /// callers never send the same records.
+    pub(crate) fn begin_replay(&self) -> Option<ReplayGuard<'_>> {
+        self.replaying.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).ok()?;
+        Some(ReplayGuard(&self.replaying))
+    }
+
+    /// Reads up to `max` records from the oldest segment, without consuming them.
+    /// Expired and unreadable records at the head are discarded on the way.
+    pub(crate) fn peek(&self, max: usize) -> io::Result<Batch> {
+        let mut st = self.state.lock().map_err(|_| io::Error::other("spool lock poisoned"))?;
+        loop {
+            let seq = match list_segments(&self.dir)?.first() { Some(s) => *s, None => return Ok(Batch { seq: 0, records: Vec::new(), ends: Vec::new() }) };
+            // Never read a segment that is still being appended to; the next append opens a new one
+            if st.writer.as_ref().is_some_and(|w| w.seq == seq) { st.writer = None; }
+
+            let mut file = File::open(segment_path(&self.dir, seq))?;
+            let len = file.metadata()?.len();
+            let mut offset = read_ack(&self.dir, seq).min(len);
+            file.seek(SeekFrom::Start(offset))?;
+            let mut reader = BufReader::new(file);
+            let mut batch = Batch { seq, records: Vec::new(), ends: Vec::new() };
+            let mut skipped = 0u64;
+            let mut line = Vec::new();
+            let now = now_secs();
+            while batch.records.len() < max {
+                line.clear();
+                let n = reader.read_until(b'\n', &mut line)? as u64;
+                if n == 0 { break; }
+                let end = batch.ends.last().copied().unwrap_or(offset) + n;
+                let parsed: Option<(u64, Record)> = serde_json::from_slice::<JsonValue>(&line).ok().and_then(|v| {
+                    let ts = v.get("ts")?.as_u64()?;
+                    let table = v.get("table")?.as_str()?.to_string();
+                    let doc = v.get("doc")?.clone();
+                    Some((ts, Record { table, doc }))
+                });
+                match parsed {
+                    Some((ts, rec)) if self.max_age_secs == 0 || now.saturating_sub(ts) <= self.max_age_secs => {
+                        batch.records.push(rec);
+                        batch.ends.push(end);
+                    }
+                    _ if batch.records.is_empty() => {
+                        // Expired or torn record at the head: consume it now
+                        offset = end;
+                        skipped += 1;
+                        st.bytes = st.bytes.saturating_sub(n);
+                    }
+                    _ => break,
+                }
+            }
+            if skipped > 0 {
+                st.records = st.records.saturating_sub(skipped);
+                st.dropped += skipped;
+                write_ack(&self.dir, seq, offset)?;
+            }
+            if batch.records.is_empty() && offset >= len {
+                self.remove_segment(seq);
+                continue;
+            }
+            return Ok(batch);
+        }
+    }
+
+    /// Marks the first `n` records of `batch` as published.
+    pub(crate) fn ack(&self, batch: &Batch, n: usize) -> io::Result<()> {
+        if n == 0 { return Ok(()); }
+        let n = n.min(batch.ends.len());
+        let mut st = self.state.lock().map_err(|_| io::Error::other("spool lock poisoned"))?;
+        let start = read_ack(&self.dir, batch.seq);
+        let end = batch.ends[n - 1];
+        st.records = st.records.saturating_sub(n as u64);
+        st.bytes = st.bytes.saturating_sub(end.saturating_sub(start));
+        let len = fs::metadata(segment_path(&self.dir, batch.seq)).map(|m| m.len()).unwrap_or(0);
+        if end >= len {
+            self.remove_segment(batch.seq);
+            Ok(())
+        } else {
+            write_ack(&self.dir, batch.seq, end)
+        }
+    }
+
+    fn remove_segment(&self, seq: u64) {
+        let _ = fs::remove_file(segment_path(&self.dir, seq));
+        let _ = fs::remove_file(ack_path(&self.dir, seq));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Spool directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("surrealdb_ffi-spool-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+
+        fn path(&self) -> &str { self.0.to_str().unwrap() }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) { let _ = fs::remove_dir_all(&self.0); }
+    }
+
+    fn docs(batch: &Batch) -> Vec<JsonValue> { batch.records.iter().map(|r| r.doc.clone()).collect() }
+
+    #[test]
+    fn append_peek_ack() {
+        let dir = TempDir::new("ack");
+        let spool = Spool::open(dir.path(), 0, 0).unwrap();
+        spool.append("events", &json!({"n": 1})).unwrap();
+        assert!(spool.append_all("events", &[json!({"n": 2}), json!({"n": 3})]).iter().all(|r| r.is_ok()));
+        assert_eq!(spool.depth(), 3);
+        assert!(spool.bytes() > 0);
+
+        let batch = spool.peek(2).unwrap();
+        assert_eq!(docs(&batch), vec![json!({"n": 1}), json!({"n": 2})]);
+        assert!(batch.records.iter().all(|r| r.table == "events"));
+        // Peeking does not consume
+        assert_eq!(docs(&spool.peek(2).unwrap()), docs(&batch));
+
+        spool.ack(&batch, 1).unwrap();
+        assert_eq!(spool.depth(), 2);
+        let batch = spool.peek(10).unwrap();
+        assert_eq!(docs(&batch), vec![json!({"n": 2}), json!({"n": 3})]);
+        spool.ack(&batch, 2).unwrap();
+        assert_eq!(spool.depth(), 0);
+        assert_eq!(spool.bytes(), 0);
+        assert!(spool.peek(10).unwrap().records.is_empty());
+        assert_eq!(spool.dropped(), 0);
+    }
+
+    #[test]
+    fn reopen_keeps_unacked_records() {
+        let dir = TempDir::new("reopen");
+        {
+            let spool = Spool::open(dir.path(), 0, 0).unwrap();
+            spool.append_all("t", &[json!(1), json!(2), json!(3)]);
+            let batch = spool.peek(1).unwrap();
+            spool.ack(&batch, 1).unwrap();
+        }
+        let spool = Spool::open(dir.path(), 0, 0).unwrap();
+        assert_eq!(spool.depth(), 2);
+        assert_eq!(docs(&spool.peek(10).unwrap()), vec![json!(2), json!(3)]);
+    }
+
+    #[test]
+    fn directory_is_locked() {
+        let dir = TempDir::new("lock");
+        let _spool = Spool::open(dir.path(), 0, 0).unwrap();
+        let err = Spool::open(dir.path(), 0, 0).err().expect("second open must fail");
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn replay_is_exclusive() {
+        let dir = TempDir::new("replay");
+        let spool = Spool::open(dir.path(), 0, 0).unwrap();
+        let guard = spool.begin_replay();
+        assert!(guard.is_some());
+        assert!(spool.begin_replay().is_none());
+        drop(guard);
+        assert!(spool.begin_replay().is_some());
+    }
+
+    #[test]
+    fn size_bound_evicts_oldest_segments() {
+        let dir = TempDir::new("evict");
+        let max = 256 * 1024;
+        let spool = Spool::open(dir.path(), max, 0).unwrap();
+        let pad = "x".repeat(1000);
+        for chunk in 0..6 {
+            let batch: Vec<JsonValue> = (0..100).map(|i| json!({"n": chunk * 100 + i, "pad": pad})).collect();
+            assert!(spool.append_all("t", &batch).iter().all(|r| r.is_ok()));
+        }
+        assert!(spool.bytes() <= max);
+        assert!(spool.dropped() > 0);
+        assert_eq!(spool.depth() + spool.dropped(), 600);
+        // The oldest records went first
+        let first = spool.peek(1).unwrap().records[0].doc["n"].as_u64().unwrap();
+        assert_eq!(first, spool.dropped());
+    }
+
+    #[test]
+    fn record_larger_than_bound_is_rejected() {
+        let dir = TempDir::new("full");
+        let spool = Spool::open(dir.path(), 1024, 0).unwrap();
+        assert!(spool.append("t", &json!({"pad": "x".repeat(2048)})).is_err());
+        assert_eq!(spool.depth(), 0);
+    }
+
+    #[test]
+    fn expired_records_are_dropped() {
+        let dir = TempDir::new("age");
+        fs::create_dir_all(&dir.0).unwrap();
+        let old = json!({"ts": 1, "table": "t", "doc": "old"}).to_string();
+        let fresh = json!({"ts": now_secs(), "table": "t", "doc": "fresh"}).to_string();
+        fs::write(segment_path(&dir.0, 1), format!("{}\n{}\nnot json\n", old, fresh)).unwrap();
+
+        let spool = Spool::open(dir.path(), 0, 60).unwrap();
+        assert_eq!(spool.depth(), 3);
+        let batch = spool.peek(10).unwrap();
+        assert_eq!(docs(&batch), vec![json!("fresh")]);
+        assert_eq!(spool.dropped(), 1);
+        assert_eq!(spool.depth(), 2);
+
+        // The torn record behind it is discarded once it reaches the head
+        spool.ack(&batch, 1).unwrap();
+        assert!(spool.peek(10).unwrap().records.is_empty());
+        assert_eq!(spool.dropped(), 2);
+        assert_eq!(spool.depth(), 0);
+    }
+}